@@ -0,0 +1,91 @@
+//! Recording latencies into `AtomicHistogram` vs. a `Mutex<Histogram>`, from
+//! one thread and from several threads sharing the histogram.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use hdrhistogram::Histogram;
+use rustwrk::stats::AtomicHistogram;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const THREADS: usize = 4;
+
+// 模拟 100us 到 100ms 之间的延迟
+fn latency(i: u64) -> u64 {
+    100 + (i.wrapping_mul(2654435761) % 100_000)
+}
+
+fn contended<H: Send + Sync + 'static>(histogram: Arc<H>, iters: u64, record: fn(&H, u64)) -> Duration {
+    let per_thread = iters / THREADS as u64 + 1;
+    let start = Instant::now();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let histogram = histogram.clone();
+            thread::spawn(move || {
+                for i in 0..per_thread {
+                    record(&histogram, latency(i + t as u64));
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn record_atomic(histogram: &AtomicHistogram, value: u64) {
+    histogram.record(black_box(value));
+}
+
+fn record_mutex(histogram: &Mutex<Histogram<u64>>, value: u64) {
+    histogram.lock().unwrap().record(black_box(value)).unwrap_or_default();
+}
+
+fn bench_record(c: &mut Criterion) {
+    let mut group = c.benchmark_group("record");
+
+    let atomic = AtomicHistogram::new();
+    let mut i = 0;
+    group.bench_function("atomic", |b| {
+        b.iter(|| {
+            i += 1;
+            record_atomic(&atomic, latency(i));
+        })
+    });
+
+    let mutex = Mutex::new(Histogram::<u64>::new(3).unwrap());
+    let mut i = 0;
+    group.bench_function("mutex", |b| {
+        b.iter(|| {
+            i += 1;
+            record_mutex(&mutex, latency(i));
+        })
+    });
+
+    group.bench_function(format!("atomic_{}_threads", THREADS), |b| {
+        b.iter_custom(|iters| contended(Arc::new(AtomicHistogram::new()), iters, record_atomic))
+    });
+    group.bench_function(format!("mutex_{}_threads", THREADS), |b| {
+        b.iter_custom(|iters| contended(Arc::new(Mutex::new(Histogram::<u64>::new(3).unwrap())), iters, record_mutex))
+    });
+    group.finish();
+}
+
+fn bench_quantile(c: &mut Criterion) {
+    let atomic = AtomicHistogram::new();
+    let mut hdr = Histogram::<u64>::new(3).unwrap();
+    for i in 0..100_000 {
+        atomic.record(latency(i));
+        hdr.record(latency(i)).unwrap();
+    }
+
+    let mut group = c.benchmark_group("value_at_quantile");
+    group.bench_function("atomic", |b| b.iter(|| atomic.value_at_quantile(black_box(0.99))));
+    group.bench_function("hdr", |b| b.iter(|| hdr.value_at_quantile(black_box(0.99))));
+    group.bench_function("atomic_to_histogram", |b| b.iter(|| atomic.to_histogram()));
+    group.finish();
+}
+
+criterion_group!(benches, bench_record, bench_quantile);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+use anyhow::{anyhow, Result};
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Pins the runtime's worker threads to a list of CPU cores, one core per
+/// thread in the order the threads start (--bind-cpu).
+#[derive(Clone, Debug)]
+pub struct CpuBinding {
+    cpus: Arc<Vec<usize>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl CpuBinding {
+    /// Check that there is one existing core per thread
+    pub fn new(cpus: Vec<usize>, threads: usize) -> Result<Self> {
+        if cpus.len() != threads {
+            return Err(anyhow!(
+                "--bind-cpu lists {} cores but there are {} threads; give exactly one core per thread",
+                cpus.len(),
+                threads
+            ));
+        }
+        let available = num_cpus::get();
+        if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= available) {
+            return Err(anyhow!("CPU {} doesn't exist; this machine has {} cores", cpu, available));
+        }
+        Ok(CpuBinding {
+            cpus: Arc::new(cpus),
+            next: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn cpus(&self) -> &[usize] {
+        &self.cpus
+    }
+
+    /// Pin the calling thread to the next core in the list; threads started
+    /// after every core has been handed out (e.g. blocking threads) stay unpinned
+    pub fn pin_next(&self) {
+        let Some(&cpu) = self.cpus.get(self.next.fetch_add(1, Ordering::Relaxed)) else {
+            return;
+        };
+        if let Err(e) = pin_current_thread(cpu) {
+            tracing::warn!("Failed to pin thread to CPU {}: {}", cpu, e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpu: usize) -> io::Result<()> {
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "CPU number out of range"));
+    }
+    // SAFETY: cpu_set_t is plain data, and cpu is below CPU_SETSIZE
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpu: usize) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "CPU affinity is only supported on Linux"))
+}
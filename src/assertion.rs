@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// A `$.path.to[0].field=value` check against a JSON response body.
+#[derive(Clone, Debug)]
+pub struct JsonPathAssertion {
+    path: Vec<PathSegment>,
+    expected: String,
+    // 期望值不是合法 JSON 时只能匹配字符串
+    expected_json: Option<Value>,
+}
+
+impl JsonPathAssertion {
+    pub fn parse(assertion: &str) -> Result<Self> {
+        let (path, expected) = assertion
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid JSON path assertion {:?}: expected \"$.path=value\"", assertion))?;
+        let rest = path
+            .trim()
+            .strip_prefix('$')
+            .ok_or_else(|| anyhow!("JSON path {:?} must start with $", path))?;
+
+        let mut segments = Vec::new();
+        let mut chars = rest.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => {
+                    let mut key = String::new();
+                    while let Some(&next) = chars.peek() {
+                        if next == '.' || next == '[' {
+                            break;
+                        }
+                        key.push(next);
+                        chars.next();
+                    }
+                    if key.is_empty() {
+                        return Err(anyhow!("Empty key in JSON path {:?}", path));
+                    }
+                    segments.push(PathSegment::Key(key));
+                }
+                '[' => {
+                    let index: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                    let index = index
+                        .trim()
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid array index {:?} in JSON path {:?}", index, path))?;
+                    segments.push(PathSegment::Index(index));
+                }
+                _ => return Err(anyhow!("Unexpected {:?} in JSON path {:?}", c, path)),
+            }
+        }
+
+        let expected = expected.trim();
+        Ok(JsonPathAssertion {
+            path: segments,
+            expected: expected.to_string(),
+            expected_json: serde_json::from_str(expected).ok(),
+        })
+    }
+
+    fn check(&self, json: &Value) -> bool {
+        let mut node = json;
+        for segment in &self.path {
+            let next = match (segment, node) {
+                (PathSegment::Key(key), Value::Object(map)) => map.get(key),
+                (PathSegment::Index(index), Value::Array(items)) => items.get(*index),
+                _ => None,
+            };
+            match next {
+                Some(next) => node = next,
+                None => return false,
+            }
+        }
+        // 字符串按原文比较，数字按数值比较（1.0 与 1 相等），其他类型按 JSON 值比较
+        match (node, &self.expected_json) {
+            (Value::String(value), _) => *value == self.expected,
+            (Value::Number(value), Some(Value::Number(expected))) => value.as_f64() == expected.as_f64(),
+            (other, expected) => expected.as_ref() == Some(other),
+        }
+    }
+}
+
+/// Checks applied to the body of every 2xx response.
+#[derive(Clone, Debug, Default)]
+pub struct BodyAssertions {
+    pub contains: Option<String>,
+    pub json_paths: Vec<JsonPathAssertion>,
+}
+
+impl BodyAssertions {
+    pub fn is_empty(&self) -> bool {
+        self.contains.is_none() && self.json_paths.is_empty()
+    }
+
+    pub fn check(&self, body: &[u8]) -> bool {
+        if let Some(needle) = &self.contains {
+            if !contains(body, needle.as_bytes()) {
+                return false;
+            }
+        }
+        if self.json_paths.is_empty() {
+            return true;
+        }
+        match serde_json::from_slice::<Value>(body) {
+            Ok(json) => self.json_paths.iter().all(|assertion| assertion.check(&json)),
+            Err(_) => false,
+        }
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    needle.is_empty() || haystack.windows(needle.len()).any(|window| window == needle)
+}
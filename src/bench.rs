@@ -0,0 +1,1144 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use hdrhistogram::Histogram;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Sub;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+use url::Url;
+use crate::assertion::BodyAssertions;
+use crate::cookie::CookieConfig;
+use crate::multipart::MultipartForm;
+use crate::ntlm::NtlmCredentials;
+use crate::proxy::ProxyConfig;
+use crate::request_log::{ErrorLog, RequestLog};
+use crate::stats::{new_histogram, BurstSample, ConcurrencySample, ConnectionReport, CounterSnapshot, DEFAULT_HISTOGRAM_PRECISION, DEFAULT_PERCENTILES, Report, Sampler, SizeReport, Statistics, StatsSnapshot, StreamingReport};
+use crate::template::Template;
+use crate::resolve::Resolver;
+use crate::script::Script;
+use crate::sigv4::AwsSigner;
+use crate::socket::SocketOptions;
+use crate::tls::TlsOptions;
+use crate::trace::TraceContext;
+use crate::worker::{BurstLimiter, ClientOptions, ConcurrencyLimit, ConnectionConfig, ConnectionPacer, Http2Options, HeaderDump, HttpVersion, Jitter, LatencyCorrection, RateLimiter, RequestBody, RequestBudget, RetryPolicy, Targets, ThinkTime, Worker, WorkerResult};
+
+const UNLIMITED: Duration = Duration::from_secs(365 * 24 * 60 * 60);
+
+/// --target-rps 的初始并发和每秒增加的连接数
+const INITIAL_CONCURRENCY: usize = 10;
+const CONCURRENCY_STEP: usize = 10;
+
+/// User-Agent sent by default so load test traffic is easy to spot in access logs
+pub const DEFAULT_USER_AGENT: &str = concat!("rustwrk/", env!("CARGO_PKG_VERSION"));
+
+/// Settings for a single benchmark run, built with chained setters:
+///
+/// ```no_run
+/// # async fn example() -> anyhow::Result<()> {
+/// use std::time::Duration;
+/// use rustwrk::BenchmarkConfig;
+///
+/// let result = BenchmarkConfig::new("http://localhost:8080/")
+///     .threads(2)
+///     .connections(50)
+///     .duration(Duration::from_secs(5))
+///     .run()
+///     .await?;
+/// println!("{:.2} req/s", result.report().rps);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct BenchmarkConfig {
+    urls: Vec<String>,
+    random: bool,
+    threads: usize,
+    connections: usize,
+    socket_pool_size: Option<usize>,
+    duration: Duration,
+    warmup: Duration,
+    timeout: Duration,
+    rampup: Duration,
+    connections_per_second: Option<u64>,
+    burst: Option<(usize, Duration)>,
+    max_requests: Option<u64>,
+    target_rps: Option<u64>,
+    max_connections: usize,
+    abort_on_error_rate: Option<f64>,
+    error_window: Duration,
+    stop: Arc<AtomicBool>,
+    rate: u64,
+    correct_latency: bool,
+    think_time: Option<ThinkTime>,
+    jitter: Option<Jitter>,
+    retry: Option<RetryPolicy>,
+    max_redirects: Option<u32>,
+    script: Option<Script>,
+    connection_configs: Vec<ConnectionConfig>,
+    trace: Option<TraceContext>,
+    cookies: CookieConfig,
+    request_log: Option<RequestLog>,
+    error_log: Option<ErrorLog>,
+    method: Method,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: RequestBody,
+    assertions: BodyAssertions,
+    http_version: HttpVersion,
+    http2: Http2Options,
+    keepalive: bool,
+    decompress: bool,
+    streaming: bool,
+    size_histograms: bool,
+    dump_response_headers: Option<usize>,
+    ntlm: Option<NtlmCredentials>,
+    aws_signer: Option<AwsSigner>,
+    user_agent: Option<HeaderValue>,
+    request_id_header: Option<HeaderName>,
+    keepalive_timeout: Duration,
+    pool_max_idle_per_host: Option<usize>,
+    proxy: ProxyConfig,
+    unix_socket: Option<PathBuf>,
+    socket: SocketOptions,
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    expect_status: Vec<u16>,
+    chunk_size: Option<usize>,
+    chunk_delay: Option<Duration>,
+    resolver: Resolver,
+    tls: TlsOptions,
+}
+
+/// Everything measured by a run; saved and loaded as JSON with
+/// --save-result / --load-result.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BenchmarkResult {
+    pub total_requests: u64,
+    pub total_successes: u64,
+    pub total_errors: u64,
+    pub total_assertion_failures: u64,
+    pub total_retries: u64,
+    pub total_redirects: u64,
+    pub total_connect_timeouts: u64,
+    pub total_read_timeouts: u64,
+    pub total_body_timeouts: u64,
+    pub script_counters: BTreeMap<String, f64>,
+    pub total_bytes: u64,
+    pub total_bytes_decompressed: u64,
+    pub total_header_bytes: u64,
+    #[serde(default)]
+    pub total_bytes_sent: u64,
+    pub duration: Duration,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub histogram: Histogram<u64>,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub ttfb_histogram: Histogram<u64>,
+    /// Latency percentiles included in the report
+    pub percentiles: Vec<f64>,
+    /// Body chunks received with --streaming
+    pub total_chunks: u64,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub ttfc_histogram: Histogram<u64>,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub chunk_delay_histogram: Histogram<u64>,
+    /// Number of responses that carried each header name
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, u64>,
+    /// Methods listed in the Allow headers of OPTIONS responses
+    #[serde(default)]
+    pub allowed_methods: BTreeSet<String>,
+    /// Request and response body sizes, only with --request-size-histogram
+    #[serde(with = "crate::compare::base64_histogram", default = "default_histogram")]
+    pub request_size_histogram: Histogram<u64>,
+    #[serde(with = "crate::compare::base64_histogram", default = "default_histogram")]
+    pub response_size_histogram: Histogram<u64>,
+    pub protocol: String,
+    pub status_codes: BTreeMap<u16, u64>,
+    pub connections: ConnectionReport,
+    /// Active connections sampled every second, only filled in with a ramp-up
+    pub concurrency: Vec<ConcurrencySample>,
+    /// One snapshot per full second after warmup
+    pub timeline: Vec<StatsSnapshot>,
+    /// Every burst after warmup, only filled in with --burst-size
+    #[serde(default)]
+    pub bursts: Vec<BurstSample>,
+    /// Each worker's own totals, ordered by worker ID
+    pub per_worker: Vec<WorkerResult>,
+    /// Per-URL reports, only filled in when more than one URL was benchmarked
+    pub per_url: Vec<(String, Report)>,
+    /// Rolling error rate (percent) that caused the test to be aborted early
+    pub aborted: Option<f64>,
+    /// Concurrency the --target-rps controller settled on
+    pub final_concurrency: Option<usize>,
+    /// Latencies beyond the histogram range, recorded at its maximum
+    #[serde(default)]
+    pub overrange_values: u64,
+}
+
+// 旧版本保存的结果没有大小直方图
+fn default_histogram() -> Histogram<u64> {
+    new_histogram(DEFAULT_HISTOGRAM_PRECISION)
+}
+
+impl BenchmarkResult {
+    fn new(sigfigs: u8) -> Self {
+        BenchmarkResult {
+            total_requests: 0,
+            total_successes: 0,
+            total_errors: 0,
+            total_assertion_failures: 0,
+            total_retries: 0,
+            total_redirects: 0,
+            total_connect_timeouts: 0,
+            total_read_timeouts: 0,
+            total_body_timeouts: 0,
+            script_counters: BTreeMap::new(),
+            total_bytes: 0,
+            total_bytes_decompressed: 0,
+            total_header_bytes: 0,
+            total_bytes_sent: 0,
+            duration: Duration::ZERO,
+            histogram: new_histogram(sigfigs),
+            ttfb_histogram: new_histogram(sigfigs),
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+            total_chunks: 0,
+            ttfc_histogram: new_histogram(sigfigs),
+            chunk_delay_histogram: new_histogram(sigfigs),
+            response_headers: BTreeMap::new(),
+            allowed_methods: BTreeSet::new(),
+            request_size_histogram: new_histogram(sigfigs),
+            response_size_histogram: new_histogram(sigfigs),
+            protocol: String::new(),
+            status_codes: BTreeMap::new(),
+            connections: ConnectionReport::default(),
+            concurrency: Vec::new(),
+            bursts: Vec::new(),
+            timeline: Vec::new(),
+            per_worker: Vec::new(),
+            per_url: Vec::new(),
+            aborted: None,
+            final_concurrency: None,
+            overrange_values: 0,
+        }
+    }
+
+    fn add(&mut self, worker: &WorkerResult) {
+        self.total_requests += worker.total_requests;
+        self.total_successes += worker.total_successes;
+        self.total_errors += worker.total_errors;
+        self.total_assertion_failures += worker.total_assertion_failures;
+        self.total_retries += worker.total_retries;
+        self.total_redirects += worker.total_redirects;
+        for (name, value) in &worker.script_counters {
+            *self.script_counters.entry(name.clone()).or_default() += value;
+        }
+        self.total_bytes += worker.total_bytes;
+        self.total_bytes_decompressed += worker.total_bytes_decompressed;
+        self.duration = self.duration.max(worker.duration);
+        self.histogram.add(&worker.histogram).unwrap_or_default();
+        self.ttfb_histogram.add(&worker.ttfb_histogram).unwrap_or_default();
+        self.per_worker.push(worker.clone());
+    }
+
+    /// Fold the result of a later, sequential run into this one
+    pub fn merge(&mut self, other: &BenchmarkResult) {
+        self.total_requests += other.total_requests;
+        self.total_successes += other.total_successes;
+        self.total_errors += other.total_errors;
+        self.total_assertion_failures += other.total_assertion_failures;
+        self.total_retries += other.total_retries;
+        self.total_redirects += other.total_redirects;
+        self.total_connect_timeouts += other.total_connect_timeouts;
+        self.total_header_bytes += other.total_header_bytes;
+        self.total_bytes_sent += other.total_bytes_sent;
+        self.total_read_timeouts += other.total_read_timeouts;
+        self.total_body_timeouts += other.total_body_timeouts;
+        for (name, value) in &other.script_counters {
+            *self.script_counters.entry(name.clone()).or_default() += value;
+        }
+        self.total_bytes += other.total_bytes;
+        self.total_bytes_decompressed += other.total_bytes_decompressed;
+        self.duration += other.duration;
+        self.histogram.add(&other.histogram).unwrap_or_default();
+        self.ttfb_histogram.add(&other.ttfb_histogram).unwrap_or_default();
+        self.total_chunks += other.total_chunks;
+        self.ttfc_histogram.add(&other.ttfc_histogram).unwrap_or_default();
+        self.chunk_delay_histogram.add(&other.chunk_delay_histogram).unwrap_or_default();
+        for (name, count) in &other.response_headers {
+            *self.response_headers.entry(name.clone()).or_default() += count;
+        }
+        self.allowed_methods.extend(other.allowed_methods.iter().cloned());
+        self.request_size_histogram.add(&other.request_size_histogram).unwrap_or_default();
+        self.response_size_histogram.add(&other.response_size_histogram).unwrap_or_default();
+        if self.protocol != other.protocol {
+            self.protocol = if self.protocol.is_empty() { other.protocol.clone() } else { "mixed".to_string() };
+        }
+        for (code, count) in &other.status_codes {
+            *self.status_codes.entry(*code).or_default() += count;
+        }
+        // 各阶段的建连耗时分布无法合并，只累加连接数
+        self.connections = ConnectionReport {
+            new: self.connections.new + other.connections.new,
+            reused: self.connections.reused + other.connections.reused,
+            http1: self.connections.http1 + other.connections.http1,
+            http2: self.connections.http2 + other.connections.http2,
+            tls12: self.connections.tls12 + other.connections.tls12,
+            tls13: self.connections.tls13 + other.connections.tls13,
+            socket: other.connections.socket.or(self.connections.socket),
+            setup: other.connections.setup.or(self.connections.setup),
+            ..ConnectionReport::default()
+        };
+        self.concurrency.clear();
+        self.timeline.extend(&other.timeline);
+        self.bursts.extend(&other.bursts);
+        // 同一编号的 worker 跨阶段累加
+        for worker in &other.per_worker {
+            match self.per_worker.iter_mut().find(|w| w.worker_id == worker.worker_id) {
+                Some(existing) => existing.merge(worker),
+                None => self.per_worker.push(worker.clone()),
+            }
+        }
+        self.per_url.extend(other.per_url.iter().cloned());
+        self.aborted = self.aborted.or(other.aborted);
+        self.final_concurrency = other.final_concurrency.or(self.final_concurrency);
+        self.overrange_values += other.overrange_values;
+    }
+
+    /// Write the full result as JSON, for later use with [`BenchmarkResult::load`]
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self).context("Failed to serialize result")?;
+        std::fs::write(path, json).with_context(|| format!("Failed to write result {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read result {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("Failed to parse result {}", path.display()))
+    }
+
+    pub fn report(&self) -> Report {
+        let counters = CounterSnapshot {
+            requests: self.total_requests,
+            success: self.total_successes,
+            errors: self.total_errors,
+            bytes: self.total_bytes,
+            bytes_decompressed: self.total_bytes_decompressed,
+        };
+        Report {
+            protocol: self.protocol.clone(),
+            status_codes: self.status_codes.clone(),
+            connections: self.connections.clone(),
+            assertion_failures: self.total_assertion_failures,
+            retries: self.total_retries,
+            redirects: self.total_redirects,
+            connect_timeouts: self.total_connect_timeouts,
+            read_timeouts: self.total_read_timeouts,
+            body_timeouts: self.total_body_timeouts,
+            script_counters: self.script_counters.clone(),
+            concurrency: self.concurrency.clone(),
+            timeline: self.timeline.clone(),
+            bursts: self.bursts.clone(),
+            streaming: (self.total_chunks > 0)
+                .then(|| StreamingReport::new(self.total_chunks, &self.ttfc_histogram, &self.chunk_delay_histogram)),
+            response_headers: self.response_headers.clone(),
+            allowed_methods: self.allowed_methods.clone(),
+            request_sizes: (!self.request_size_histogram.is_empty()).then(|| SizeReport::new(&self.request_size_histogram)),
+            response_sizes: (!self.response_size_histogram.is_empty()).then(|| SizeReport::new(&self.response_size_histogram)),
+            ..Report::new(&counters, self.duration, &self.histogram)
+        }
+        .with_percentiles(&self.histogram, &self.percentiles)
+        .with_ttfb(&self.ttfb_histogram)
+        .with_header_bytes(self.total_header_bytes)
+        .with_bytes_sent(self.total_bytes_sent)
+    }
+}
+
+/// Change from one result to another (`current - baseline`); latencies in
+/// milliseconds, throughput in requests per second.
+#[derive(Clone, Debug, Serialize)]
+pub struct BenchmarkDiff {
+    pub requests: i64,
+    pub errors: i64,
+    pub duration_secs: f64,
+    pub rps: f64,
+    pub latency_mean_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+    pub ttfb_p99_ms: f64,
+    /// Percentage points
+    pub error_rate: f64,
+}
+
+impl Sub for &BenchmarkResult {
+    type Output = BenchmarkDiff;
+
+    fn sub(self, baseline: &BenchmarkResult) -> BenchmarkDiff {
+        let (current, base) = (self.report(), baseline.report());
+        let error_rate = |r: &Report| if r.requests > 0 { r.errors as f64 / r.requests as f64 * 100.0 } else { 0.0 };
+        BenchmarkDiff {
+            requests: current.requests as i64 - base.requests as i64,
+            errors: current.errors as i64 - base.errors as i64,
+            duration_secs: current.duration_secs - base.duration_secs,
+            rps: current.rps - base.rps,
+            latency_mean_ms: current.latency_mean_ms - base.latency_mean_ms,
+            latency_p50_ms: current.latency_p50_ms - base.latency_p50_ms,
+            latency_p95_ms: current.latency_p95_ms - base.latency_p95_ms,
+            latency_p99_ms: current.latency_p99_ms - base.latency_p99_ms,
+            ttfb_p99_ms: current.ttfb_p99_ms - base.ttfb_p99_ms,
+            error_rate: error_rate(&current) - error_rate(&base),
+        }
+    }
+}
+
+impl Sub for BenchmarkResult {
+    type Output = BenchmarkDiff;
+
+    fn sub(self, baseline: BenchmarkResult) -> BenchmarkDiff {
+        &self - &baseline
+    }
+}
+
+impl BenchmarkConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        BenchmarkConfig::with_urls(vec![url.into()])
+    }
+
+    /// Benchmark several URLs, cycling through them round-robin
+    pub fn with_urls(urls: Vec<String>) -> Self {
+        BenchmarkConfig {
+            urls,
+            random: false,
+            threads: num_cpus::get(),
+            connections: 100,
+            socket_pool_size: None,
+            duration: Duration::from_secs(10),
+            warmup: Duration::ZERO,
+            timeout: Duration::from_secs(5),
+            rampup: Duration::ZERO,
+            connections_per_second: None,
+            burst: None,
+            max_requests: None,
+            target_rps: None,
+            max_connections: 1000,
+            abort_on_error_rate: None,
+            error_window: Duration::from_secs(5),
+            stop: Arc::new(AtomicBool::new(false)),
+            rate: 0,
+            correct_latency: false,
+            think_time: None,
+            jitter: None,
+            retry: None,
+            max_redirects: None,
+            script: None,
+            connection_configs: Vec::new(),
+            trace: None,
+            cookies: CookieConfig::default(),
+            request_log: None,
+            error_log: None,
+            method: Method::GET,
+            headers: Vec::new(),
+            body: RequestBody::Static(Bytes::new()),
+            assertions: BodyAssertions::default(),
+            http_version: HttpVersion::Auto,
+            http2: Http2Options::default(),
+            keepalive: true,
+            decompress: true,
+            streaming: false,
+            size_histograms: false,
+            dump_response_headers: None,
+            ntlm: None,
+            aws_signer: None,
+            user_agent: Some(HeaderValue::from_static(DEFAULT_USER_AGENT)),
+            request_id_header: None,
+            keepalive_timeout: Duration::from_secs(30),
+            pool_max_idle_per_host: None,
+            proxy: ProxyConfig::default(),
+            unix_socket: None,
+            socket: SocketOptions::default(),
+            connect_timeout: None,
+            read_timeout: None,
+            expect_status: Vec::new(),
+            chunk_size: None,
+            chunk_delay: None,
+            resolver: Resolver::default(),
+            tls: TlsOptions::default(),
+        }
+    }
+
+    pub fn urls(mut self, urls: Vec<String>) -> Self {
+        self.urls = urls;
+        self
+    }
+
+    /// Pick URLs at random instead of round-robin
+    pub fn random_urls(mut self, random: bool) -> Self {
+        self.random = random;
+        self
+    }
+
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    pub fn connections(mut self, connections: usize) -> Self {
+        self.connections = connections;
+        self
+    }
+
+    /// Open this many TCP connections in total, independent of the number of
+    /// concurrent requests; extra requests share sockets, extra sockets stay idle
+    pub fn socket_pool_size(mut self, sockets: Option<usize>) -> Self {
+        self.socket_pool_size = sockets;
+        self
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn warmup(mut self, warmup: Duration) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stop after exactly this many requests (excluding warmup) instead of
+    /// after the configured duration
+    pub fn max_requests(mut self, max_requests: Option<u64>) -> Self {
+        self.max_requests = max_requests;
+        self
+    }
+
+    /// Adjust the number of active connections every second to reach this
+    /// throughput, starting from 10
+    pub fn target_rps(mut self, target_rps: Option<u64>) -> Self {
+        self.target_rps = target_rps;
+        self
+    }
+
+    /// Upper bound on active connections for `target_rps`
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Stop the test early once the rolling error rate exceeds this percentage
+    pub fn abort_on_error_rate(mut self, threshold: Option<f64>) -> Self {
+        self.abort_on_error_rate = threshold;
+        self
+    }
+
+    /// Window over which the rolling error rate is computed
+    pub fn error_window(mut self, window: Duration) -> Self {
+        self.error_window = window;
+        self
+    }
+
+    /// Connections finish their current request and stop once this flag is set
+    pub fn stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = stop;
+        self
+    }
+
+    /// Open connections gradually over this period instead of all at once
+    pub fn rampup(mut self, rampup: Duration) -> Self {
+        self.rampup = rampup;
+        self
+    }
+
+    /// Start at most this many connections per second, across all threads;
+    /// only the initial setup is paced
+    pub fn connections_per_second(mut self, per_second: Option<u64>) -> Self {
+        self.connections_per_second = per_second;
+        self
+    }
+
+    /// Send requests in bursts of `size` across all connections: each burst
+    /// starts once the previous one has completed and `interval` has passed
+    pub fn burst(mut self, size: Option<usize>, interval: Duration) -> Self {
+        self.burst = size.map(|size| (size, interval));
+        self
+    }
+
+    /// Limit total throughput in requests per second (0 = unlimited)
+    pub fn rate(mut self, rate: u64) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    pub fn correct_latency(mut self, correct: bool) -> Self {
+        self.correct_latency = correct;
+        self
+    }
+
+    /// Pause after each successful response before the next request
+    pub fn think_time(mut self, think_time: Option<ThinkTime>) -> Self {
+        self.think_time = think_time;
+        self
+    }
+
+    /// Wait a random delay before each request
+    pub fn jitter(mut self, jitter: Option<Jitter>) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Run a Lua script's `setup`/`request`/`response` hooks, one Lua state per thread
+    pub fn script(mut self, script: Option<Script>) -> Self {
+        self.script = script;
+        self
+    }
+
+    /// Per-connection overrides, reused round-robin when there are fewer than `connections`
+    pub fn connection_configs(mut self, configs: Vec<ConnectionConfig>) -> Self {
+        self.connection_configs = configs;
+        self
+    }
+
+    /// Send a W3C `traceparent` header with every request
+    pub fn trace_context(mut self, trace: Option<TraceContext>) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    /// Static cookies and the per-connection cookie jar
+    pub fn cookies(mut self, cookies: CookieConfig) -> Self {
+        self.cookies = cookies;
+        self
+    }
+
+    /// Send one record per request to this log
+    pub fn request_log(mut self, log: Option<RequestLog>) -> Self {
+        self.request_log = log;
+        self
+    }
+
+    /// Send one record per failed request to this log
+    pub fn error_log(mut self, log: Option<ErrorLog>) -> Self {
+        self.error_log = log;
+        self
+    }
+
+    /// Follow up to this many 3xx redirects per request; `None` records redirects as-is
+    pub fn max_redirects(mut self, max_redirects: Option<u32>) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    /// Retry connection errors and selected status codes before recording a result
+    pub fn retry(mut self, retry: Option<RetryPolicy>) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = method;
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.push((name, value));
+        self
+    }
+
+    pub fn headers(mut self, headers: Vec<(HeaderName, HeaderValue)>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = RequestBody::Static(body.into());
+        self
+    }
+
+    /// Render the body from a template before each request
+    pub fn body_template(mut self, template: Template) -> Self {
+        self.body = RequestBody::Template(template);
+        self
+    }
+
+    /// Send a `multipart/form-data` body with the correct Content-Type
+    pub fn multipart(mut self, form: MultipartForm) -> Self {
+        self.body = RequestBody::Multipart(form);
+        self
+    }
+
+    /// Count 2xx responses whose body fails these checks as errors
+    pub fn assertions(mut self, assertions: BodyAssertions) -> Self {
+        self.assertions = assertions;
+        self
+    }
+
+    pub fn http_version(mut self, version: HttpVersion) -> Self {
+        self.http_version = version;
+        self
+    }
+
+    /// Stream limit and flow control windows for HTTP/2 connections
+    pub fn http2_options(mut self, http2: Http2Options) -> Self {
+        self.http2 = http2;
+        self
+    }
+
+    pub fn keepalive(mut self, keepalive: bool) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Read response bodies frame by frame and record chunk timings
+    pub fn streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Record histograms of request and response body sizes
+    pub fn size_histograms(mut self, size_histograms: bool) -> Self {
+        self.size_histograms = size_histograms;
+        self
+    }
+
+    /// Print the headers of the first `count` responses to stderr and count
+    /// how many responses carried each header name
+    pub fn dump_response_headers(mut self, count: Option<usize>) -> Self {
+        self.dump_response_headers = count;
+        self
+    }
+
+    /// Authenticate every connection with NTLM before its first request
+    /// completes; connections then use HTTP/1.1
+    pub fn ntlm(mut self, credentials: Option<NtlmCredentials>) -> Self {
+        self.ntlm = credentials;
+        self
+    }
+
+    /// Sign every request with AWS Signature Version 4
+    pub fn aws_sigv4(mut self, signer: Option<AwsSigner>) -> Self {
+        self.aws_signer = signer;
+        self
+    }
+
+    /// Decode gzip/deflate/br/zstd bodies to report their decompressed size
+    pub fn decompress(mut self, decompress: bool) -> Self {
+        self.decompress = decompress;
+        self
+    }
+
+    /// User-Agent sent unless the headers already set one; `None` omits it
+    pub fn user_agent(mut self, user_agent: Option<HeaderValue>) -> Self {
+        self.user_agent = user_agent;
+        self
+    }
+
+    /// Send a random UUID in this header with every request
+    pub fn request_id_header(mut self, name: Option<HeaderName>) -> Self {
+        self.request_id_header = name;
+        self
+    }
+
+    pub fn keepalive_timeout(mut self, timeout: Duration) -> Self {
+        self.keepalive_timeout = timeout;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, max_idle: Option<usize>) -> Self {
+        self.pool_max_idle_per_host = max_idle;
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    /// Connect through a Unix domain socket instead of TCP
+    pub fn unix_socket(mut self, path: Option<PathBuf>) -> Self {
+        self.unix_socket = path;
+        self
+    }
+
+    /// Limit for opening a connection (TCP and TLS handshakes), separate from the request timeout
+    pub fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Limit for each read of the response body, separate from the request timeout
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Stream the request body in pieces of `size` bytes, pausing `delay`
+    /// between them to simulate a slow client
+    pub fn chunked_upload(mut self, size: Option<usize>, delay: Option<Duration>) -> Self {
+        self.chunk_size = size;
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Count only these status codes as successes; empty means any 2xx
+    pub fn expect_status(mut self, codes: Vec<u16>) -> Self {
+        self.expect_status = codes;
+        self
+    }
+
+    /// TCP_NODELAY and socket buffer sizes for every connection
+    pub fn socket_options(mut self, socket: SocketOptions) -> Self {
+        self.socket = socket;
+        self
+    }
+
+    /// Restrict DNS to one address family and/or pin hosts to fixed addresses
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub async fn run(&self) -> Result<BenchmarkResult> {
+        self.run_with(Statistics::new()).await
+    }
+
+    // 检查 URL 并加载 TLS 配置，出错时不会建立任何连接
+    fn client_options(&self) -> Result<ClientOptions> {
+        for url in &self.urls {
+            let parsed = Url::parse(url).with_context(|| format!("Invalid URL {:?}", url))?;
+            if self.http_version == HttpVersion::H2c && parsed.scheme() == "https" {
+                return Err(anyhow!("HTTP/2 prior knowledge (h2c) needs an http:// URL, got {}", url));
+            }
+        }
+        if self.http_version == HttpVersion::H2c {
+            if let Some(uri) = self.connection_configs.iter().filter_map(|c| c.url.as_ref()).find(|uri| uri.scheme_str() == Some("https")) {
+                return Err(anyhow!("HTTP/2 prior knowledge (h2c) needs an http:// URL, got {}", uri));
+            }
+        }
+        // NTLM 认证绑定在 HTTP/1.1 的持久连接上
+        if self.ntlm.is_some() {
+            if matches!(self.http_version, HttpVersion::Http2 | HttpVersion::H2c) {
+                return Err(anyhow!("NTLM authentication needs HTTP/1.1 and can't be used with HTTP/2"));
+            }
+            if !self.keepalive {
+                return Err(anyhow!("NTLM authentication needs keep-alive connections"));
+            }
+        }
+        Ok(ClientOptions {
+            http_version: if self.ntlm.is_some() { HttpVersion::Http1 } else { self.http_version },
+            http2: self.http2,
+            keepalive: self.keepalive,
+            decompress: self.decompress,
+            streaming: self.streaming,
+            size_histograms: self.size_histograms,
+            header_dump: self.dump_response_headers.map(HeaderDump::new),
+            ntlm: self.ntlm.clone().map(Arc::new),
+            aws_signer: self.aws_signer.clone().map(Arc::new),
+            user_agent: self.user_agent.clone(),
+            request_id_header: self.request_id_header.clone(),
+            keepalive_timeout: self.keepalive_timeout,
+            pool_max_idle_per_host: self.pool_max_idle_per_host,
+            proxy: self.proxy.clone(),
+            unix_socket: self.unix_socket.clone(),
+            socket: self.socket,
+            connect_timeout: self.connect_timeout,
+            read_timeout: self.read_timeout,
+            expect_status: self.expect_status.clone(),
+            chunk_size: self.chunk_size,
+            chunk_delay: self.chunk_delay,
+            resolver: self.resolver.clone(),
+            tls: self.tls.client_config()?,
+        })
+    }
+
+    /// Validate the configuration and build the first request the benchmark
+    /// would send, without opening any connections
+    pub fn dry_run(&self) -> Result<hyper::Request<Bytes>> {
+        let client_options = self.client_options()?;
+        let targets = Targets::new(self.urls.clone(), self.random, DEFAULT_HISTOGRAM_PRECISION)?;
+        let script = match &self.script {
+            Some(script) => Some(Arc::new(script.instance(0, &self.urls[0], &self.method, &self.headers)?)),
+            None => None,
+        };
+        let worker = Worker::new(
+            0,
+            1,
+            None,
+            self.method.clone(),
+            self.body.clone(),
+            self.headers.clone(),
+            Statistics::new(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            CancellationToken::new(),
+            self.stop.clone(),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            script,
+            self.connection_configs.iter().take(1).cloned().collect(),
+            self.trace.clone(),
+            self.cookies.clone(),
+            None,
+            None,
+            &client_options,
+        )?;
+        worker.first_request(&targets)
+    }
+
+    /// Run the benchmark, recording into `stats` so the caller can sample it
+    /// while the test is in progress
+    pub async fn run_with(&self, stats: Statistics) -> Result<BenchmarkResult> {
+        let client_options = self.client_options()?;
+        let targets = Arc::new(Targets::new(self.urls.clone(), self.random, stats.histogram_precision())?);
+
+        let rate_limiter = (self.rate > 0).then(|| RateLimiter::new(self.rate));
+        let burst = self.burst.map(|(size, _)| BurstLimiter::new(size));
+        let budget = self.max_requests.map(RequestBudget::new);
+        let assertions = (!self.assertions.is_empty()).then(|| Arc::new(self.assertions.clone()));
+        // 按请求数运行时不限制时长
+        let duration = if budget.is_some() { UNLIMITED } else { self.duration };
+        // 自适应并发时预先建好上限数量的任务，由控制器决定其中多少个在运行
+        let concurrency = self.target_rps.map(|_| ConcurrencyLimit::new(INITIAL_CONCURRENCY.min(self.max_connections)));
+        let pacer = self.connections_per_second.map(ConnectionPacer::new);
+        let total_connections = if concurrency.is_some() { self.max_connections } else { self.connections };
+        let connections_per_thread = total_connections / self.threads;
+        // 限速时每个连接的期望请求间隔是固定的，否则参考上一个请求的耗时
+        let correction = self.correct_latency.then(|| {
+            if self.rate > 0 {
+                LatencyCorrection::Fixed(Duration::from_secs_f64(self.connections as f64 / self.rate as f64))
+            } else {
+                LatencyCorrection::Previous
+            }
+        });
+
+        // 预热结束后重置统计数据，之后每秒记录一次窗口快照
+        let snapshots = Arc::new(Mutex::new(Vec::with_capacity(self.duration.as_secs() as usize)));
+        let stats_timeline = {
+            let stats = stats.clone();
+            let targets = targets.clone();
+            let snapshots = snapshots.clone();
+            let warmup = self.warmup;
+            tokio::spawn(async move {
+                if !warmup.is_zero() {
+                    tokio::time::sleep(warmup).await;
+                    stats.reset();
+                    targets.reset();
+                }
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                interval.tick().await;
+                let mut sampler = Sampler::new(stats);
+                let mut elapsed_secs = 0;
+                loop {
+                    interval.tick().await;
+                    elapsed_secs += 1;
+                    snapshots.lock().unwrap().push(StatsSnapshot::new(elapsed_secs, &sampler.sample()));
+                }
+            })
+        };
+
+        // 爬坡期间每秒记录一次并发连接数
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let timeline = (!self.rampup.is_zero()).then(|| {
+            let stats = stats.clone();
+            let samples = samples.clone();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                interval.tick().await;
+                let mut elapsed_secs = 0;
+                loop {
+                    interval.tick().await;
+                    elapsed_secs += 1;
+                    samples.lock().unwrap().push(ConcurrencySample {
+                        elapsed_secs,
+                        connections: stats.active_connections(),
+                    });
+                }
+            })
+        });
+
+        // 滚动错误率超过阈值时取消所有连接任务
+        let cancel = CancellationToken::new();
+        let monitor = self.abort_on_error_rate.map(|threshold| {
+            let stats = stats.clone();
+            let cancel = cancel.clone();
+            let window = (self.error_window.as_secs() as usize).max(1);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                interval.tick().await;
+                let mut last = stats.snapshot();
+                let mut recent = VecDeque::with_capacity(window);
+                loop {
+                    interval.tick().await;
+                    let current = stats.snapshot();
+                    recent.push_back(current.delta(&last));
+                    last = current;
+                    if recent.len() > window {
+                        recent.pop_front();
+                    }
+                    let requests: u64 = recent.iter().map(|c| c.requests).sum();
+                    let errors: u64 = recent.iter().map(|c| c.errors).sum();
+                    let error_rate = if requests > 0 { errors as f64 / requests as f64 * 100.0 } else { 0.0 };
+                    if error_rate > threshold {
+                        cancel.cancel();
+                        return Some(error_rate);
+                    }
+                }
+            })
+        });
+
+        // 突发模式的节奏由单独的任务控制
+        let bursts = burst.clone().zip(self.burst).map(|(burst, (_, interval))| {
+            tokio::spawn(burst.drive(interval, Instant::now() + self.warmup))
+        });
+
+        // 每秒按实际吞吐调整并发：未达目标时加性增加，错误率过高时减半
+        let controller = self.target_rps.zip(concurrency.clone()).map(|(target, concurrency)| {
+            let stats = stats.clone();
+            let max = connections_per_thread * self.threads;
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(1));
+                interval.tick().await;
+                let mut last = stats.snapshot();
+                loop {
+                    interval.tick().await;
+                    let current = stats.snapshot();
+                    let delta = current.delta(&last);
+                    last = current;
+                    let limit = concurrency.get();
+                    let error_rate = if delta.requests > 0 { delta.errors as f64 / delta.requests as f64 } else { 0.0 };
+                    if error_rate > 0.01 {
+                        concurrency.set((limit / 2).max(1));
+                    } else if (delta.requests as f64) < target as f64 * 0.95 {
+                        concurrency.set((limit + CONCURRENCY_STEP).min(max));
+                    }
+                }
+            })
+        });
+
+        // 启动工作线程
+        let mut handles = Vec::with_capacity(self.threads);
+        for thread in 0..self.threads {
+            let connection_configs = if self.connection_configs.is_empty() {
+                Vec::new()
+            } else {
+                (0..connections_per_thread)
+                    .map(|i| self.connection_configs[(thread * connections_per_thread + i) % self.connection_configs.len()].clone())
+                    .collect()
+            };
+            let script = match &self.script {
+                Some(script) => Some(Arc::new(script.instance(thread, &self.urls[0], &self.method, &self.headers)?)),
+                None => None,
+            };
+            // 套接字平均分给各线程，余数给编号靠前的线程；每个线程至少一个
+            let sockets = self
+                .socket_pool_size
+                .map(|sockets| (sockets / self.threads + usize::from(thread < sockets % self.threads)).max(1));
+            let worker = Worker::new(
+                thread,
+                connections_per_thread,
+                sockets,
+                self.method.clone(),
+                self.body.clone(),
+                self.headers.clone(),
+                stats.clone(),
+                rate_limiter.clone(),
+                burst.clone(),
+                budget.clone(),
+                concurrency.clone(),
+                pacer.clone(),
+                cancel.clone(),
+                self.stop.clone(),
+                assertions.clone(),
+                correction,
+                self.think_time,
+                self.jitter,
+                self.retry.clone(),
+                self.max_redirects,
+                script,
+                connection_configs,
+                self.trace.clone(),
+                self.cookies.clone(),
+                self.request_log.clone(),
+                self.error_log.clone(),
+                &client_options,
+            )?;
+            let targets = targets.clone();
+            let (warmup, timeout, rampup) = (self.warmup, self.timeout, self.rampup);
+            handles.push(tokio::spawn(async move {
+                worker.run(targets, warmup, duration, timeout, rampup).await
+            }));
+        }
+
+        // 等待所有线程完成并汇总结果
+        let mut result = BenchmarkResult::new(stats.histogram_precision());
+        for handle in handles {
+            result.add(&handle.await??);
+        }
+        result.protocol = stats.protocol();
+        if self.http_version == HttpVersion::H2c && result.protocol == "HTTP/2" {
+            result.protocol = "HTTP/2 (h2c, prior knowledge)".to_string();
+        }
+        result.status_codes = stats.status_codes();
+        result.percentiles = stats.percentiles();
+        result.total_connect_timeouts = stats.connect_timeouts();
+        result.total_header_bytes = stats.header_bytes();
+        result.total_bytes_sent = stats.bytes_sent();
+        result.total_read_timeouts = stats.read_timeouts();
+        result.total_body_timeouts = stats.body_timeouts();
+        result.overrange_values = stats.overrange();
+        result.connections = stats.connections();
+        result.connections.setup = pacer.as_ref().and_then(ConnectionPacer::setup);
+        result.total_chunks = stats.chunks();
+        (result.ttfc_histogram, result.chunk_delay_histogram) = stats.chunk_histograms();
+        (result.request_size_histogram, result.response_size_histogram) = stats.size_histograms();
+        result.response_headers = stats.response_headers();
+        result.allowed_methods = stats.allowed_methods();
+        stats_timeline.abort();
+        if let Some(timeline) = timeline {
+            timeline.abort();
+        }
+        if let Some(controller) = controller {
+            controller.abort();
+        }
+        if let Some(bursts) = bursts {
+            bursts.abort();
+        }
+        result.bursts = burst.as_ref().map(BurstLimiter::samples).unwrap_or_default();
+        result.final_concurrency = concurrency.map(|c| c.get());
+        if let Some(monitor) = monitor {
+            if cancel.is_cancelled() {
+                result.aborted = monitor.await?;
+            } else {
+                monitor.abort();
+            }
+        }
+        result.concurrency = std::mem::take(&mut *samples.lock().unwrap());
+        result.timeline = std::mem::take(&mut *snapshots.lock().unwrap());
+        result.per_url = targets
+            .per_url()
+            .into_iter()
+            .map(|(url, stats)| (url.to_string(), stats.report()))
+            .collect();
+        Ok(result)
+    }
+}
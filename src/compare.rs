@@ -0,0 +1,193 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
+use hdrhistogram::Histogram;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::stats::Report;
+
+/// Encode a histogram in HdrHistogram's V2 format, base64-encoded.
+pub fn encode_histogram(histogram: &Histogram<u64>) -> Result<String> {
+    let mut bytes = Vec::new();
+    V2Serializer::new()
+        .serialize(histogram, &mut bytes)
+        .context("Failed to serialize histogram")?;
+    Ok(STANDARD.encode(bytes))
+}
+
+pub fn decode_histogram(encoded: &str) -> Result<Histogram<u64>> {
+    let bytes = STANDARD.decode(encoded.trim()).context("Histogram is not valid base64")?;
+    Deserializer::new()
+        .deserialize(&mut bytes.as_slice())
+        .context("Failed to deserialize histogram")
+}
+
+/// Save a latency histogram in HdrHistogram's V2 format, base64-encoded.
+pub fn save_histogram(histogram: &Histogram<u64>, path: &Path) -> Result<()> {
+    std::fs::write(path, encode_histogram(histogram)?)
+        .with_context(|| format!("Failed to write histogram file {}", path.display()))
+}
+
+pub fn load_histogram(path: &Path) -> Result<Histogram<u64>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read histogram file {}", path.display()))?;
+    decode_histogram(&contents).with_context(|| format!("Invalid histogram file {}", path.display()))
+}
+
+/// `#[serde(with)]` adapter storing a histogram as a base64 V2 string.
+pub mod base64_histogram {
+    use hdrhistogram::Histogram;
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(histogram: &Histogram<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        let encoded = super::encode_histogram(histogram).map_err(ser::Error::custom)?;
+        serializer.serialize_str(&encoded)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Histogram<u64>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        super::decode_histogram(&encoded).map_err(de::Error::custom)
+    }
+}
+
+/// Latency differences between a baseline and a candidate histogram, in milliseconds.
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub baseline_mean_ms: f64,
+    pub candidate_mean_ms: f64,
+    pub baseline_p99_ms: f64,
+    pub candidate_p99_ms: f64,
+    /// Welch's t statistic for the difference in means
+    pub t_statistic: f64,
+}
+
+impl Comparison {
+    pub fn new(baseline: &Histogram<u64>, candidate: &Histogram<u64>) -> Result<Self> {
+        if baseline.is_empty() || candidate.is_empty() {
+            return Err(anyhow!("Cannot compare an empty histogram"));
+        }
+        let ms = |micros: f64| micros / 1000.0;
+        let variance = |h: &Histogram<u64>| h.stdev().powi(2) / h.len() as f64;
+        let standard_error = (variance(baseline) + variance(candidate)).sqrt();
+        let mean_diff = candidate.mean() - baseline.mean();
+
+        Ok(Comparison {
+            baseline_mean_ms: ms(baseline.mean()),
+            candidate_mean_ms: ms(candidate.mean()),
+            baseline_p99_ms: ms(baseline.value_at_quantile(0.99) as f64),
+            candidate_p99_ms: ms(candidate.value_at_quantile(0.99) as f64),
+            t_statistic: if standard_error > 0.0 { mean_diff / standard_error } else { 0.0 },
+        })
+    }
+
+    /// A rough, human-readable verdict on whether the means really differ
+    pub fn significance(&self) -> &'static str {
+        match self.t_statistic.abs() {
+            t if t >= 3.0 => "the difference in means is very likely significant",
+            t if t >= 2.0 => "the difference in means is probably significant",
+            _ => "the difference in means is within noise",
+        }
+    }
+}
+
+/// The headline metrics of a saved `--json` report, used as a regression baseline.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Baseline {
+    pub requests: u64,
+    pub errors: u64,
+    pub rps: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+}
+
+impl Baseline {
+    pub fn from_report(report: &Report) -> Self {
+        Baseline {
+            requests: report.requests,
+            errors: report.errors,
+            rps: report.rps,
+            latency_p50_ms: report.latency_p50_ms,
+            latency_p95_ms: report.latency_p95_ms,
+            latency_p99_ms: report.latency_p99_ms,
+        }
+    }
+
+    /// Load a baseline from any rustwrk JSON report; extra fields are ignored
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline {}", path.display()))?;
+        serde_json::from_str(&contents).with_context(|| format!("Failed to parse baseline {}", path.display()))
+    }
+
+    /// Errors as a percentage of all requests
+    pub fn error_rate(&self) -> f64 {
+        if self.requests > 0 {
+            self.errors as f64 / self.requests as f64 * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Save the full report so it can later be loaded with [`Baseline::load`].
+pub fn save_baseline(report: &Report, path: &Path) -> Result<()> {
+    let json = serde_json::to_string_pretty(report).context("Failed to serialize report")?;
+    std::fs::write(path, json).with_context(|| format!("Failed to write baseline {}", path.display()))
+}
+
+/// One metric of a baseline comparison.
+#[derive(Clone, Debug)]
+pub struct MetricDiff {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+    /// True for throughput, false for latency and errors
+    pub higher_is_better: bool,
+}
+
+impl MetricDiff {
+    pub fn diff(&self) -> f64 {
+        self.current - self.baseline
+    }
+
+    /// Relative change in percent; infinite when the baseline was zero
+    pub fn percent(&self) -> f64 {
+        if self.baseline == 0.0 {
+            return if self.current == 0.0 { 0.0 } else { f64::INFINITY };
+        }
+        self.diff() / self.baseline * 100.0
+    }
+
+    /// Change in the bad direction beyond `threshold` percent
+    pub fn regressed(&self, threshold: f64) -> bool {
+        let change = if self.higher_is_better { -self.percent() } else { self.percent() };
+        change > threshold
+    }
+
+    pub fn improved(&self, threshold: f64) -> bool {
+        let change = if self.higher_is_better { self.percent() } else { -self.percent() };
+        change > threshold
+    }
+}
+
+/// Compare RPS, latency percentiles and error rate against a baseline
+pub fn diff_baseline(baseline: &Baseline, current: &Baseline) -> Vec<MetricDiff> {
+    let metric = |name, unit, baseline, current, higher_is_better| MetricDiff {
+        name,
+        unit,
+        baseline,
+        current,
+        higher_is_better,
+    };
+    vec![
+        metric("RPS", "", baseline.rps, current.rps, true),
+        metric("P50", "ms", baseline.latency_p50_ms, current.latency_p50_ms, false),
+        metric("P95", "ms", baseline.latency_p95_ms, current.latency_p95_ms, false),
+        metric("P99", "ms", baseline.latency_p99_ms, current.latency_p99_ms, false),
+        metric("Error rate", "%", baseline.error_rate(), current.error_rate(), false),
+    ]
+}
@@ -0,0 +1,209 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+use url::Url;
+
+use crate::bench::BenchmarkConfig;
+
+/// A multi-phase scenario loaded from a TOML file. Phases run one after another:
+///
+/// ```toml
+/// [[phases]]
+/// name = "baseline"
+/// url = "http://localhost:8080/"
+/// duration = "30s"
+/// connections = 10
+///
+/// [[phases]]
+/// url = "http://localhost:8080/search"
+/// method = "POST"
+/// headers = { "Content-Type" = "application/json" }
+/// body = '{"q": "rust"}'
+/// rate = 500
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    pub phases: Vec<Phase>,
+}
+
+/// One phase of a scenario; fields left out keep their command-line values.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct Phase {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub method: Option<String>,
+    /// Added to the headers given on the command line
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Same format as `-d`, e.g. "30s" or "2m"
+    #[serde(default)]
+    pub duration: Option<String>,
+    #[serde(default)]
+    pub connections: Option<usize>,
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub rate: Option<u64>,
+}
+
+impl Config {
+    /// Read and validate a scenario; phases without a `url` use `default_url`
+    pub fn load(path: &Path, default_url: Option<&str>) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {}", path.display()))?;
+        let mut config: Config = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+        if config.phases.is_empty() {
+            return Err(anyhow!("Config file {} defines no [[phases]]", path.display()));
+        }
+
+        // 开始第一个阶段之前先检查所有阶段的配置
+        for (i, phase) in config.phases.iter_mut().enumerate() {
+            if phase.url.is_none() {
+                phase.url = default_url.map(str::to_string);
+            }
+            phase.validate().with_context(|| format!("Invalid {} in {}", phase.label(i), path.display()))?;
+        }
+        Ok(config)
+    }
+}
+
+impl Phase {
+    /// The phase's name, or "phase N" (1-based) when it has none
+    pub fn label(&self, index: usize) -> String {
+        match &self.name {
+            Some(name) => format!("phase {:?}", name),
+            None => format!("phase {}", index + 1),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        let url = self
+            .url
+            .as_deref()
+            .ok_or_else(|| anyhow!("No url given and none on the command line"))?;
+        Url::parse(url).with_context(|| format!("Invalid URL {:?}", url))?;
+        self.method()?;
+        self.headers()?;
+        self.duration()?;
+        if self.connections == Some(0) || self.threads == Some(0) {
+            return Err(anyhow!("connections and threads must be at least 1"));
+        }
+        Ok(())
+    }
+
+    fn method(&self) -> Result<Option<Method>> {
+        self.method
+            .as_deref()
+            .map(|method| {
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .with_context(|| format!("Invalid method {:?}", method))
+            })
+            .transpose()
+    }
+
+    fn headers(&self) -> Result<Vec<(HeaderName, HeaderValue)>> {
+        self.headers
+            .iter()
+            .map(|(name, value)| {
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name {:?}", name))?;
+                let value = HeaderValue::from_str(value)
+                    .with_context(|| format!("Invalid value for header {}", name))?;
+                Ok((name, value))
+            })
+            .collect()
+    }
+
+    fn duration(&self) -> Result<Option<Duration>> {
+        self.duration.as_deref().map(parse_duration).transpose()
+    }
+
+    /// Apply this phase's settings on top of the command-line configuration
+    pub fn apply(&self, mut config: BenchmarkConfig) -> Result<BenchmarkConfig> {
+        if let Some(url) = &self.url {
+            config = config.urls(vec![url.clone()]);
+        }
+        if let Some(method) = self.method()? {
+            config = config.method(method);
+        }
+        for (name, value) in self.headers()? {
+            config = config.header(name, value);
+        }
+        if let Some(body) = &self.body {
+            config = config.body(Bytes::from(body.clone()));
+        }
+        if let Some(duration) = self.duration()? {
+            config = config.duration(duration);
+        }
+        if let Some(connections) = self.connections {
+            config = config.connections(connections);
+        }
+        if let Some(threads) = self.threads {
+            config = config.threads(threads);
+        }
+        if let Some(rate) = self.rate {
+            config = config.rate(rate);
+        }
+        Ok(config)
+    }
+}
+
+/// Parse a duration such as 30, 500ms, 1.5s, 2m or 1h (bare numbers are
+/// seconds), rounded to whole milliseconds
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (number, unit_ms) = if let Some(number) = value.strip_suffix("ms") {
+        (number, 1.0)
+    } else if let Some(number) = value.strip_suffix('s') {
+        (number, 1000.0)
+    } else if let Some(number) = value.strip_suffix('m') {
+        (number, 60_000.0)
+    } else if let Some(number) = value.strip_suffix('h') {
+        (number, 3_600_000.0)
+    } else {
+        (value, 1000.0)
+    };
+    let number: f64 = number
+        .parse()
+        .ok()
+        .filter(|number: &f64| number.is_finite() && *number >= 0.0)
+        .ok_or_else(|| anyhow!("Invalid duration {:?}: expected e.g. 30, 500ms, 1.5s, 2m or 1h", value))?;
+    let millis = (number * unit_ms).round();
+    if millis > u64::MAX as f64 {
+        return Err(anyhow!("Duration {:?} is too large", value));
+    }
+    Ok(Duration::from_millis(millis as u64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("30").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("30s").unwrap(), Duration::from_secs(30));
+        assert_eq!(parse_duration("2m").unwrap(), Duration::from_secs(120));
+        assert_eq!(parse_duration("1h").unwrap(), Duration::from_secs(3600));
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1.5s").unwrap(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn parse_duration_rejects_invalid() {
+        for value in ["", "-1s", "abc", "inf"] {
+            assert!(parse_duration(value).is_err(), "{:?} should be rejected", value);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+use anyhow::{anyhow, Context as _};
+use hyper::Uri;
+use hyper_rustls::{HttpsConnector, MaybeHttpsStream};
+use rustls::pki_types::ServerName;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::time;
+use tokio_rustls::TlsConnector;
+use tower_service::Service;
+use crate::proxy::{MaybeTlsStream, ProxyConnector, ProxyStream};
+use crate::resolve::Resolver;
+use crate::stats::Statistics;
+use crate::tls::{TlsOptions, TlsVersion};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long [`RawTarget::connect`] waits after a failed connection attempt
+pub const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// The TCP and TLS handshakes didn't finish within `--connection-timeout`.
+#[derive(Debug)]
+pub struct ConnectTimeout;
+
+impl fmt::Display for ConnectTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("connection timed out")
+    }
+}
+
+impl Error for ConnectTimeout {}
+
+/// True when a client error was caused by a connect timeout, either ours or
+/// the TCP connect timeout of `HttpConnector`
+pub fn is_connect_timeout(error: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(error);
+    while let Some(error) = source {
+        if error.is::<ConnectTimeout>() {
+            return true;
+        }
+        if error.downcast_ref::<io::Error>().is_some_and(|e| e.kind() == io::ErrorKind::TimedOut) {
+            return true;
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Records DNS, TCP and TLS timings for every newly opened connection.
+#[derive(Clone)]
+pub struct TimedConnector {
+    inner: HttpsConnector<ProxyConnector>,
+    stats: Statistics,
+    timeout: Option<Duration>,
+    h2c: bool,
+}
+
+impl TimedConnector {
+    pub fn new(inner: HttpsConnector<ProxyConnector>, stats: Statistics) -> Self {
+        TimedConnector { inner, stats, timeout: None, h2c: false }
+    }
+
+    /// Count plain http:// connections as HTTP/2 (prior knowledge)
+    pub fn h2c(mut self, h2c: bool) -> Self {
+        self.h2c = h2c;
+        self
+    }
+
+    /// Fail connections whose TCP and TLS handshakes together take longer than this
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Service<Uri> for TimedConnector {
+    type Response = MaybeHttpsStream<ProxyStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let connecting = self.inner.call(dst);
+        let stats = self.stats.clone();
+        let timeout = self.timeout;
+        let h2c = self.h2c;
+
+        Box::pin(async move {
+            let stream = match timeout {
+                Some(timeout) => time::timeout(timeout, connecting).await.map_err(|_| ConnectTimeout)??,
+                None => connecting.await?,
+            };
+            // TLS 握手耗时 = 完成时间 - TCP 连接就绪时间；HTTP 版本由 ALPN 决定
+            let (tcp, tls, http2, tls_version) = match &stream {
+                MaybeHttpsStream::Http(tcp) => (tcp, None, h2c, None),
+                MaybeHttpsStream::Https(tls) => {
+                    let (tcp, session) = tls.inner().get_ref();
+                    let tcp = tcp.inner();
+                    let version = session.protocol_version().and_then(TlsVersion::negotiated);
+                    (tcp, Some(tcp.connected_at().elapsed()), session.alpn_protocol() == Some(b"h2"), version)
+                }
+            };
+            stats.record_connection(tcp.dns_time(), tcp.tcp_time(), tls, tcp.socket_info());
+            stats.record_connection_protocol(http2);
+            if let Some(version) = tls_version {
+                stats.record_tls_version(version);
+            }
+            Ok(stream)
+        })
+    }
+}
+
+/// The measured part of a run: connections keep going until `end_time` or a
+/// stop request, and only record results from `warmup_end` on.
+#[derive(Clone, Debug)]
+pub struct RunWindow {
+    pub warmup_end: Instant,
+    pub end_time: Instant,
+    stop: Arc<AtomicBool>,
+}
+
+impl RunWindow {
+    /// A window starting now: `warmup`, then `duration` of measurement
+    pub fn new(warmup: Duration, duration: Duration, stop: Arc<AtomicBool>) -> Self {
+        let warmup_end = Instant::now() + warmup;
+        RunWindow { warmup_end, end_time: warmup_end + duration, stop }
+    }
+
+    pub fn running(&self) -> bool {
+        Instant::now() < self.end_time && !self.stop.load(Ordering::Relaxed)
+    }
+
+    pub fn recording(&self) -> bool {
+        Instant::now() >= self.warmup_end
+    }
+}
+
+/// Host, port and TLS settings of the URL benchmarked by the modes that drive
+/// the socket themselves instead of going through hyper (--websocket,
+/// --pipeline and server push). Hosts are resolved with the run's
+/// [`Resolver`], so --resolve and -4/-6 apply.
+#[derive(Clone)]
+pub struct RawTarget {
+    uri: Uri,
+    host: String,
+    port: u16,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    resolver: Resolver,
+    timeout: Duration,
+}
+
+/// A connection opened by [`RawTarget::connect`] and how long it took.
+pub struct RawConnection {
+    pub stream: MaybeTlsStream,
+    pub dns_time: Duration,
+    pub tcp_time: Duration,
+    /// TLS handshake time, `None` for plain connections
+    pub tls_time: Option<Duration>,
+}
+
+impl RawConnection {
+    /// The protocol the server selected with ALPN
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        match &self.stream {
+            MaybeTlsStream::Tls(tls) => tls.get_ref().1.alpn_protocol(),
+            _ => None,
+        }
+    }
+}
+
+impl RawTarget {
+    /// Parse `url`, which must use one of `schemes` (plain, secure), e.g.
+    /// ("http", "https"); `alpn` is offered in the TLS handshake and `timeout`
+    /// limits each step of connecting
+    pub fn new(
+        url: &str,
+        (plain, secure): (&str, &str),
+        tls: &TlsOptions,
+        alpn: &[&[u8]],
+        resolver: Resolver,
+        timeout: Duration,
+    ) -> anyhow::Result<Self> {
+        let uri: Uri = url.parse().with_context(|| format!("Invalid URL {:?}", url))?;
+        let https = match uri.scheme_str() {
+            Some(scheme) if scheme == plain => false,
+            Some(scheme) if scheme == secure => true,
+            _ => return Err(anyhow!("Expected a {}:// or {}:// URL, got {:?}", plain, secure, url)),
+        };
+        let host = uri.host().ok_or_else(|| anyhow!("URL {:?} has no host", url))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let tls = if https {
+            let mut config = tls.client_config()?;
+            config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+            let name = ServerName::try_from(host.clone()).with_context(|| format!("Invalid TLS server name {:?}", host))?;
+            Some((TlsConnector::from(Arc::new(config)), name))
+        } else {
+            None
+        };
+        Ok(RawTarget { uri, host, port, tls, resolver, timeout })
+    }
+
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Open a connection, including the TLS handshake for secure URLs. After
+    /// a failure other than a timeout this waits a little before returning,
+    /// so callers can reconnect in a loop without spinning
+    pub async fn connect(&self) -> Result<RawConnection, BoxError> {
+        let result = self.open().await;
+        if matches!(&result, Err(e) if !is_connect_timeout(e.as_ref())) {
+            time::sleep(RECONNECT_DELAY).await;
+        }
+        result
+    }
+
+    async fn open(&self) -> Result<RawConnection, BoxError> {
+        let started = Instant::now();
+        let addrs = time::timeout(self.timeout, self.resolver.resolve(&self.host, self.port))
+            .await
+            .map_err(|_| ConnectTimeout)??;
+        let dns_time = started.elapsed();
+
+        // 按解析结果逐个地址尝试
+        let mut last_error: BoxError = format!("No addresses found for {}", self.host).into();
+        let mut tcp = None;
+        for addr in addrs {
+            match time::timeout(self.timeout, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => {
+                    tcp = Some(stream);
+                    break;
+                }
+                Ok(Err(e)) => last_error = e.into(),
+                Err(_) => last_error = ConnectTimeout.into(),
+            }
+        }
+        let tcp = tcp.ok_or(last_error)?;
+        let _ = tcp.set_nodelay(true);
+        let tcp_time = started.elapsed() - dns_time;
+
+        let Some((connector, name)) = &self.tls else {
+            return Ok(RawConnection { stream: MaybeTlsStream::Plain(tcp), dns_time, tcp_time, tls_time: None });
+        };
+        let tls = time::timeout(self.timeout, connector.connect(name.clone(), tcp))
+            .await
+            .map_err(|_| ConnectTimeout)??;
+        Ok(RawConnection {
+            stream: MaybeTlsStream::Tls(Box::new(tls)),
+            dns_time,
+            tcp_time,
+            tls_time: Some(started.elapsed() - dns_time - tcp_time),
+        })
+    }
+}
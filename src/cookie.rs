@@ -0,0 +1,82 @@
+use anyhow::{anyhow, Result};
+use cookie_store::CookieStore;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::Uri;
+use url::Url;
+
+/// Cookies sent by every connection task: fixed `name=value` pairs plus, when
+/// the jar is enabled, whatever the server set earlier on the same task.
+#[derive(Clone, Debug, Default)]
+pub struct CookieConfig {
+    /// Store `Set-Cookie` responses and send them back on later requests
+    pub jar: bool,
+    pub cookies: Vec<(String, String)>,
+}
+
+impl CookieConfig {
+    /// Parse a `name=value` cookie given on the command line.
+    pub fn parse_cookie(cookie: &str) -> Result<(String, String)> {
+        let invalid = || anyhow!("Invalid cookie {:?}: expected \"name=value\"", cookie);
+        let (name, value) = cookie.split_once('=').ok_or_else(invalid)?;
+        let name = name.trim();
+        if name.is_empty() || name.contains([';', ' ']) || value.contains(';') {
+            return Err(invalid());
+        }
+        Ok((name.to_string(), value.to_string()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.jar && self.cookies.is_empty()
+    }
+
+    /// Fresh cookie state for one connection task
+    pub fn session(&self) -> CookieSession {
+        CookieSession {
+            cookies: self.cookies.clone(),
+            jar: self.jar.then(CookieStore::default),
+        }
+    }
+}
+
+/// The cookies of a single connection task; never shared with other tasks.
+#[derive(Debug)]
+pub struct CookieSession {
+    cookies: Vec<(String, String)>,
+    jar: Option<CookieStore>,
+}
+
+impl CookieSession {
+    /// `Cookie` header for a request to `uri`, or `None` when there is nothing to send
+    pub fn header(&self, uri: &Uri) -> Option<HeaderValue> {
+        let mut pairs: Vec<String> = self.cookies.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+        if let (Some(jar), Some(url)) = (&self.jar, to_url(uri)) {
+            // 命令行指定的 cookie 优先
+            for (name, value) in jar.get_request_values(&url) {
+                if !self.cookies.iter().any(|(n, _)| n == name) {
+                    pairs.push(format!("{}={}", name, value));
+                }
+            }
+        }
+        if pairs.is_empty() {
+            return None;
+        }
+        HeaderValue::from_str(&pairs.join("; ")).ok()
+    }
+
+    /// Remember the cookies set by a response to a request for `uri`
+    pub fn store(&mut self, uri: &Uri, headers: &HeaderMap) {
+        let (Some(jar), Some(url)) = (&mut self.jar, to_url(uri)) else {
+            return;
+        };
+        for value in headers.get_all(header::SET_COOKIE) {
+            let Ok(value) = value.to_str() else { continue };
+            if let Err(e) = jar.parse(value, &url) {
+                tracing::debug!("Ignoring Set-Cookie {:?}: {}", value, e);
+            }
+        }
+    }
+}
+
+fn to_url(uri: &Uri) -> Option<Url> {
+    Url::parse(&uri.to_string()).ok()
+}
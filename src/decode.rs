@@ -0,0 +1,54 @@
+use bytes::Bytes;
+use hyper::header::{HeaderMap, CONTENT_ENCODING};
+use std::io::{self, Read};
+
+/// Decode a response body according to its Content-Encoding header.
+/// Unencoded bodies are returned as-is.
+pub fn decode_body(headers: &HeaderMap, body: Bytes) -> io::Result<Bytes> {
+    if body.is_empty() {
+        return Ok(body);
+    }
+    let encodings: Vec<String> = headers
+        .get_all(CONTENT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .collect();
+    if encodings.is_empty() {
+        return Ok(body);
+    }
+
+    // 多重编码按相反顺序解码
+    let mut data = body.to_vec();
+    for encoding in encodings.iter().rev() {
+        data = decode(encoding, &data)?;
+    }
+    Ok(Bytes::from(data))
+}
+
+fn decode(encoding: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match encoding {
+        "gzip" | "x-gzip" => {
+            flate2::read::GzDecoder::new(data).read_to_end(&mut out)?;
+        }
+        "deflate" => {
+            flate2::read::ZlibDecoder::new(data).read_to_end(&mut out)?;
+        }
+        "br" => {
+            brotli::Decompressor::new(data, 4096).read_to_end(&mut out)?;
+        }
+        "zstd" => {
+            out = zstd::stream::decode_all(data)?;
+        }
+        other => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported content encoding {:?}", other),
+            ))
+        }
+    }
+    Ok(out)
+}
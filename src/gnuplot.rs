@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use hdrhistogram::Histogram;
+use std::ffi::OsString;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::stats::StatsSnapshot;
+
+/// Latency histogram, RPS time series and a gnuplot script that charts both,
+/// written next to each other as `<prefix>_latency_hist.dat`,
+/// `<prefix>_rps_timeseries.dat` and `<prefix>.gnuplot`.
+pub fn write_files(prefix: &Path, histogram: &Histogram<u64>, timeline: &[StatsSnapshot]) -> Result<()> {
+    let latency = with_suffix(prefix, "_latency_hist.dat");
+    let rps = with_suffix(prefix, "_rps_timeseries.dat");
+    write(&latency, &latency_histogram(histogram))?;
+    write(&rps, &rps_timeseries(timeline))?;
+    write(&with_suffix(prefix, ".gnuplot"), &script(prefix, &latency, &rps))
+}
+
+fn with_suffix(prefix: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(prefix.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn write(path: &Path, data: &str) -> Result<()> {
+    std::fs::write(path, data).with_context(|| format!("Failed to write gnuplot file {}", path.display()))
+}
+
+// 按 2 的幂划分桶，与 HdrHistogram 的精度一致；横坐标取桶的中点（毫秒）
+fn latency_histogram(histogram: &Histogram<u64>) -> String {
+    let mut data = String::from("# latency_ms count\n");
+    let mut low = 0;
+    for step in histogram.iter_log(1, 2.0) {
+        let high = step.value_iterated_to();
+        let count = step.count_since_last_iteration();
+        if count > 0 {
+            let _ = writeln!(data, "{:.3} {}", (low + high) as f64 / 2.0 / 1000.0, count);
+        }
+        low = high + 1;
+    }
+    data
+}
+
+fn rps_timeseries(timeline: &[StatsSnapshot]) -> String {
+    let mut data = String::from("# second rps\n");
+    for snapshot in timeline {
+        let _ = writeln!(data, "{} {}", snapshot.elapsed_secs, snapshot.requests);
+    }
+    data
+}
+
+fn script(prefix: &Path, latency: &Path, rps: &Path) -> String {
+    let prefix = prefix.display();
+    format!(
+        r#"# Generated by rustwrk; run with: gnuplot {prefix}.gnuplot
+set terminal pngcairo size 1000,600
+set grid
+
+set output '{prefix}_latency_hist.png'
+set title 'Latency distribution'
+set logscale x 2
+set xlabel 'Latency (ms)'
+set ylabel 'Requests'
+plot '{latency}' using 1:2 with impulses linewidth 4 notitle
+
+set output '{prefix}_rps_timeseries.png'
+set title 'Requests per second'
+unset logscale x
+set xlabel 'Time (s)'
+set ylabel 'Requests/sec'
+set yrange [0:*]
+plot '{rps}' using 1:2 with lines linewidth 2 notitle
+"#,
+        latency = latency.display(),
+        rps = rps.display(),
+    )
+}
@@ -0,0 +1,97 @@
+use anyhow::{Context, Result};
+use hyper::header::{self, HeaderValue};
+use hyper::Method;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::push;
+
+/// Per-second samples in InfluxDB line protocol, e.g.
+/// `rustwrk,url=http://localhost/,method=GET requests=1234,errors=0,p50=1.2,p99=5.6 1700000000000000000`.
+#[derive(Clone, Debug)]
+pub struct InfluxLines {
+    tags: String,
+    lines: Arc<Mutex<Vec<String>>>,
+}
+
+impl InfluxLines {
+    pub fn new(url: &str, method: &Method, labels: &[(String, String)]) -> Self {
+        let mut tags = format!("url={},method={}", escape(url), escape(method.as_str()));
+        for (name, value) in labels {
+            let _ = write!(tags, ",{}={}", escape(name), escape(value));
+        }
+        InfluxLines {
+            tags,
+            lines: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Add a point for the last second, timestamped now
+    pub fn record(&self, requests: u64, errors: u64, p50_ms: f64, p99_ms: f64) {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos();
+        let line = format!(
+            "rustwrk,{} requests={},errors={},p50={},p99={} {}",
+            self.tags, requests, errors, p50_ms, p99_ms, timestamp
+        );
+        self.lines.lock().unwrap().push(line);
+    }
+
+    pub fn render(&self) -> String {
+        let lines = self.lines.lock().unwrap();
+        let mut out = lines.join("\n");
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// 标签中的逗号、等号和空格需要转义
+fn escape(value: &str) -> String {
+    value.replace(',', "\\,").replace('=', "\\=").replace(' ', "\\ ")
+}
+
+pub fn write_file(path: &Path, data: &str) -> Result<()> {
+    std::fs::write(path, data).with_context(|| format!("Failed to write InfluxDB data to {}", path.display()))
+}
+
+/// An InfluxDB v2 write endpoint.
+#[derive(Clone, Debug)]
+pub struct InfluxDb {
+    pub url: String,
+    pub token: Option<String>,
+    pub org: Option<String>,
+    pub bucket: Option<String>,
+}
+
+impl InfluxDb {
+    /// POST the data to `/api/v2/write`, which is appended unless the URL already names a write endpoint
+    pub async fn push(&self, data: String) -> Result<()> {
+        let base = if self.url.contains("/write") {
+            self.url.clone()
+        } else {
+            format!("{}/api/v2/write", self.url.trim_end_matches('/'))
+        };
+        let mut url = Url::parse(&base).with_context(|| format!("Invalid InfluxDB URL {:?}", self.url))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(org) = &self.org {
+                query.append_pair("org", org);
+            }
+            if let Some(bucket) = &self.bucket {
+                query.append_pair("bucket", bucket);
+            }
+            query.append_pair("precision", "ns");
+        }
+
+        let mut headers = vec![(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; charset=utf-8"))];
+        if let Some(token) = &self.token {
+            let value = HeaderValue::from_str(&format!("Token {}", token)).context("Invalid --influxdb-token")?;
+            headers.push((header::AUTHORIZATION, value));
+        }
+        push::post("InfluxDB", url.as_str(), &headers, data).await
+    }
+}
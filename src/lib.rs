@@ -0,0 +1,37 @@
+//! rustwrk as a library: describe a run with [`BenchmarkConfig`] and await
+//! its [`BenchmarkResult`].
+
+pub mod affinity;
+pub mod assertion;
+pub mod bench;
+pub mod compare;
+pub mod config;
+pub mod connect;
+pub mod cookie;
+pub mod decode;
+pub mod gnuplot;
+pub mod influxdb;
+pub mod multipart;
+pub mod ntlm;
+pub mod pipeline;
+pub mod prometheus;
+pub mod proxy;
+pub mod push;
+pub mod request_log;
+pub mod resolve;
+pub mod resource;
+pub mod script;
+pub mod server_push;
+pub mod sigv4;
+pub mod sla;
+pub mod socket;
+pub mod stats;
+pub mod template;
+pub mod tls;
+pub mod trace;
+pub mod websocket;
+pub mod worker;
+
+pub use bench::{BenchmarkConfig, BenchmarkResult};
+pub use stats::{AtomicStats, Report, Statistics};
+pub use worker::Worker;
@@ -1,83 +1,2699 @@
-mod stats;
-mod worker;
-
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use clap::Parser;
-use anyhow::Result;
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use url::Url;
-use worker::Worker;
+use hdrhistogram::Histogram;
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::Bytes;
+use colored::Colorize;
+use hyper::header::{self, HeaderName, HeaderValue};
+use hyper::Method;
+use rustwrk::affinity::CpuBinding;
+use rustwrk::assertion::{BodyAssertions, JsonPathAssertion};
+use rustwrk::compare::{self, Baseline, Comparison, MetricDiff};
+use rustwrk::config::{parse_duration, Config};
+use rustwrk::cookie::CookieConfig;
+use rustwrk::gnuplot;
+use rustwrk::influxdb::{self, InfluxDb, InfluxLines};
+use rustwrk::multipart::MultipartForm;
+use rustwrk::ntlm::NtlmCredentials;
+use rustwrk::pipeline::{PipelineConfig, PipelineReport};
+use rustwrk::prometheus;
+use rustwrk::push;
+use rustwrk::proxy::ProxyConfig;
+use rustwrk::request_log::{ErrorLog, RequestLog};
+use rustwrk::resolve::{IpFamily, ResolveOverride, Resolver};
+use rustwrk::resource::ResourceUsage;
+use rustwrk::stats::{BurstSample, ConnectionReport, Report, Sampler, Statistics, StatsSnapshot, DEFAULT_HISTOGRAM_PRECISION};
+use rustwrk::script::Script;
+use rustwrk::server_push::{PushMode, ServerPushConfig};
+use rustwrk::sigv4::AwsSigner;
+use rustwrk::sla;
+use rustwrk::socket::{self, LocalAddress, SocketOptions};
+use rustwrk::template::Template;
+use rustwrk::tls::{TlsOptions, TlsVersion};
+use rustwrk::trace::TraceContext;
+use rustwrk::websocket::{WebSocketConfig, WebSocketReport};
+use rustwrk::worker::{ConnectionConfig, Http2Options, HttpVersion, Jitter, JitterDistribution, RetryPolicy, ThinkTime};
+use rustwrk::bench::DEFAULT_USER_AGENT;
+use rustwrk::{BenchmarkConfig, BenchmarkResult};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::io::IsTerminal;
+use tokio::task::JoinHandle;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Number of threads to use
     #[arg(short = 't', default_value_t = num_cpus::get())]
     threads: usize,
 
-    /// Number of connections to keep open
+    /// Pin the worker threads to these CPU cores (e.g. 0,2,4,6), one per
+    /// thread; Linux only
+    #[arg(long, value_delimiter = ',')]
+    bind_cpu: Vec<usize>,
+
+    /// Number of connections to keep open. With HTTP/2 this is the number of
+    /// concurrent in-flight streams, which may share fewer TCP connections.
+    /// With --socket-pool-size it is only the number of in-flight requests
     #[arg(short = 'c', default_value_t = 100)]
     connections: usize,
 
-    /// Duration of the test in seconds
-    #[arg(short = 'd', default_value_t = 10)]
-    duration: u64,
+    /// Open exactly N TCP connections regardless of -c: with more requests
+    /// than sockets the requests share them (multiplexed over HTTP/2, queued
+    /// over HTTP/1.1), with fewer the extra sockets are opened and kept idle
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["ntlm", "websocket", "pipeline", "server_push_accept", "server_push_cancel", "pool_max_idle_per_host"]
+    )]
+    socket_pool_size: Option<u64>,
+
+    /// Duration of the test, e.g. 30, 500ms, 1.5s, 2m or 1h (bare numbers are seconds)
+    #[arg(short = 'd', value_parser = parse_duration, default_value = "10s")]
+    duration: Duration,
+
+    /// Send exactly this many requests instead of running for a fixed duration
+    #[arg(short = 'n', long, conflicts_with = "duration")]
+    max_requests: Option<u64>,
+
+    /// Warmup period in seconds before statistics collection begins
+    #[arg(short = 'w', long = "warmup", default_value_t = 0)]
+    warmup: u64,
 
     /// Timeout for each request in seconds
     #[arg(short = 'T', default_value_t = 5)]
     timeout: u64,
 
+    /// Timeout in seconds for opening a connection (TCP and TLS handshakes),
+    /// counted separately from request timeouts; keep it below -T
+    #[arg(long)]
+    connection_timeout: Option<f64>,
+
+    /// Timeout in milliseconds for each read of the response body, counted
+    /// separately from -T, which only covers waiting for the response headers
+    #[arg(long)]
+    read_timeout: Option<u64>,
+
+    /// Ramp up to the full connection count over this many seconds
+    #[arg(long, default_value_t = 0)]
+    rampup_duration: u64,
+
+    /// Open at most N connections per second while setting up, to avoid a
+    /// burst of simultaneous TLS handshakes; later requests are not limited
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    connections_per_second: Option<u64>,
+
+    /// Send N requests at once across all connections, wait for every
+    /// response, pause for --burst-interval-ms and repeat; -c should be at
+    /// least N for the whole burst to be in flight together
+    #[arg(
+        long,
+        alias = "burst-mode",
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with = "target_rps"
+    )]
+    burst_size: Option<u64>,
+
+    /// Pause in milliseconds between the end of one burst and the start of the next
+    #[arg(long, requires = "burst_size", default_value_t = 0)]
+    burst_interval_ms: u64,
+
+    /// Wait this long after the last request before printing statistics, e.g.
+    /// 500ms or 2s, so the server can finish asynchronous processing
+    #[arg(long, value_parser = parse_duration)]
+    post_delay: Option<Duration>,
+
+    /// Limit total throughput to this many requests per second (0 = unlimited)
+    #[arg(short = 'r', long = "rate", default_value_t = 0)]
+    rate: u64,
+
+    /// Find the concurrency that sustains this many requests per second:
+    /// starting from 10 connections, add 10 every second while below target
+    /// and halve when more than 1% of requests fail. Replaces -c
+    #[arg(long, conflicts_with_all = ["max_requests", "rate"])]
+    target_rps: Option<u64>,
+
+    /// Upper bound on connections opened by --target-rps
+    #[arg(long, default_value_t = 1000, requires = "target_rps")]
+    max_connections: usize,
+
+    /// Correct latency for coordinated omission by back-filling samples for
+    /// request slots missed while waiting on slow responses
+    #[arg(long)]
+    correct_latency: bool,
+
+    /// Pause this many milliseconds after each successful response
+    #[arg(long)]
+    think_time: Option<f64>,
+
+    /// Add a uniformly random 0..N milliseconds to each think time
+    #[arg(long, requires = "think_time", default_value_t = 0.0)]
+    think_time_jitter: f64,
+
+    /// Wait a random 0..N milliseconds before each request, not counted in latency
+    #[arg(long)]
+    jitter_ms: Option<u64>,
+
+    /// Distribution of --jitter-ms delays; exponential and pareto keep the
+    /// same mean as uniform but have no upper bound
+    #[arg(long, value_enum, requires = "jitter_ms", default_value_t = JitterDistribution::Uniform)]
+    jitter_distribution: JitterDistribution,
+
+    /// Retry each request up to N times on connection errors and --retry-on-status codes
+    #[arg(long, default_value_t = 0)]
+    retry: u32,
+
+    /// Backoff before the first retry in milliseconds, doubled on each further retry
+    #[arg(long, requires = "retry", default_value_t = 100)]
+    retry_backoff_ms: u64,
+
+    /// Comma-separated status codes that should be retried (e.g. 429,503)
+    #[arg(long, requires = "retry", value_delimiter = ',')]
+    retry_on_status: Vec<u16>,
+
+    /// Comma-separated status codes that count as successes instead of any
+    /// 2xx (e.g. 200,201,404); all other codes are errors
+    #[arg(long, value_delimiter = ',', value_parser = clap::value_parser!(u16).range(100..=599))]
+    expect_status: Vec<u16>,
+
+    /// Follow 3xx redirects instead of recording them as errors
+    #[arg(long)]
+    follow_redirects: bool,
+
+    /// Maximum redirects to follow per request; longer chains count as errors
+    #[arg(long, requires = "follow_redirects", default_value_t = 10)]
+    max_redirects: u32,
+
+    /// HTTP version to negotiate via ALPN (auto, http1, http2), or h2c for
+    /// cleartext HTTP/2 with prior knowledge. ALPN only applies to https://
+    /// targets; plain http:// always uses HTTP/1.1 unless h2c is chosen
+    #[arg(long, value_enum, default_value_t = HttpVersion::Auto)]
+    http_version: HttpVersion,
+
+    /// Only offer HTTP/2 over TLS (shorthand for --http-version http2)
+    #[arg(long, conflicts_with = "http_version")]
+    http2: bool,
+
+    /// Only offer HTTP/1.1, even when the server supports HTTP/2 (shorthand
+    /// for --http-version http1)
+    #[arg(long = "disable-http2", alias = "http1-only", conflicts_with_all = ["http_version", "http2", "http2_prior_knowledge"])]
+    disable_http2: bool,
+
+    /// Speak HTTP/2 over plain http:// without an upgrade (shorthand for --http-version h2c)
+    #[arg(long, conflicts_with_all = ["http_version", "http2"])]
+    http2_prior_knowledge: bool,
+
+    /// Streams to open concurrently on an HTTP/2 connection until the server
+    /// announces its own limit
+    #[arg(long)]
+    h2_max_concurrent_streams: Option<usize>,
+
+    /// Initial HTTP/2 flow control window of each stream, in bytes
+    #[arg(long)]
+    h2_initial_stream_window_size: Option<u32>,
+
+    /// Initial HTTP/2 flow control window of each connection, in bytes
+    #[arg(long)]
+    h2_initial_connection_window_size: Option<u32>,
+
+    /// Idle keep-alive timeout for pooled connections in seconds (0 disables keep-alive)
+    #[arg(long, default_value_t = 30)]
+    keepalive_timeout: u64,
+
+    /// Maximum number of idle pooled connections per host
+    #[arg(long)]
+    pool_max_idle_per_host: Option<usize>,
+
+    /// Disable keep-alive and open a fresh connection for every request
+    #[arg(long)]
+    no_keepalive: bool,
+
+    /// Accept-Encoding header sent with every request, e.g. "gzip, br, zstd";
+    /// responses are then decompressed to report their uncompressed size. By
+    /// default no Accept-Encoding is sent and nothing is decompressed
+    #[arg(long, conflicts_with = "no_decompress")]
+    accept_encoding: Option<String>,
+
+    /// Only accept gzip (shorthand for --accept-encoding gzip)
+    #[arg(long, conflicts_with_all = ["accept_encoding", "no_decompress"])]
+    gzip: bool,
+
+    /// Send a W3C traceparent header with a new span ID on every request
+    #[arg(long)]
+    otel_trace: bool,
+
+    /// Use this trace ID (32 hex characters) for every request instead of a random one
+    #[arg(long, requires = "otel_trace")]
+    otel_trace_id: Option<String>,
+
+    /// tracestate header to send alongside traceparent
+    #[arg(long, requires = "otel_trace")]
+    otel_tracestate: Option<String>,
+
+    /// Keep a cookie jar per connection: Set-Cookie responses are sent back on
+    /// later requests of the same connection
+    #[arg(long)]
+    cookie_jar: bool,
+
+    /// Cookie (name=value) to send with every request (repeatable)
+    #[arg(long = "cookie", value_parser = CookieConfig::parse_cookie)]
+    cookies: Vec<(String, String)>,
+
+    /// User-Agent header to send (defaults to rustwrk/<version>)
+    #[arg(long, conflicts_with = "no_user_agent")]
+    user_agent: Option<String>,
+
+    /// Don't send a User-Agent header
+    #[arg(long)]
+    no_user_agent: bool,
+
+    /// Send a random UUID v4 in this header (e.g. X-Request-ID) with every
+    /// request; the ID of each failed request is written to --error-log
+    #[arg(long)]
+    request_id_header: Option<HeaderName>,
+
+    /// Read response bodies chunk by chunk and report time to first chunk and
+    /// inter-chunk delays (for SSE, chunked JSON and other streaming APIs)
+    #[arg(long)]
+    streaming: bool,
+
+    /// Print the headers of the first N responses to stderr (default 3, set
+    /// with --dump-response-headers=N) and count the unique response header
+    /// names seen during the test
+    #[arg(long, alias = "response-header-dump", num_args = 0..=1, require_equals = true, default_missing_value = "3")]
+    dump_response_headers: Option<usize>,
+
+    /// Report the distribution of request and response body sizes
+    #[arg(long)]
+    request_size_histogram: bool,
+
+    /// Don't decode compressed responses, even when -H sends Accept-Encoding;
+    /// report wire bytes only
+    #[arg(long)]
+    no_decompress: bool,
+
+    /// Never send Accept-Encoding, dropping it from -H headers too, so the
+    /// server replies uncompressed
+    #[arg(long, conflicts_with_all = ["accept_encoding", "gzip"])]
+    disable_compression: bool,
+
+    /// Route requests through this proxy (http://host:port or https://host:port);
+    /// defaults to HTTP_PROXY / HTTPS_PROXY from the environment
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Set TCP_NODELAY on every connection, disabling Nagle's algorithm
+    #[arg(long, overrides_with = "no_tcp_nodelay")]
+    tcp_nodelay: bool,
+
+    /// Leave Nagle's algorithm enabled (the default)
+    #[arg(long, overrides_with = "tcp_nodelay")]
+    no_tcp_nodelay: bool,
+
+    /// Socket send buffer size (SO_SNDBUF) in bytes
+    #[arg(long)]
+    tcp_send_buffer: Option<usize>,
+
+    /// Socket receive buffer size (SO_RCVBUF) in bytes
+    #[arg(long)]
+    tcp_recv_buffer: Option<usize>,
+
+    /// Enable TCP Fast Open (TCP_FASTOPEN_CONNECT) so repeat connections send
+    /// the first request in the SYN; Linux only
+    #[arg(long, conflicts_with = "unix_socket")]
+    tcp_fast_open: bool,
+
+    /// Bind outgoing connections to this source IP, or to the addresses of
+    /// this network interface (e.g. eth1)
+    #[arg(long, value_parser = LocalAddress::parse, conflicts_with = "unix_socket")]
+    interface: Option<LocalAddress>,
+
+    /// Only connect to IPv4 addresses
+    #[arg(long = "ipv4", short = '4', conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Only connect to IPv6 addresses
+    #[arg(long = "ipv6", short = '6')]
+    ipv6: bool,
+
+    /// Resolve host:port to this address instead of using DNS, e.g.
+    /// example.com:443:127.0.0.1 (repeatable)
+    #[arg(long, value_parser = ResolveOverride::parse)]
+    resolve: Vec<ResolveOverride>,
+
+    /// Connect to this Unix domain socket instead of TCP; the URL's host is
+    /// only sent as the Host header
+    #[arg(long, conflicts_with = "proxy")]
+    unix_socket: Option<PathBuf>,
+
+    /// Skip TLS certificate verification
+    #[arg(short = 'k', long)]
+    insecure: bool,
+
+    /// CA bundle (PEM) used to verify the server instead of the system roots
+    #[arg(long)]
+    cacert: Option<PathBuf>,
+
+    /// Client certificate (PEM) for mutual TLS
+    #[arg(long, requires = "key")]
+    cert: Option<PathBuf>,
+
+    /// Private key (PEM) for the client certificate
+    #[arg(long, requires = "cert")]
+    key: Option<PathBuf>,
+
+    /// Lowest TLS version to offer
+    #[arg(long, value_enum)]
+    tls_min_version: Option<TlsVersion>,
+
+    /// Highest TLS version to offer
+    #[arg(long, value_enum)]
+    tls_max_version: Option<TlsVersion>,
+
+    /// Comma-separated rustls cipher suite names to offer instead of the
+    /// defaults (e.g. TLS13_AES_128_GCM_SHA256,TLS13_CHACHA20_POLY1305_SHA256)
+    #[arg(long, value_delimiter = ',')]
+    tls_ciphers: Vec<String>,
+
+    /// HTTP method to use
+    #[arg(short = 'X', long = "method", value_enum, ignore_case = true, default_value_t = HttpMethod::Get)]
+    method: HttpMethod,
+
+    /// Request body sent with each request
+    #[arg(long, conflicts_with = "body_file")]
+    body: Option<String>,
+
+    /// Request body rendered per request; supports {{index}}, {{random_int}},
+    /// {{random_uuid}} and {{timestamp_ms}}
+    #[arg(long, conflicts_with_all = ["body", "body_file"])]
+    body_template: Option<String>,
+
+    /// File to read the request body from
+    #[arg(long)]
+    body_file: Option<PathBuf>,
+
+    /// Download the request body from this URL once before the test
+    #[arg(long, conflicts_with_all = ["body", "body_template", "body_file"])]
+    body_from_url: Option<String>,
+
+    /// Largest body accepted from --body-from-url, in bytes
+    #[arg(long, default_value_t = 10 * 1024 * 1024, requires = "body_from_url")]
+    body_max_size: usize,
+
+    /// Stream the request body with chunked transfer encoding, in pieces of
+    /// this many bytes, to benchmark upload endpoints
+    #[arg(long)]
+    chunk_size: Option<usize>,
+
+    /// Pause this many milliseconds between request body chunks to simulate
+    /// a slow client
+    #[arg(long, requires = "chunk_size")]
+    chunk_delay_ms: Option<u64>,
+
+    /// Text field (name=value) of a multipart/form-data body, may be repeated
+    #[arg(long = "form", value_parser = MultipartForm::parse_field, conflicts_with_all = ["body", "body_template", "body_file", "body_from_url"])]
+    form_fields: Vec<(String, String)>,
+
+    /// File upload (name=@path) of a multipart/form-data body, may be repeated;
+    /// the file is re-read for every request
+    #[arg(long = "form-file", value_parser = MultipartForm::parse_file, conflicts_with_all = ["body", "body_template", "body_file", "body_from_url"])]
+    form_files: Vec<(String, PathBuf)>,
+
+    /// Add a header to every request ("Name: Value"), may be repeated
+    #[arg(short = 'H', long = "header")]
+    headers: Vec<String>,
+
+    /// Count 2xx responses whose body doesn't contain this string as errors
+    #[arg(long)]
+    assert_body_contains: Option<String>,
+
+    /// Count 2xx responses as errors unless the JSON body matches, e.g. $.status=ok;
+    /// may be repeated
+    #[arg(long = "assert-json-path", alias = "assert-body-json-path", value_parser = JsonPathAssertion::parse)]
+    assert_json_paths: Vec<JsonPathAssertion>,
+
+    /// Send "Authorization: Bearer <token>"; use @file to read the token from a file
+    #[arg(long, conflicts_with = "auth_basic")]
+    auth_bearer: Option<String>,
+
+    /// Send HTTP Basic credentials ("user:password"); use @file to read them from a file
+    #[arg(long)]
+    auth_basic: Option<String>,
+
+    /// Authenticate each connection with NTLM ("DOMAIN\user:password"); use
+    /// @file to read the credentials from a file. Forces HTTP/1.1
+    #[arg(long, conflicts_with_all = ["auth_bearer", "auth_basic", "no_keepalive"])]
+    ntlm: Option<String>,
+
+    /// Sign every request with AWS Signature Version 4 for this
+    /// "<service>/<region>", e.g. execute-api/us-east-1 or s3/eu-west-1
+    #[arg(
+        long,
+        value_name = "SERVICE/REGION",
+        requires_all = ["aws_access_key", "aws_secret_key"],
+        conflicts_with_all = ["auth_bearer", "auth_basic", "ntlm"]
+    )]
+    aws_sigv4: Option<String>,
+
+    /// AWS access key ID for --aws-sigv4
+    #[arg(long, requires = "aws_sigv4")]
+    aws_access_key: Option<String>,
+
+    /// AWS secret access key for --aws-sigv4; use @file to read it from a file
+    #[arg(long, requires = "aws_sigv4")]
+    aws_secret_key: Option<String>,
+
+    /// Temporary session token for --aws-sigv4, sent as x-amz-security-token;
+    /// use @file to read it from a file
+    #[arg(long, requires = "aws_sigv4")]
+    aws_session_token: Option<String>,
+
+    /// Lua script with optional setup(thread), request() and response(status, headers, body) hooks
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// Print a per-worker breakdown after the summary
+    #[arg(short = 'v', long)]
+    verbose: bool,
+
+    /// Print Tokio runtime metrics after the summary (workers, alive tasks,
+    /// global queue depth). Park counts and busy time additionally need a
+    /// build with RUSTFLAGS="--cfg tokio_unstable"
+    #[arg(long)]
+    runtime_metrics: bool,
+
+    /// Print a single JSON object with the results instead of human-readable output
+    #[arg(long)]
+    json: bool,
+
+    /// Summary format; defaults to table on a terminal and text otherwise
+    #[arg(long, value_enum, conflicts_with = "json")]
+    output_format: Option<OutputFormat>,
+
+    /// Unit for latencies in all output; JSON reports keep the `_ms` fields
+    /// and add each of them in this unit as well (e.g. latency_p99_us)
+    #[arg(long, value_enum, default_value_t = LatencyUnit::Ms)]
+    latency_unit: LatencyUnit,
+
+    /// Print the share of requests served on a reused connection
+    #[arg(long)]
+    connect_reuse_report: bool,
+
+    /// Print how many new connections negotiated HTTP/1.1 and HTTP/2
+    #[arg(long)]
+    http_version_report: bool,
+
+    /// Print an ASCII bar chart of the latency distribution
+    #[arg(long)]
+    histogram: bool,
+
+    /// Comma-separated latency percentiles to report, each between 0 and 100
+    #[arg(long, alias = "histogram-percentiles", value_delimiter = ',', value_parser = parse_percentile, default_value = "50,75,90,95,99")]
+    percentiles: Vec<f64>,
+
+    /// Save this run's JSON report as a baseline for --compare-baseline
+    #[arg(long)]
+    save_baseline: Option<PathBuf>,
+
+    /// Compare RPS, latency and error rate against a report saved with
+    /// --save-baseline (or --json) and print a regression table
+    #[arg(long)]
+    compare_baseline: Option<PathBuf>,
+
+    /// Exit with status 1 when a metric is worse than the baseline by more than this percentage
+    #[arg(long, default_value_t = 5.0, requires = "compare_baseline")]
+    regression_threshold: f64,
+
+    /// Significant figures (1-5) of the latency histograms. Each extra figure
+    /// makes percentiles ten times more accurate and histograms ten times
+    /// larger: 3 takes ~250KB per histogram, 5 takes ~25MB, and every thread
+    /// keeps two
+    #[arg(long, alias = "histogram-sigfigs", default_value_t = DEFAULT_HISTOGRAM_PRECISION, value_parser = clap::value_parser!(u8).range(1..=5))]
+    histogram_precision: u8,
+
+    /// Save the full latency histogram (HdrHistogram V2, base64) to this file
+    #[arg(long)]
+    output_histogram: Option<PathBuf>,
+
+    /// Write <PREFIX>_latency_hist.dat, <PREFIX>_rps_timeseries.dat and a
+    /// <PREFIX>.gnuplot script that plots both as PNG charts
+    #[arg(long, value_name = "PREFIX")]
+    output_gnuplot: Option<PathBuf>,
+
+    /// Save the full result, including histograms, as JSON for --load-result
+    #[arg(long)]
+    save_result: Option<PathBuf>,
+
+    /// Print the stats of a result saved with --save-result instead of
+    /// running a benchmark
+    #[arg(long, conflicts_with_all = ["url", "url_file", "config", "save_result"])]
+    load_result: Option<PathBuf>,
+
+    /// Write the results in Prometheus text format to this file
+    #[arg(long)]
+    prometheus_output: Option<PathBuf>,
+
+    /// Push the results to this Prometheus Pushgateway
+    #[arg(long)]
+    prometheus_push: Option<String>,
+
+    /// Write one InfluxDB line-protocol point per second of the test to this file
+    #[arg(long)]
+    influxdb_output: Option<PathBuf>,
+
+    /// Write the per-second points to this InfluxDB (v2 write API) at the end of the test
+    #[arg(long)]
+    influxdb_push: Option<String>,
+
+    /// InfluxDB API token, or @file to read it from a file
+    #[arg(long, requires = "influxdb_push")]
+    influxdb_token: Option<String>,
+
+    /// InfluxDB organization to write to
+    #[arg(long, requires = "influxdb_push")]
+    influxdb_org: Option<String>,
+
+    /// InfluxDB bucket to write to
+    #[arg(long, requires = "influxdb_push")]
+    influxdb_bucket: Option<String>,
+
+    /// Extra key=value labels for the Prometheus metrics and InfluxDB tags
+    /// (repeatable or comma-separated)
+    #[arg(long, value_delimiter = ',', value_parser = prometheus::parse_label)]
+    labels: Vec<(String, String)>,
+
+    /// Write one CSV row per second of the test to this file
+    #[arg(long)]
+    csv: Option<PathBuf>,
+
+    /// Write one CSV row per request (timestamp_us,latency_us,status,bytes,error) to this file
+    #[arg(long)]
+    request_log: Option<PathBuf>,
+
+    /// Write buffer size of the request log, in bytes
+    #[arg(long, default_value_t = 64 * 1024, requires = "request_log")]
+    request_log_buffer_size: usize,
+
+    /// Write one CSV row per failed request (timestamp,url,error,latency_ms,request_id)
+    /// to this file; error is the status code for non-2xx responses
+    #[arg(long, alias = "log-errors")]
+    error_log: Option<PathBuf>,
+
+    /// Fail if the p99 latency exceeds this many milliseconds
+    #[arg(long)]
+    latency_p99: Option<f64>,
+
+    /// Fail if the p95 latency exceeds this many milliseconds
+    #[arg(long)]
+    latency_p95: Option<f64>,
+
+    /// Fail if the error rate exceeds this percentage
+    #[arg(long)]
+    error_rate_threshold: Option<f64>,
+
+    /// YAML list of per-URL thresholds ({url_pattern, p99_ms, error_rate_pct});
+    /// fail if any URL matching a pattern exceeds them
+    #[arg(long, alias = "response-time-sla-file")]
+    sla_file: Option<PathBuf>,
+
+    /// Abort the test once the rolling error rate exceeds this percentage
+    #[arg(long, value_parser = parse_percentage)]
+    abort_on_error_rate: Option<f64>,
+
+    /// Window in seconds for the rolling error rate used by --abort-on-error-rate
+    #[arg(long, default_value_t = 5, requires = "abort_on_error_rate")]
+    error_window: u64,
+
+    /// File with one target URL per line; requests cycle through the list
+    #[arg(long)]
+    url_file: Option<PathBuf>,
+
+    /// Pick URLs from --url-file at random instead of round-robin
+    #[arg(long, requires = "url_file")]
+    url_file_random: bool,
+
+    /// JSON array of per-connection overrides ({"url", "headers", "body"}),
+    /// cycled through when there are fewer entries than connections
+    #[arg(long)]
+    connections_file: Option<PathBuf>,
+
+    /// Validate the configuration and print the first request instead of
+    /// running the benchmark; no connections are opened
+    #[arg(long, conflicts_with = "websocket")]
+    dry_run: bool,
+
+    /// Benchmark a WebSocket (ws:// or wss://) endpoint: each connection sends
+    /// the message and waits for a reply before sending the next one
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "url_file", "config", "max_requests", "script", "sla_file", "save_baseline", "compare_baseline",
+            "threads", "rate", "percentiles", "histogram_precision", "csv", "request_log", "error_log",
+            "save_result", "output_histogram", "prometheus_output", "prometheus_push", "influxdb_output",
+            "influxdb_push", "influxdb_token", "influxdb_org", "influxdb_bucket", "proxy", "unix_socket",
+        ]
+    )]
+    websocket: bool,
+
+    /// Text message to send in --websocket mode
+    #[arg(long, requires = "websocket")]
+    ws_message: Option<String>,
+
+    /// File to read the --websocket message from; sent as binary unless it is valid UTF-8
+    #[arg(long, requires = "websocket", conflicts_with = "ws_message")]
+    ws_message_file: Option<PathBuf>,
+
+    /// HTTP/1.1 pipelining: each connection writes a burst of N requests
+    /// before reading any of the N responses
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = [
+            "websocket", "url_file", "config", "max_requests", "script", "http2", "dry_run", "sla_file", "save_baseline",
+            "compare_baseline", "threads", "rate", "percentiles", "histogram_precision", "csv", "request_log", "error_log",
+            "save_result", "output_histogram", "prometheus_output", "prometheus_push", "influxdb_output", "influxdb_push",
+            "influxdb_token", "influxdb_org", "influxdb_bucket", "proxy", "unix_socket",
+        ]
+    )]
+    pipeline: Option<u32>,
+
+    /// Enable HTTP/2 server push and read every pushed resource; requests go
+    /// over HTTP/2 (h2c with prior knowledge for http:// URLs)
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "websocket", "pipeline", "url_file", "config", "max_requests", "script", "dry_run", "disable_http2",
+            "threads", "rate", "csv", "request_log", "error_log", "save_result", "output_histogram", "prometheus_output",
+            "prometheus_push", "influxdb_output", "influxdb_push", "influxdb_token", "influxdb_org", "influxdb_bucket",
+            "proxy", "unix_socket",
+        ]
+    )]
+    server_push_accept: bool,
+
+    /// Like --server-push-accept, but reset every pushed stream with
+    /// RST_STREAM as soon as it is promised, to measure the push overhead
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "server_push_accept", "websocket", "pipeline", "url_file", "config", "max_requests", "script", "dry_run",
+            "disable_http2",
+            "threads", "rate", "csv", "request_log", "error_log", "save_result", "output_histogram", "prometheus_output",
+            "prometheus_push", "influxdb_output", "influxdb_push", "influxdb_token", "influxdb_org", "influxdb_bucket",
+            "proxy", "unix_socket",
+        ]
+    )]
+    server_push_cancel: bool,
+
+    /// Benchmark two or more URLs at the same time, each with the full -t/-c
+    /// load and its own statistics, and print a side-by-side comparison
+    #[arg(
+        long = "parallel",
+        alias = "parallel-urls",
+        num_args = 2..,
+        value_name = "URL",
+        conflicts_with_all = ["url", "url_file", "config", "request_log", "error_log", "save_baseline", "compare_baseline", "save_result", "csv"]
+    )]
+    parallel: Vec<String>,
+
     /// Target URL
-    #[arg(required = true)]
-    url: String,
+    #[arg(required_unless_present_any = ["url_file", "config", "load_result", "parallel"])]
+    url: Option<String>,
+
+    /// Run the phases of a TOML scenario file one after another; settings a
+    /// phase leaves out are taken from the command line
+    #[arg(long)]
+    config: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare two latency histograms saved with --output-histogram
+    Compare {
+        /// Histogram from the baseline run
+        baseline: PathBuf,
+        /// Histogram from the run to compare against the baseline
+        candidate: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Indented sections
+    Text,
+    /// Bordered Metric / Value / Unit table
+    Table,
+    /// One JSON object, same as --json
+    Json,
+    /// metric,value,unit rows
+    Csv,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LatencyUnit {
+    /// Microseconds, the histogram's own resolution
+    Us,
+    Ms,
+    S,
+}
+
+impl LatencyUnit {
+    fn suffix(self) -> &'static str {
+        match self {
+            LatencyUnit::Us => "us",
+            LatencyUnit::Ms => "ms",
+            LatencyUnit::S => "s",
+        }
+    }
+
+    /// Format a latency in milliseconds, without the unit suffix
+    fn format(self, ms: f64) -> String {
+        match self {
+            LatencyUnit::Us => format!("{:.0}", ms * 1000.0),
+            LatencyUnit::Ms => format!("{:.2}", ms),
+            LatencyUnit::S => format!("{:.4}", ms / 1000.0),
+        }
+    }
+
+    fn convert(self, ms: f64) -> f64 {
+        match self {
+            LatencyUnit::Us => ms * 1000.0,
+            LatencyUnit::Ms => ms,
+            LatencyUnit::S => ms / 1000.0,
+        }
+    }
+}
+
+impl Args {
+    fn output_format(&self) -> OutputFormat {
+        match self.output_format {
+            _ if self.json => OutputFormat::Json,
+            Some(format) => format,
+            None if std::io::stdout().is_terminal() => OutputFormat::Table,
+            None => OutputFormat::Text,
+        }
+    }
+
+    /// JSON and CSV output must not be mixed with progress and banners
+    fn machine_output(&self) -> bool {
+        matches!(self.output_format(), OutputFormat::Json | OutputFormat::Csv)
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum HttpMethod {
+    Get,
+    Post,
+    Put,
+    Delete,
+    Patch,
+    Head,
+    Options,
+    Trace,
+}
+
+impl From<HttpMethod> for Method {
+    fn from(method: HttpMethod) -> Self {
+        match method {
+            HttpMethod::Get => Method::GET,
+            HttpMethod::Post => Method::POST,
+            HttpMethod::Put => Method::PUT,
+            HttpMethod::Delete => Method::DELETE,
+            HttpMethod::Patch => Method::PATCH,
+            HttpMethod::Head => Method::HEAD,
+            HttpMethod::Options => Method::OPTIONS,
+            HttpMethod::Trace => Method::TRACE,
+        }
+    }
+}
+
+fn parse_percentage(value: &str) -> Result<f64> {
+    let percentage: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid percentage {:?}", value))?;
+    if !(0.0..=100.0).contains(&percentage) {
+        return Err(anyhow!("Percentage {} must be between 0 and 100", percentage));
+    }
+    Ok(percentage)
+}
+
+fn parse_percentile(value: &str) -> Result<f64> {
+    let percentile: f64 = value
+        .parse()
+        .map_err(|_| anyhow!("Invalid percentile {:?}", value))?;
+    if percentile <= 0.0 || percentile >= 100.0 {
+        return Err(anyhow!("Percentile {} must be greater than 0 and less than 100", percentile));
+    }
+    Ok(percentile)
+}
+
+fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .ok_or_else(|| anyhow!("Invalid header {:?}: expected \"Name: Value\"", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .with_context(|| format!("Invalid header name in {:?}", header))?;
+    let value = HeaderValue::from_str(value.trim())
+        .with_context(|| format!("Invalid header value in {:?}", header))?;
+    Ok((name, value))
+}
+
+fn aws_signer(args: &Args) -> Result<Option<AwsSigner>> {
+    let Some(scope) = &args.aws_sigv4 else {
+        return Ok(None);
+    };
+    let signer = AwsSigner::new(
+        scope,
+        args.aws_access_key.clone().unwrap_or_default(),
+        read_secret(args.aws_secret_key.as_deref().unwrap_or_default())?,
+        args.aws_session_token.as_deref().map(read_secret).transpose()?,
+    )?;
+    Ok(Some(signer))
+}
+
+// 以 @ 开头时从文件读取
+fn read_secret(value: &str) -> Result<String> {
+    match value.strip_prefix('@') {
+        Some(path) => Ok(std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read credentials file {}", path))?
+            .trim_end_matches(['\r', '\n'])
+            .to_string()),
+        None => Ok(value.to_string()),
+    }
+}
+
+fn tls_options(args: &Args) -> TlsOptions {
+    TlsOptions {
+        insecure: args.insecure,
+        cacert: args.cacert.clone(),
+        cert: args.cert.clone(),
+        key: args.key.clone(),
+        min_version: args.tls_min_version,
+        max_version: args.tls_max_version,
+        ciphers: args.tls_ciphers.clone(),
+    }
+}
+
+fn resolver(args: &Args) -> Resolver {
+    let family = match (args.ipv4, args.ipv6) {
+        (true, _) => IpFamily::V4,
+        (_, true) => IpFamily::V6,
+        _ => IpFamily::Any,
+    };
+    Resolver::new(family, args.resolve.clone())
+}
+
+fn auth_header(args: &Args) -> Result<Option<(HeaderName, HeaderValue)>> {
+    let value = match (&args.auth_bearer, &args.auth_basic) {
+        (Some(token), _) => format!("Bearer {}", read_secret(token)?),
+        (None, Some(credentials)) => {
+            let credentials = read_secret(credentials)?;
+            if !credentials.contains(':') {
+                return Err(anyhow!("--auth-basic expects \"user:password\""));
+            }
+            format!("Basic {}", STANDARD.encode(credentials))
+        }
+        (None, None) => return Ok(None),
+    };
+    let mut value = HeaderValue::from_str(&value).context("Invalid characters in credentials")?;
+    value.set_sensitive(true);
+    Ok(Some((header::AUTHORIZATION, value)))
+}
+
+/// One element of --connections-file.
+#[derive(Deserialize)]
+struct ConnectionEntry {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+fn read_connections_file(path: &Path, connections: usize) -> Result<Vec<ConnectionConfig>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read connections file {}", path.display()))?;
+    let entries: Vec<ConnectionEntry> = serde_json::from_str(&contents)
+        .with_context(|| format!("Connections file {} must be a JSON array of objects", path.display()))?;
+    if entries.is_empty() {
+        return Err(anyhow!("Connections file {} is empty", path.display()));
+    }
+    if entries.len() > connections {
+        tracing::warn!(
+            "Connections file has {} entries but only {} connections; the rest are unused",
+            entries.len(),
+            connections
+        );
+    }
+
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let context = || format!("Invalid entry {} in {}", i, path.display());
+            let url = match entry.url {
+                Some(url) => {
+                    Url::parse(&url).with_context(|| format!("Invalid URL {:?}", url)).with_context(context)?;
+                    Some(url.parse().with_context(context)?)
+                }
+                None => None,
+            };
+            let headers = entry
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let name = HeaderName::from_bytes(name.as_bytes())
+                        .with_context(|| format!("Invalid header name {:?}", name))?;
+                    let value = HeaderValue::from_str(value)
+                        .with_context(|| format!("Invalid value for header {}", name))?;
+                    Ok((name, value))
+                })
+                .collect::<Result<Vec<_>>>()
+                .with_context(context)?;
+            Ok(ConnectionConfig {
+                url,
+                headers,
+                body: entry.body.map(Bytes::from),
+            })
+        })
+        .collect()
+}
+
+fn read_url_file(path: &PathBuf) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read URL file {}", path.display()))?;
+    let urls: Vec<String> = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+    if urls.is_empty() {
+        return Err(anyhow!("URL file {} contains no URLs", path.display()));
+    }
+    Ok(urls)
+}
+
+fn print_json(report: &impl Serialize, unit: LatencyUnit) {
+    let mut json = serde_json::to_value(report).expect("Failed to serialize report");
+    add_latency_unit(&mut json, unit);
+    if let serde_json::Value::Object(map) = &mut json {
+        map.insert("latency_unit".to_string(), unit.suffix().into());
+    }
+    println!("{}", serde_json::to_string_pretty(&json).expect("Failed to serialize report"));
+}
+
+// 每个 xxx_ms 字段旁边补一个 --latency-unit 单位的 xxx_us / xxx_s 字段，原字段保持不变
+fn add_latency_unit(json: &mut serde_json::Value, unit: LatencyUnit) {
+    match json {
+        serde_json::Value::Object(map) => {
+            let converted: Vec<(String, f64)> = map
+                .iter()
+                .filter(|_| unit != LatencyUnit::Ms)
+                .filter_map(|(key, value)| Some((key.strip_suffix("_ms")?, value.as_f64()?)))
+                .map(|(name, ms)| (format!("{}_{}", name, unit.suffix()), unit.convert(ms)))
+                .collect();
+            for value in map.values_mut() {
+                add_latency_unit(value, unit);
+            }
+            for (key, value) in converted {
+                map.insert(key, value.into());
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(|item| add_latency_unit(item, unit)),
+        _ => {}
+    }
+}
+
+// 表格和 CSV 共用的 (指标, 值, 单位) 行
+fn summary_rows(report: &Report, unit: LatencyUnit) -> Vec<(String, String, &'static str)> {
+    let mb = |bytes: u64| format!("{:.2}", bytes as f64 / 1024.0 / 1024.0);
+    let ms = |value: f64| unit.format(value);
+    let error_rate = if report.requests > 0 { report.errors as f64 / report.requests as f64 * 100.0 } else { 0.0 };
+    let mut rows = vec![
+        ("Protocol".to_string(), report.protocol.clone(), ""),
+        ("Duration".to_string(), format!("{:.2}", report.duration_secs), "s"),
+        ("Requests".to_string(), report.requests.to_string(), ""),
+        ("Successes".to_string(), report.successes.to_string(), ""),
+        ("Errors".to_string(), report.errors.to_string(), ""),
+        ("Error Rate".to_string(), format!("{:.2}", error_rate), "%"),
+        ("Requests/sec".to_string(), format!("{:.2}", report.rps), "req/s"),
+        ("Transfer".to_string(), mb(report.bytes_transferred), "MB"),
+        ("Transfer/sec".to_string(), format!("{:.2}", report.transfer_mb_per_sec), "MB/s"),
+    ];
+    if report.bytes_decompressed > 0 {
+        rows.push(("Decompressed".to_string(), mb(report.bytes_decompressed), "MB"));
+    }
+    if report.bytes_sent > 0 {
+        rows.push(("Uploaded".to_string(), mb(report.bytes_sent), "MB"));
+        rows.push(("Upload/sec".to_string(), format!("{:.2}", report.upload_mb_per_sec), "MB/s"));
+    }
+    if report.header_bytes > 0 {
+        rows.push(("Header Bytes/sec".to_string(), format!("{:.2}", report.header_bytes_per_sec / 1024.0), "KB/s"));
+    }
+    for (name, sizes) in [("Request Size", &report.request_sizes), ("Response Size", &report.response_sizes)] {
+        if let Some(sizes) = sizes {
+            rows.push((format!("{} Min", name), sizes.min.to_string(), "B"));
+            rows.push((format!("{} Mean", name), format!("{:.1}", sizes.mean), "B"));
+            rows.push((format!("{} Max", name), sizes.max.to_string(), "B"));
+            rows.push((format!("{} P99", name), sizes.p99.to_string(), "B"));
+        }
+    }
+    for (name, value) in [
+        ("Latency Avg", report.latency_mean_ms),
+        ("Latency Min", report.latency_min_ms),
+        ("Latency Max", report.latency_max_ms),
+    ] {
+        rows.push((name.to_string(), ms(value), unit.suffix()));
+    }
+    for p in &report.latency_percentiles {
+        rows.push((format!("Latency P{}", p.percentile), ms(p.latency_ms), unit.suffix()));
+    }
+    for (name, value) in [
+        ("TTFB P50", report.ttfb_p50_ms),
+        ("TTFB P95", report.ttfb_p95_ms),
+        ("TTFB P99", report.ttfb_p99_ms),
+    ] {
+        rows.push((name.to_string(), ms(value), unit.suffix()));
+    }
+    if report.connections.new > 0 {
+        rows.push(("New Connections".to_string(), report.connections.new.to_string(), ""));
+        rows.push(("Reused Connections".to_string(), report.connections.reused.to_string(), ""));
+    }
+    if let Some(setup) = &report.connections.setup {
+        rows.push(("First Connection At".to_string(), format!("{:.0}", setup.first_ms), "ms"));
+        rows.push(("Last Connection At".to_string(), format!("{:.0}", setup.last_ms), "ms"));
+    }
+    for (code, count) in &report.status_codes {
+        rows.push((format!("Status {}", code), count.to_string(), ""));
+    }
+    if let Some(push) = &report.server_push {
+        rows.push(("Pushed Resources".to_string(), push.resources.to_string(), ""));
+        rows.push(("Pushed Bytes".to_string(), push.bytes.to_string(), "B"));
+    }
+    if !report.allowed_methods.is_empty() {
+        let methods = report.allowed_methods.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+        rows.push(("Allowed Methods".to_string(), methods, ""));
+    }
+    if !report.response_headers.is_empty() {
+        rows.push(("Unique Response Headers".to_string(), report.response_headers.len().to_string(), ""));
+    }
+    for (name, count) in [
+        ("Assertion Failures", report.assertion_failures),
+        ("Redirects", report.redirects),
+        ("Retries", report.retries),
+        ("Connect Timeouts", report.connect_timeouts),
+        ("Read Timeouts", report.read_timeouts),
+        ("Body Read Timeouts", report.body_timeouts),
+    ] {
+        if count > 0 {
+            rows.push((name.to_string(), count.to_string(), ""));
+        }
+    }
+    for (name, value) in &report.script_counters {
+        rows.push((format!("Counter {}", name), value.to_string(), ""));
+    }
+    rows
+}
+
+fn print_summary_table(report: &Report, unit: LatencyUnit) {
+    let rows = summary_rows(report, unit);
+    let len = |s: &str| s.chars().count();
+    let mut widths = [len("Metric"), len("Value"), len("Unit")];
+    for (metric, value, unit) in &rows {
+        widths = [widths[0].max(len(metric)), widths[1].max(len(value)), widths[2].max(len(unit))];
+    }
+    // 超出终端宽度时截断指标名，边框和间隔共 10 列
+    let terminal = terminal_size::terminal_size().map_or(80, |(terminal_size::Width(w), _)| w as usize);
+    widths[0] = widths[0].min(terminal.saturating_sub(widths[1] + widths[2] + 10).max(8));
+    let fit = |s: &str| {
+        if len(s) <= widths[0] {
+            s.to_string()
+        } else {
+            s.chars().take(widths[0] - 1).chain(std::iter::once('…')).collect()
+        }
+    };
+    let border = format!("+-{}-+-{}-+-{}-+", "-".repeat(widths[0]), "-".repeat(widths[1]), "-".repeat(widths[2]));
+
+    println!();
+    println!("{}", border);
+    println!("| {:<w0$} | {:<w1$} | {:<w2$} |", "Metric", "Value", "Unit", w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+    println!("{}", border);
+    for (metric, value, unit) in &rows {
+        println!("| {:<w0$} | {:>w1$} | {:<w2$} |", fit(metric), value, unit, w0 = widths[0], w1 = widths[1], w2 = widths[2]);
+    }
+    println!("{}", border);
+}
+
+fn print_summary_csv(report: &Report, unit: LatencyUnit) {
+    let field = |s: &str| {
+        if s.contains([',', '"', '\n']) {
+            format!("\"{}\"", s.replace('"', "\"\""))
+        } else {
+            s.to_string()
+        }
+    };
+    println!("metric,value,unit");
+    for (metric, value, suffix) in summary_rows(report, unit) {
+        println!("{},{},{}", field(&metric), field(&value), suffix);
+    }
+}
+
+fn print_stats(report: &Report, unit: LatencyUnit) {
+    let latency = |ms: f64| format!("{}{}", unit.format(ms), unit.suffix());
+    println!("\nStatistics:");
+    println!("  Protocol: {}", report.protocol);
+    println!("  Total Requests: {}", report.requests);
+    println!("  Total Bytes: {:.2}MB", report.bytes_transferred as f64 / 1024.0 / 1024.0);
+    if report.bytes_decompressed > 0 {
+        println!(
+            "  Decompressed: {:.2}MB (ratio {:.2}x)",
+            report.bytes_decompressed as f64 / 1024.0 / 1024.0,
+            report.compression_ratio
+        );
+    }
+    println!("  Requests/sec: {:.2}", report.rps);
+    println!("  Transfer/sec: {:.2}MB", report.transfer_mb_per_sec);
+    if report.bytes_sent > 0 {
+        println!("  Uploaded: {:.2}MB", report.bytes_sent as f64 / 1024.0 / 1024.0);
+        println!("  Upload/sec: {:.2}MB", report.upload_mb_per_sec);
+    }
+    if report.header_bytes > 0 {
+        println!("  Header Bytes: {:.2}MB", report.header_bytes as f64 / 1024.0 / 1024.0);
+        println!("  Header Bytes/sec: {:.2}KB", report.header_bytes_per_sec / 1024.0);
+    }
+    for (name, sizes) in [("Request", &report.request_sizes), ("Response", &report.response_sizes)] {
+        if let Some(sizes) = sizes {
+            println!(
+                "  {} Sizes: min {}B, mean {:.1}B, max {}B, p99 {}B",
+                name, sizes.min, sizes.mean, sizes.max, sizes.p99
+            );
+        }
+    }
+    print_rps_timeline(&report.timeline);
+    print_bursts(&report.bursts, &latency);
+    println!("\nLatency:");
+    
+    println!("  Avg: {}", latency(report.latency_mean_ms));
+    println!("  Min: {}", latency(report.latency_min_ms));
+    println!("  Max: {}", latency(report.latency_max_ms));
+    for p in &report.latency_percentiles {
+        println!("  P{}: {}", p.percentile, latency(p.latency_ms));
+    }
+    println!("\nTTFB:");
+    println!("  P50: {}", latency(report.ttfb_p50_ms));
+    println!("  P95: {}", latency(report.ttfb_p95_ms));
+    println!("  P99: {}", latency(report.ttfb_p99_ms));
+    
+    let success_rate = if report.requests > 0 {
+        (report.successes as f64 / report.requests as f64) * 100.0
+    } else {
+        0.0
+    };
+    let connections = &report.connections;
+    if connections.new > 0 {
+        println!("\nConnections:");
+        println!("  New: {}, Reused: {}", connections.new, connections.reused);
+        if let Some(setup) = &connections.setup {
+            println!(
+                "  Connection setup time: first at T={:.0}ms, last at T={:.0}ms",
+                setup.first_ms, setup.last_ms
+            );
+        }
+        let tls = connections.tls12 + connections.tls13;
+        if tls > 0 {
+            println!(
+                "  TLS versions: {:.1}% TLS 1.3, {:.1}% TLS 1.2",
+                connections.tls13 as f64 / tls as f64 * 100.0,
+                connections.tls12 as f64 / tls as f64 * 100.0
+            );
+        }
+        for (name, phase) in [("DNS", &connections.dns), ("TCP", &connections.tcp), ("TLS", &connections.tls)] {
+            if phase.count > 0 {
+                println!(
+                    "  {}: avg {}, p50 {}, p99 {}",
+                    name,
+                    latency(phase.mean_ms),
+                    latency(phase.p50_ms),
+                    latency(phase.p99_ms)
+                );
+            }
+        }
+    }
+    if !report.status_codes.is_empty() {
+        println!("\nStatus Codes:");
+        for (code, count) in &report.status_codes {
+            println!("  {}: {}", code, count);
+        }
+    }
+
+    if let Some(push) = &report.server_push {
+        println!("\nServer Push: {} resources, {} bytes", push.resources, push.bytes);
+        println!("  Completed: {}, Failed: {}, Cancelled: {}", push.completed, push.failed, push.cancelled);
+    }
+
+    if !report.allowed_methods.is_empty() {
+        let methods = report.allowed_methods.iter().map(String::as_str).collect::<Vec<_>>().join(", ");
+        println!("\nAllowed methods: {}", methods);
+    }
+
+    if !report.response_headers.is_empty() {
+        println!("\nUnique response headers: {}", report.response_headers.len());
+        for (name, count) in &report.response_headers {
+            println!("  {}: {} responses", name, count);
+        }
+    }
+
+    if let Some(streaming) = &report.streaming {
+        println!("\nStreaming:");
+        println!("  Chunks: {} ({:.1} per response)", streaming.chunks, streaming.chunks_per_response);
+        println!(
+            "  Time to First Chunk: p50 {}, p99 {}",
+            latency(streaming.ttfc_p50_ms),
+            latency(streaming.ttfc_p99_ms)
+        );
+        println!(
+            "  Inter-chunk Delay: p50 {}, p99 {}",
+            latency(streaming.chunk_delay_p50_ms),
+            latency(streaming.chunk_delay_p99_ms)
+        );
+    }
+
+    if report.assertion_failures > 0 {
+        println!("\nAssertion Failures: {}", report.assertion_failures);
+    }
+    if report.redirects > 0 {
+        println!("\nRedirects: {}", report.redirects);
+    }
+    if report.retries > 0 {
+        println!("\nRetries: {}", report.retries);
+    }
+    if report.connect_timeouts > 0 || report.read_timeouts > 0 || report.body_timeouts > 0 {
+        println!("\nTimeouts:");
+        println!("  Connect timeouts: {}", report.connect_timeouts);
+        println!("  Read timeouts: {}", report.read_timeouts);
+        println!("  Body read timeouts: {}", report.body_timeouts);
+    }
+    if !report.script_counters.is_empty() {
+        println!("\nScript Counters:");
+        for (name, value) in &report.script_counters {
+            println!("  {}: {}", name, value);
+        }
+    }
+
+    println!("\nSuccess: {:.2}% ({}/{})", success_rate, report.successes, report.requests);
+    println!("Errors: {:.2}% ({} errors)", (report.errors as f64 / report.requests as f64) * 100.0, report.errors);
+}
+
+// 线程池是否饱和：队列积压、worker 忙碌时间占比
+fn print_runtime_metrics(duration_secs: f64) {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    println!("\nRuntime:");
+    println!("  Workers: {}", metrics.num_workers());
+    println!("  Alive tasks: {}", metrics.num_alive_tasks());
+    println!("  Global queue depth: {}", metrics.global_queue_depth());
+    print_worker_activity(&metrics, duration_secs);
+}
+
+#[cfg(tokio_unstable)]
+fn print_worker_activity(metrics: &tokio::runtime::RuntimeMetrics, duration_secs: f64) {
+    let workers = 0..metrics.num_workers();
+    let parks: u64 = workers.clone().map(|worker| metrics.worker_park_count(worker)).sum();
+    let busy: Duration = workers.map(|worker| metrics.worker_total_busy_duration(worker)).sum();
+    let capacity = duration_secs * metrics.num_workers() as f64;
+    println!("  Park count: {}", parks);
+    println!(
+        "  Busy time: {:.2}s ({:.1}% of worker time)",
+        busy.as_secs_f64(),
+        if capacity > 0.0 { busy.as_secs_f64() / capacity * 100.0 } else { 0.0 }
+    );
+}
+
+#[cfg(not(tokio_unstable))]
+fn print_worker_activity(_metrics: &tokio::runtime::RuntimeMetrics, _duration_secs: f64) {
+    println!("  Park count, busy time: unavailable (rebuild with RUSTFLAGS=\"--cfg tokio_unstable\")");
+}
+
+fn print_resources(resources: &ResourceUsage) {
+    let mut memory = Vec::new();
+    if let Some(rss) = resources.rss_mb {
+        memory.push(format!("RSS {:.1}MB", rss));
+    }
+    if let Some(peak) = resources.peak_rss_mb {
+        memory.push(format!("peak {:.1}MB", peak));
+    }
+    if memory.is_empty() && resources.open_fds.is_none() {
+        return;
+    }
+    println!("\nResources:");
+    if !memory.is_empty() {
+        println!("  Memory: {}", memory.join(", "));
+    }
+    match (resources.open_fds, resources.fd_limit) {
+        (Some(open), Some(limit)) => println!("  File Descriptors: {} open (limit {})", open, limit),
+        (Some(open), None) => println!("  File Descriptors: {} open", open),
+        _ => {}
+    }
+}
+
+// --dry-run 只显示请求体的前 1 KB
+const DRY_RUN_BODY_PREVIEW: usize = 1024;
+
+fn print_request(request: &hyper::Request<Bytes>) {
+    println!("{} {} {:?}", request.method(), request.uri(), request.version());
+    for (name, value) in request.headers() {
+        println!("{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+    }
+    let body = request.body();
+    if !body.is_empty() {
+        println!();
+        let preview = &body[..body.len().min(DRY_RUN_BODY_PREVIEW)];
+        println!("{}", String::from_utf8_lossy(preview));
+        if body.len() > preview.len() {
+            println!("... ({} more bytes, {} total)", body.len() - preview.len(), body.len());
+        }
+    }
+    println!();
+}
+
+fn print_websocket_stats(report: &WebSocketReport, unit: LatencyUnit) {
+    let latency = |ms: f64| format!("{}{}", unit.format(ms), unit.suffix());
+    println!("\nWebSocket Messages:");
+    println!("  Sent: {}", report.messages_sent);
+    println!("  Received: {}", report.messages_received);
+    println!("  Messages/sec: {:.2}", report.messages_per_sec);
+    println!("\nRound-trip Latency:");
+    println!("  Avg: {}", latency(report.latency_mean_ms));
+    println!("  Min: {}", latency(report.latency_min_ms));
+    println!("  Max: {}", latency(report.latency_max_ms));
+    println!("  P50: {}", latency(report.latency_p50_ms));
+    println!("  P95: {}", latency(report.latency_p95_ms));
+    println!("  P99: {}", latency(report.latency_p99_ms));
+    println!("\nErrors: {}", report.errors);
+}
+
+async fn run_websocket(args: &Args, headers: Vec<(HeaderName, HeaderValue)>, stop: Arc<AtomicBool>) -> Result<()> {
+    let url = args.url.clone().unwrap_or_default();
+    let message = match (&args.ws_message, &args.ws_message_file) {
+        (Some(message), _) => message.clone().into_bytes(),
+        (None, Some(path)) => std::fs::read(path)
+            .with_context(|| format!("Failed to read WebSocket message file {}", path.display()))?,
+        (None, None) => return Err(anyhow!("--websocket needs --ws-message or --ws-message-file")),
+    };
+    if matches!(args.output_format, Some(OutputFormat::Csv | OutputFormat::Table)) {
+        return Err(anyhow!("--websocket only supports text and JSON output"));
+    }
+    if !args.machine_output() {
+        println!("Running {:?} WebSocket test @ {}", args.duration, url);
+        println!("  {} connections", args.connections);
+        println!();
+    }
+
+    let result = WebSocketConfig {
+        url,
+        message,
+        headers,
+        connections: args.connections,
+        duration: args.duration,
+        warmup: Duration::from_secs(args.warmup),
+        timeout: Duration::from_secs(args.timeout),
+        tls: tls_options(args),
+        resolver: resolver(args),
+        stop,
+    }
+    .run()
+    .await?;
+
+    let report = result.report();
+    if args.output_format() == OutputFormat::Json {
+        print_json(&report, args.latency_unit);
+    } else {
+        print_websocket_stats(&report, args.latency_unit);
+    }
+    exit_on_sla_failures(&check_thresholds(
+        args,
+        report.latency_p95_ms,
+        report.latency_p99_ms,
+        // 连接和发送失败不计入已发送消息，每次尝试要么收到回复要么记一次错误
+        report.messages_received + report.errors,
+        report.errors,
+    ));
+    Ok(())
+}
+
+fn print_pipeline_stats(report: &PipelineReport, unit: LatencyUnit) {
+    let latency = |ms: f64| format!("{}{}", unit.format(ms), unit.suffix());
+    println!("\nPipelined Requests (depth {}):", report.depth);
+    println!("  Sent: {}", report.requests_sent);
+    println!("  Responses: {}", report.responses);
+    println!("  Requests/sec: {:.2}", report.requests_per_sec);
+    println!("  Transfer: {:.2}MB", report.bytes as f64 / 1024.0 / 1024.0);
+    println!("\nLatency (send to response):");
+    println!("  Avg: {}", latency(report.latency_mean_ms));
+    println!("  Min: {}", latency(report.latency_min_ms));
+    println!("  Max: {}", latency(report.latency_max_ms));
+    println!("  P50: {}", latency(report.latency_p50_ms));
+    println!("  P95: {}", latency(report.latency_p95_ms));
+    println!("  P99: {}", latency(report.latency_p99_ms));
+    if !report.status_codes.is_empty() {
+        println!("\nStatus Codes:");
+        for (status, count) in &report.status_codes {
+            println!("  {}: {}", status, count);
+        }
+    }
+    println!("\nErrors: {}", report.errors);
+}
+
+async fn run_pipeline(
+    args: &Args,
+    depth: usize,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Bytes,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    let url = args.url.clone().unwrap_or_default();
+    if matches!(args.output_format, Some(OutputFormat::Csv | OutputFormat::Table)) {
+        return Err(anyhow!("--pipeline only supports text and JSON output"));
+    }
+    if !args.machine_output() {
+        println!("Running {:?} pipelined test @ {}", args.duration, url);
+        println!("  {} connections, {} requests per burst", args.connections, depth);
+        println!();
+    }
+
+    let result = PipelineConfig {
+        url,
+        method: args.method.into(),
+        headers,
+        body,
+        connections: args.connections,
+        depth,
+        duration: args.duration,
+        warmup: Duration::from_secs(args.warmup),
+        timeout: Duration::from_secs(args.timeout),
+        tls: tls_options(args),
+        resolver: resolver(args),
+        stop,
+    }
+    .run()
+    .await?;
+
+    let report = result.report(depth);
+    if args.output_format() == OutputFormat::Json {
+        print_json(&report, args.latency_unit);
+    } else {
+        print_pipeline_stats(&report, args.latency_unit);
+    }
+    exit_on_sla_failures(&check_thresholds(
+        args,
+        report.latency_p95_ms,
+        report.latency_p99_ms,
+        report.responses + report.errors,
+        report.errors,
+    ));
+    Ok(())
+}
+
+async fn run_server_push(
+    args: &Args,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Bytes,
+    stop: Arc<AtomicBool>,
+) -> Result<()> {
+    let url = args.url.clone().unwrap_or_default();
+    let mode = if args.server_push_cancel { PushMode::Cancel } else { PushMode::Accept };
+    if !args.machine_output() {
+        println!("Running {:?} HTTP/2 server push test @ {}", args.duration, url);
+        println!("  {} connections, pushes {}", args.connections, if mode == PushMode::Cancel { "cancelled" } else { "accepted" });
+        println!();
+    }
+
+    let stats = Statistics::new()
+        .with_percentiles(args.percentiles.clone())
+        .with_histogram_precision(args.histogram_precision);
+    let push = ServerPushConfig {
+        url: url.clone(),
+        method: args.method.into(),
+        headers,
+        body,
+        connections: args.connections,
+        duration: args.duration,
+        warmup: Duration::from_secs(args.warmup),
+        timeout: Duration::from_secs(args.timeout),
+        tls: tls_options(args),
+        resolver: resolver(args),
+        mode,
+        stop,
+    }
+    .run(stats.clone())
+    .await?;
+
+    let mut report = stats.report();
+    report.server_push = Some(push);
+    if let Some(path) = &args.sla_file {
+        report.sla_results = check_sla_file(path, &[(url.clone(), report.clone())])?;
+    }
+    match args.output_format() {
+        OutputFormat::Json => print_json(&report, args.latency_unit),
+        OutputFormat::Csv => print_summary_csv(&report, args.latency_unit),
+        format => {
+            if format == OutputFormat::Table {
+                print_summary_table(&report, args.latency_unit);
+            } else {
+                print_stats(&report, args.latency_unit);
+            }
+            print_sla_results(&report.sla_results);
+        }
+    }
+
+    let regressions = check_baseline(args, &report)?;
+    exit_on_sla_failures(&check_sla(args, &report));
+    exit_on_regressions(args, &regressions);
+    Ok(())
+}
+
+/// Print a result saved with --save-result in the selected output format
+fn print_saved_result(args: &Args, result: &BenchmarkResult) {
+    let report = result.report();
+    match args.output_format() {
+        OutputFormat::Json => print_json(&report, args.latency_unit),
+        OutputFormat::Csv => print_summary_csv(&report, args.latency_unit),
+        format => {
+            if format == OutputFormat::Table {
+                print_summary_table(&report, args.latency_unit);
+            } else {
+                print_stats(&report, args.latency_unit);
+            }
+            if args.histogram {
+                print_latency_histogram(&result.histogram);
+            }
+            print_url_breakdown(result);
+            if args.connect_reuse_report {
+                print_connection_reuse(&report);
+            }
+            if args.http_version_report {
+                print_connection_protocols(&report);
+            }
+            if args.verbose {
+                print_worker_breakdown(result);
+            }
+        }
+    }
+}
+
+fn print_comparison(baseline: &Path, candidate: &Path) -> Result<()> {
+    let comparison = Comparison::new(&compare::load_histogram(baseline)?, &compare::load_histogram(candidate)?)?;
+    let diff = |before: f64, after: f64| {
+        let percent = if before > 0.0 { (after - before) / before * 100.0 } else { 0.0 };
+        format!("{:+.2}ms ({:+.1}%)", after - before, percent)
+    };
+
+    println!("Baseline:  {}", baseline.display());
+    println!("Candidate: {}", candidate.display());
+    println!();
+    println!("        {:>12} {:>12}   Diff", "Baseline", "Candidate");
+    println!(
+        "  Mean: {:>10.2}ms {:>10.2}ms   {}",
+        comparison.baseline_mean_ms,
+        comparison.candidate_mean_ms,
+        diff(comparison.baseline_mean_ms, comparison.candidate_mean_ms)
+    );
+    println!(
+        "  P99:  {:>10.2}ms {:>10.2}ms   {}",
+        comparison.baseline_p99_ms,
+        comparison.candidate_p99_ms,
+        diff(comparison.baseline_p99_ms, comparison.candidate_p99_ms)
+    );
+    println!();
+    println!("t = {:.2}: {}", comparison.t_statistic, comparison.significance());
+    Ok(())
+}
+
+fn print_baseline_comparison(path: &Path, diffs: &[MetricDiff], threshold: f64) {
+    println!("\nBaseline Comparison ({}):", path.display());
+    println!("  {:<10} {:>12} {:>12} {:>12} {:>9}", "Metric", "Baseline", "Current", "Diff", "Change");
+    for diff in diffs {
+        let change = if diff.percent().is_finite() {
+            format!("{:+.1}%", diff.percent())
+        } else {
+            "new".to_string()
+        };
+        let row = format!(
+            "  {:<10} {:>10.2}{:<2} {:>10.2}{:<2} {:>+10.2}{:<2} {:>9}",
+            diff.name, diff.baseline, diff.unit, diff.current, diff.unit, diff.diff(), diff.unit, change
+        );
+        // 退步标红，改进标绿
+        if diff.regressed(threshold) {
+            println!("{}", row.red());
+        } else if diff.improved(threshold) {
+            println!("{}", row.green());
+        } else {
+            println!("{}", row);
+        }
+    }
+}
+
+/// 并排比较 --parallel 各目标的吞吐、延迟和错误率，最优值标绿
+fn print_parallel_comparison(urls: &[String], reports: &[Report], unit: LatencyUnit) {
+    println!("\nParallel Comparison:");
+    for (i, url) in urls.iter().enumerate() {
+        println!("  #{}: {}", i + 1, url);
+    }
+    println!();
+
+    let error_rate = |r: &Report| if r.requests > 0 { r.errors as f64 / r.requests as f64 * 100.0 } else { 0.0 };
+    let latency = |ms: f64| format!("{}{}", unit.format(ms), unit.suffix());
+    let column = |value: fn(&Report) -> f64| reports.iter().map(value).collect::<Vec<_>>();
+    // (指标, 各目标的值, 越大越好)
+    let rows = [
+        ("Requests/sec", column(|r| r.rps), true),
+        ("Latency P50", column(|r| r.latency_p50_ms), false),
+        ("Latency P99", column(|r| r.latency_p99_ms), false),
+        ("Error rate", reports.iter().map(error_rate).collect(), false),
+    ];
+    let format = |name: &str, value: f64| match name {
+        "Requests/sec" => format!("{:.2}", value),
+        "Error rate" => format!("{:.2}%", value),
+        _ => latency(value),
+    };
+
+    let header: Vec<String> = (1..=urls.len()).map(|i| format!("{:>14}", format!("#{}", i))).collect();
+    println!("  {:<14}{}", "Metric", header.join(""));
+    for (name, values, higher_is_better) in &rows {
+        let best = values
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| if *higher_is_better { a.total_cmp(b) } else { b.total_cmp(a) })
+            .map(|(i, _)| i);
+        let cells: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let cell = format!("{:>14}", format(name, *value));
+                if Some(i) == best && values.len() > 1 { cell.green().bold().to_string() } else { cell }
+            })
+            .collect();
+        println!("  {:<14}{}", name, cells.join(""));
+    }
+
+    // 按吞吐给出结论
+    let mut ranked: Vec<usize> = (0..reports.len()).collect();
+    ranked.sort_by(|&a, &b| reports[b].rps.total_cmp(&reports[a].rps));
+    let (fastest, slowest) = (ranked[0], ranked[ranked.len() - 1]);
+    if reports[slowest].rps > 0.0 {
+        println!(
+            "\n  {} is fastest: {:.2}x the requests/sec of #{}",
+            format!("#{}", fastest + 1).green().bold(),
+            reports[fastest].rps / reports[slowest].rps,
+            slowest + 1
+        );
+    }
+}
+
+const SPARKLINE: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPARKLINE_WIDTH: usize = 60;
+
+// 超过一行宽度时把相邻的样本取平均
+fn sparkline(samples: &[f64]) -> String {
+    let columns: Vec<f64> = samples
+        .chunks(samples.len().div_ceil(SPARKLINE_WIDTH).max(1))
+        .map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+        .collect();
+    let max = columns.iter().copied().fold(0.0, f64::max);
+    columns
+        .iter()
+        .map(|value| {
+            let level = if max > 0.0 { (value / max * 7.0).round() as usize } else { 0 };
+            SPARKLINE[level.min(7)]
+        })
+        .collect()
+}
+
+fn print_rps_timeline(timeline: &[StatsSnapshot]) {
+    if timeline.is_empty() {
+        return;
+    }
+    let samples: Vec<u64> = timeline.iter().map(|snapshot| snapshot.requests).collect();
+    let mut sorted = samples.clone();
+    sorted.sort_unstable();
+    let p95 = sorted[(sorted.len() * 95).div_ceil(100).saturating_sub(1)];
+    println!(
+        "  RPS over time: min {}, max {}, p95 {}",
+        sorted[0],
+        sorted[sorted.len() - 1],
+        p95
+    );
+
+    // 长时间测试时把相邻几秒取平均，保持一行显示
+    let samples: Vec<f64> = samples.iter().map(|&requests| requests as f64).collect();
+    println!("  {}", sparkline(&samples));
+}
+
+fn print_bursts(bursts: &[BurstSample], latency: &impl Fn(f64) -> String) {
+    if bursts.is_empty() {
+        return;
+    }
+    let mut completion: Vec<f64> = bursts.iter().map(|burst| burst.completion_ms).collect();
+    println!("\nBursts: {} of {} requests", bursts.len(), bursts[0].requests);
+    // 按开始时间的顺序画出每批的完成时间
+    println!("  Completion over time: {}", sparkline(&completion));
+    completion.sort_by(f64::total_cmp);
+    let mean = completion.iter().sum::<f64>() / completion.len() as f64;
+    let p99 = completion[(completion.len() * 99).div_ceil(100).saturating_sub(1)];
+    println!(
+        "  Completion time: avg {}, min {}, max {}, p99 {}",
+        latency(mean),
+        latency(completion[0]),
+        latency(completion[completion.len() - 1]),
+        latency(p99)
+    );
+}
+
+// 区间上界（微秒），最后一个区间没有上界
+const LATENCY_BUCKETS: [(u64, &str); 8] = [
+    (1_000, "<=1ms"),
+    (2_000, "1-2ms"),
+    (5_000, "2-5ms"),
+    (10_000, "5-10ms"),
+    (25_000, "10-25ms"),
+    (50_000, "25-50ms"),
+    (100_000, "50-100ms"),
+    (u64::MAX, ">100ms"),
+];
+
+fn print_latency_histogram(histogram: &Histogram<u64>) {
+    let total = histogram.len();
+    if total == 0 {
+        return;
+    }
+    let mut counts = [0u64; LATENCY_BUCKETS.len()];
+    for value in histogram.iter_recorded() {
+        let micros = histogram.highest_equivalent(value.value_iterated_to());
+        let bucket = LATENCY_BUCKETS.iter().position(|(upper, _)| micros <= *upper).unwrap_or(LATENCY_BUCKETS.len() - 1);
+        counts[bucket] += value.count_at_value();
+    }
+
+    // 标签、计数和百分比大约占 32 列，剩下的留给柱子
+    let width = terminal_size::terminal_size().map_or(80, |(terminal_size::Width(w), _)| w as usize);
+    let bar_width = width.saturating_sub(32).max(10);
+    let max = counts.iter().copied().max().unwrap_or(0).max(1);
+
+    println!("\nLatency Distribution:");
+    for ((_, label), count) in LATENCY_BUCKETS.iter().zip(counts) {
+        let bar = (count as f64 / max as f64 * bar_width as f64).round() as usize;
+        println!(
+            "  {:>8} {:>10} {:>6.2}% {}",
+            label,
+            count,
+            count as f64 / total as f64 * 100.0,
+            "#".repeat(bar)
+        );
+    }
+}
+
+fn print_phase_breakdown(phases: &[(String, BenchmarkResult)]) {
+    println!("\nPhases:");
+    for (label, result) in phases {
+        let report = result.report();
+        println!(
+            "  {}: {} requests, {:.2} req/s, p50 {:.2}ms, p99 {:.2}ms, {} errors",
+            label, report.requests, report.rps, report.latency_p50_ms, report.latency_p99_ms, report.errors
+        );
+    }
+}
+
+fn print_worker_breakdown(result: &BenchmarkResult) {
+    println!("\nPer-Worker Breakdown:");
+    println!("  {:>6} {:>10} {:>8} {:>10} {:>10} {:>10}", "Worker", "Requests", "Errors", "Avg(ms)", "P99(ms)", "Bytes(MB)");
+    for worker in &result.per_worker {
+        let report = worker.report();
+        println!(
+            "  {:>6} {:>10} {:>8} {:>10.2} {:>10.2} {:>10.2}",
+            worker.worker_id,
+            report.requests,
+            report.errors,
+            report.latency_mean_ms,
+            report.latency_p99_ms,
+            report.bytes_transferred as f64 / 1024.0 / 1024.0
+        );
+    }
+}
+
+fn print_socket_options(args: &Args, report: &Report, fast_open: bool, fast_open_connections: Option<u64>) {
+    let Some(socket) = &report.connections.socket else {
+        return;
+    };
+    // 系统可能把缓冲区向上取整（Linux 会翻倍），同时列出请求值
+    let requested = |size: Option<usize>| size.map(|size| format!(" (requested {})", size)).unwrap_or_default();
+    println!("\nSocket Options:");
+    println!("  TCP_NODELAY: {}", if socket.nodelay { "on" } else { "off" });
+    println!("  SO_SNDBUF: {} bytes{}", socket.send_buffer_bytes, requested(args.tcp_send_buffer));
+    println!("  SO_RCVBUF: {} bytes{}", socket.recv_buffer_bytes, requested(args.tcp_recv_buffer));
+    match (fast_open, fast_open_connections) {
+        (false, _) => println!("  TCP Fast Open: off"),
+        (true, Some(used)) => println!(
+            "  TCP Fast Open: on ({} of {} new connections sent data in the SYN)",
+            used, report.connections.new
+        ),
+        (true, None) => println!("  TCP Fast Open: on"),
+    }
+}
+
+fn print_http2_options(args: &Args, report: &Report) {
+    if !report.protocol.starts_with("HTTP/2") {
+        return;
+    }
+    // hyper 不暴露服务端的 SETTINGS，未设置的参数显示为默认值
+    let configured = |value: Option<String>| value.unwrap_or_else(|| "default".to_string());
+    println!("\nHTTP/2 Settings:");
+    println!("  Max concurrent streams: {}", configured(args.h2_max_concurrent_streams.map(|n| n.to_string())));
+    println!(
+        "  Initial stream window: {}",
+        configured(args.h2_initial_stream_window_size.map(|size| format!("{} bytes", size)))
+    );
+    println!(
+        "  Initial connection window: {}",
+        configured(args.h2_initial_connection_window_size.map(|size| format!("{} bytes", size)))
+    );
 }
 
-#[derive(Debug, Default)]
-struct Stats {
-    requests: u64,
-    success: u64,
-    errors: u64,
-    bytes: u64,
-    latency_min: u64,
-    latency_max: u64,
-    latency_sum: u64,
+fn print_connection_reuse(report: &Report) {
+    let ConnectionReport { new, reused, .. } = report.connections;
+    let percent = if new + reused > 0 { reused as f64 / (new + reused) as f64 * 100.0 } else { 0.0 };
+    println!("\nConnection reuse: {:.1}% ({} new, {} reused)", percent, new, reused);
+}
+
+fn print_connection_protocols(report: &Report) {
+    let ConnectionReport { http1, http2, .. } = report.connections;
+    let total = http1 + http2;
+    let percent = |count: u64| if total > 0 { count as f64 / total as f64 * 100.0 } else { 0.0 };
+    println!(
+        "\nProtocol: {:.0}% HTTP/2, {:.0}% HTTP/1.1 ({} new connections)",
+        percent(http2),
+        percent(http1),
+        total
+    );
+}
+
+fn print_url_breakdown(result: &BenchmarkResult) {
+    if result.per_url.is_empty() {
+        return;
+    }
+    println!("\nPer-URL Breakdown:");
+    for (url, report) in &result.per_url {
+        println!(
+            "  {}: {} requests, {} errors, avg {:.2}ms, p99 {:.2}ms",
+            url, report.requests, report.errors, report.latency_mean_ms, report.latency_p99_ms
+        );
+    }
+}
+
+fn print_sla_results(results: &[sla::SlaResult]) {
+    if results.is_empty() {
+        return;
+    }
+    println!("\nSLA:");
+    for result in results {
+        if !result.matched() {
+            println!("  SKIP {} (no matching URLs)", result.url_pattern);
+            continue;
+        }
+        let status = if result.passed { "PASS".green() } else { "FAIL".red() };
+        println!("  {} {} ({} URLs)", status, result.url_pattern, result.urls.len());
+        for violation in &result.violations {
+            println!("    {}", violation);
+        }
+    }
+}
+
+/// Check the --sla-file rules against the per-URL reports, warning about
+/// rules that match none of the benchmarked URLs
+fn check_sla_file(path: &Path, reports: &[(String, Report)]) -> Result<Vec<sla::SlaResult>> {
+    let results = sla::check(&sla::load(path)?, reports);
+    for result in results.iter().filter(|result| !result.matched()) {
+        tracing::warn!("SLA rule {:?} matches none of the benchmarked URLs", result.url_pattern);
+    }
+    Ok(results)
+}
+
+fn check_sla(args: &Args, report: &Report) -> Vec<String> {
+    let mut failures = check_thresholds(args, report.latency_p95_ms, report.latency_p99_ms, report.requests, report.errors);
+    for result in &report.sla_results {
+        failures.extend(result.violations.iter().cloned());
+    }
+    failures
+}
+
+/// --latency-p99, --latency-p95 and --error-rate-threshold; also used by the
+/// modes that don't produce a `Report`
+fn check_thresholds(args: &Args, p95_ms: f64, p99_ms: f64, requests: u64, errors: u64) -> Vec<String> {
+    let mut failures = Vec::new();
+    if let Some(limit) = args.latency_p99 {
+        if p99_ms > limit {
+            failures.push(format!("p99 latency {:.2}ms exceeds {:.2}ms", p99_ms, limit));
+        }
+    }
+    if let Some(limit) = args.latency_p95 {
+        if p95_ms > limit {
+            failures.push(format!("p95 latency {:.2}ms exceeds {:.2}ms", p95_ms, limit));
+        }
+    }
+    if let Some(limit) = args.error_rate_threshold {
+        let error_rate = if requests > 0 { errors as f64 / requests as f64 * 100.0 } else { 0.0 };
+        if error_rate > limit {
+            failures.push(format!("error rate {:.2}% exceeds {:.2}%", error_rate, limit));
+        }
+    }
+    failures
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+/// Save the report with --save-baseline and compare it with --compare-baseline;
+/// returns the names of the regressed metrics
+fn check_baseline(args: &Args, report: &Report) -> Result<Vec<&'static str>> {
+    if let Some(path) = &args.save_baseline {
+        compare::save_baseline(report, path)?;
+    }
+    let Some(path) = &args.compare_baseline else {
+        return Ok(Vec::new());
+    };
+    let diffs = compare::diff_baseline(&Baseline::load(path)?, &Baseline::from_report(report));
+    if !args.machine_output() {
+        print_baseline_comparison(path, &diffs, args.regression_threshold);
+    }
+    Ok(diffs
+        .into_iter()
+        .filter(|diff| diff.regressed(args.regression_threshold))
+        .map(|diff| diff.name)
+        .collect())
+}
+
+fn exit_on_regressions(args: &Args, regressions: &[&str]) {
+    if regressions.is_empty() {
+        return;
+    }
+    eprintln!(
+        "\nREGRESSION: {} worse than baseline by more than {:.1}%",
+        regressions.join(", "),
+        args.regression_threshold
+    );
+    std::process::exit(1);
+}
+
+/// Print the failed SLA checks, if any, and exit with status 1
+fn exit_on_sla_failures(failures: &[String]) {
+    if failures.is_empty() {
+        return;
+    }
+    eprintln!("\nFAILED SLA:");
+    for failure in failures {
+        eprintln!("  {}", failure);
+    }
+    std::process::exit(1);
+}
+
+/// One benchmark run: the command-line configuration or one --config phase.
+struct Run {
+    label: Option<String>,
+    target: String,
+    threads: usize,
+    connections: usize,
+    duration: Duration,
+    config: BenchmarkConfig,
+}
+
+// 单 URL 的阶段没有分 URL 的统计，按目标 URL 合并各阶段的总体结果；
+// 测了多个 URL 的阶段直接用各 URL 的统计
+fn url_reports(runs: &[Run], phases: &[(String, BenchmarkResult)]) -> Vec<(String, Report)> {
+    let mut merged: Vec<(String, BenchmarkResult)> = Vec::new();
+    let mut reports = Vec::new();
+    for (run, (_, result)) in runs.iter().zip(phases) {
+        if !result.per_url.is_empty() {
+            reports.extend(result.per_url.iter().cloned());
+            continue;
+        }
+        match merged.iter_mut().find(|(url, _)| *url == run.target) {
+            Some((_, existing)) => existing.merge(result),
+            None => merged.push((run.target.clone(), result.clone())),
+        }
+    }
+    merged.into_iter().map(|(url, result)| (url, result.report())).chain(reports).collect()
+}
+
+fn print_banner(args: &Args, run: &Run) {
+    let test = match args.max_requests {
+        Some(requests) => format!("{} requests", requests),
+        None => format!("{:?} test", run.duration),
+    };
+    let prefix = run.label.as_ref().map(|label| format!("[{}] ", label)).unwrap_or_default();
+    if args.warmup > 0 {
+        println!("{}Running {}s warmup + {} @ {}", prefix, args.warmup, test, run.target);
+    } else {
+        println!("{}Running {} @ {}", prefix, test, run.target);
+    }
+    match args.target_rps {
+        Some(target) => println!("  {} threads and up to {} connections targeting {} req/s", run.threads, args.max_connections, target),
+        None => match args.socket_pool_size {
+            Some(sockets) => println!("  {} threads and {} connections over {} sockets", run.threads, run.connections, sockets),
+            None => println!("  {} threads and {} connections", run.threads, run.connections),
+        },
+    }
+    if !args.bind_cpu.is_empty() {
+        let cpus: Vec<String> = args.bind_cpu.iter().map(|cpu| cpu.to_string()).collect();
+        println!("  threads bound to CPUs {}", cpus.join(","));
+    }
+    println!();
+}
+
+fn spawn_sampler(
+    stats: Statistics,
+    progress: bool,
+    mut csv: Option<BufWriter<File>>,
+    influx: Option<InfluxLines>,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        interval.tick().await;
+        let mut sampler = Sampler::new(stats.clone());
+        let mut elapsed = 0u64;
+        loop {
+            interval.tick().await;
+            elapsed += 1;
+            let window = sampler.sample();
+            let active = stats.active_connections();
+            let counters = window.counters;
+            let ms = |q: f64| window.histogram.value_at_quantile(q) as f64 / 1000.0;
+
+            if progress {
+                println!(
+                    "[{}s] RPS: {} | Latency p50: {:.1}ms | Errors: {}",
+                    elapsed, counters.requests, ms(0.5), counters.errors
+                );
+            }
+            if let Some(influx) = &influx {
+                influx.record(counters.requests, counters.errors, ms(0.5), ms(0.99));
+            }
+            if let Some(writer) = csv.as_mut() {
+                let row = writeln!(
+                    writer,
+                    "{},{},{},{},{:.3},{:.3},{:.3},{:.3},{}",
+                    elapsed,
+                    counters.requests,
+                    counters.errors,
+                    counters.requests,
+                    ms(0.5),
+                    ms(0.95),
+                    ms(0.99),
+                    counters.bytes as f64 / 1024.0 / 1024.0,
+                    active
+                )
+                .and_then(|_| writer.flush());
+                if let Err(e) = row {
+                    tracing::error!("Failed to write CSV row: {}", e);
+                    csv = None;
+                }
+            }
+        }
+    })
+}
+
+fn main() -> Result<()> {
     // 初始化日志
-    tracing_subscriber::fmt::init();
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
 
     // 解析命令行参数
     let args = Args::parse();
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+    // 绑核时每个 worker 线程对应一个核心
+    if !args.bind_cpu.is_empty() {
+        let binding = CpuBinding::new(args.bind_cpu.clone(), args.threads)?;
+        runtime.worker_threads(args.threads).on_thread_start(move || binding.pin_next());
+    }
+    runtime.build()?.block_on(run(args))
+}
 
-    // 验证URL
-    let _url = Url::parse(&args.url)?;
+// --parallel：每个 URL 一份独立配置和统计，在同一个运行时里同时压测
+async fn run_parallel(args: &Args, config: BenchmarkConfig) -> Result<()> {
+    let configs: Vec<BenchmarkConfig> = args.parallel.iter().map(|url| config.clone().urls(vec![url.clone()])).collect();
+    if args.dry_run {
+        for config in &configs {
+            print_request(&config.dry_run()?);
+        }
+        return Ok(());
+    }
+    if !args.machine_output() {
+        print_banner(
+            args,
+            &Run {
+                label: None,
+                target: format!("{} (in parallel)", args.parallel.join(", ")),
+                threads: args.threads,
+                connections: args.connections,
+                duration: args.duration,
+                config,
+            },
+        );
+    }
 
-    println!("Running {}s test @ {}", args.duration, args.url);
-    println!("  {} threads and {} connections", args.threads, args.connections);
-    println!();
+    let results = futures::future::try_join_all(
+        configs.iter().map(|config| config.run_with(
+            Statistics::new()
+                .with_percentiles(args.percentiles.clone())
+                .with_histogram_precision(args.histogram_precision),
+        )),
+    )
+    .await?;
+    let reports: Vec<Report> = results.iter().map(BenchmarkResult::report).collect();
+    let sla_results = match &args.sla_file {
+        Some(path) => {
+            let reports: Vec<(String, Report)> = args.parallel.iter().cloned().zip(reports.iter().cloned()).collect();
+            check_sla_file(path, &reports)?
+        }
+        None => Vec::new(),
+    };
+    if args.output_format() == OutputFormat::Json {
+        let entries: Vec<_> = args
+            .parallel
+            .iter()
+            .zip(&reports)
+            .map(|(url, report)| serde_json::json!({ "url": url, "latency_unit": args.latency_unit.suffix(), "report": report }))
+            .collect();
+        print_json(&entries, args.latency_unit);
+    } else {
+        print_parallel_comparison(&args.parallel, &reports, args.latency_unit);
+        print_sla_results(&sla_results);
+    }
+
+    // 阈值对每个 URL 分别检查
+    let mut failures: Vec<String> = args
+        .parallel
+        .iter()
+        .zip(&reports)
+        .flat_map(|(url, report)| check_sla(args, report).into_iter().map(move |failure| format!("{}: {}", url, failure)))
+        .collect();
+    failures.extend(sla_results.iter().flat_map(|result| result.violations.iter().cloned()));
+    exit_on_sla_failures(&failures);
+    Ok(())
+}
 
-    let connections_per_thread = args.connections / args.threads;
-    let mut handles = Vec::with_capacity(args.threads);
-
-    // 启动工作线程
-    for _ in 0..args.threads {
-        let url = args.url.clone();
-        let duration = Duration::from_secs(args.duration);
-        let timeout = Duration::from_secs(args.timeout);
-        
-        let handle = tokio::spawn(async move {
-            let mut worker = Worker::new(connections_per_thread);
-            worker.run(url, duration, timeout).await
+async fn run(args: Args) -> Result<()> {
+    if let Some(Command::Compare { baseline, candidate }) = &args.command {
+        return print_comparison(baseline, candidate);
+    }
+    if let Some(path) = &args.load_result {
+        print_saved_result(&args, &BenchmarkResult::load(path)?);
+        return Ok(());
+    }
+
+    // 读取 URL 列表
+    let mut urls: Vec<String> = args.url.iter().chain(&args.parallel).cloned().collect();
+    if let Some(path) = &args.url_file {
+        urls.extend(read_url_file(path)?);
+    }
+    let target = if urls.len() == 1 {
+        urls[0].clone()
+    } else {
+        format!("{} URLs", urls.len())
+    };
+
+    // 读取请求体
+    let body = match (&args.body, &args.body_file, &args.body_from_url) {
+        (Some(body), _, _) => Bytes::from(body.clone()),
+        (None, Some(path), _) => Bytes::from(
+            std::fs::read(path)
+                .with_context(|| format!("Failed to read body file {}", path.display()))?,
+        ),
+        (None, None, Some(url)) => push::fetch_body(url, args.body_max_size, &tls_options(&args)).await?,
+        (None, None, None) => Bytes::new(),
+    };
+    let template = args.body_template.as_deref().map(Template::parse).transpose()?;
+    let form = MultipartForm::new(args.form_fields.clone(), args.form_files.clone());
+    if !form.is_empty() {
+        // 提前编码一次，文件不可读时立即报错
+        form.encode()?;
+    }
+    if (!body.is_empty() || template.is_some() || !form.is_empty()) && matches!(args.method, HttpMethod::Get | HttpMethod::Head | HttpMethod::Trace) {
+        tracing::warn!("Request body supplied for a {:?} request; sending it anyway", args.method);
+    }
+
+    // 解析请求头
+    let mut headers = args
+        .headers
+        .iter()
+        .map(|h| parse_header(h))
+        .collect::<Result<Vec<_>>>()?;
+    headers.extend(auth_header(&args)?);
+    if args.disable_compression {
+        headers.retain(|(name, _)| *name != header::ACCEPT_ENCODING);
+    }
+    // -H 显式指定的 Accept-Encoding 优先
+    let accept_encoding = if args.gzip { Some("gzip") } else { args.accept_encoding.as_deref() };
+    if let Some(accept_encoding) = accept_encoding.filter(|_| !headers.iter().any(|(name, _)| *name == header::ACCEPT_ENCODING)) {
+        let value = HeaderValue::from_str(accept_encoding)
+            .with_context(|| format!("Invalid --accept-encoding value {:?}", accept_encoding))?;
+        headers.push((header::ACCEPT_ENCODING, value));
+    }
+    // 只有发送了 Accept-Encoding 才解压响应
+    let decompress = !args.no_decompress && headers.iter().any(|(name, _)| *name == header::ACCEPT_ENCODING);
+
+    let user_agent = match (&args.user_agent, args.no_user_agent) {
+        (_, true) => None,
+        (Some(value), false) => Some(
+            HeaderValue::from_str(value).with_context(|| format!("Invalid --user-agent value {:?}", value))?,
+        ),
+        (None, false) => Some(HeaderValue::from_static(DEFAULT_USER_AGENT)),
+    };
+
+    // Ctrl+C 时停止发新请求并照常输出结果，再按一次直接退出
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let stop = stop.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            eprintln!("\nInterrupted, waiting for in-flight requests (Ctrl+C again to quit)");
+            stop.store(true, Ordering::Relaxed);
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(130);
+            }
         });
-        
-        handles.push(handle);
     }
 
-    // 等待所有线程完成
-    for handle in handles {
-        handle.await??;
+    if args.websocket {
+        return run_websocket(&args, headers, stop).await;
+    }
+    if let Some(depth) = args.pipeline {
+        headers.extend(user_agent.map(|value| (header::USER_AGENT, value)));
+        return run_pipeline(&args, depth as usize, headers, body, stop).await;
+    }
+    if args.server_push_accept || args.server_push_cancel {
+        headers.extend(user_agent.map(|value| (header::USER_AGENT, value)));
+        return run_server_push(&args, headers, body, stop).await;
+    }
+
+    // 请求日志由单独的任务写入，压测路径上不做磁盘 I/O
+    let (request_log, request_log_writer) = match args.request_log.as_ref().filter(|_| !args.dry_run) {
+        Some(path) => {
+            let (log, writer) = RequestLog::create(path, args.request_log_buffer_size)?;
+            (Some(log), Some(writer))
+        }
+        None => (None, None),
+    };
+    let (error_log, error_log_writer) = match args.error_log.as_ref().filter(|_| !args.dry_run) {
+        Some(path) => {
+            let (log, writer) = ErrorLog::create(path, args.request_log_buffer_size)?;
+            (Some(log), Some(writer))
+        }
+        None => (None, None),
+    };
+
+    if let Some(sockets) = args.socket_pool_size.filter(|&sockets| sockets < args.threads as u64) {
+        tracing::warn!(
+            "--socket-pool-size {} is smaller than -t {}; every thread opens at least one socket",
+            sockets,
+            args.threads
+        );
+    }
+    if let Some(size) = args.burst_size.filter(|&size| size > args.connections as u64) {
+        tracing::warn!(
+            "--burst-size {} is larger than -c {}; only {} requests of each burst are in flight at once",
+            size,
+            args.connections,
+            args.connections
+        );
+    }
+    let fast_open = args.tcp_fast_open
+        && match socket::fast_open_unavailable() {
+            Some(reason) => {
+                tracing::warn!("{}; ignoring --tcp-fast-open", reason);
+                false
+            }
+            None => true,
+        };
+    let mut config = BenchmarkConfig::with_urls(urls.clone())
+        .stop_flag(stop.clone())
+        .random_urls(args.url_file_random)
+        .threads(args.threads)
+        .connections(args.connections)
+        .duration(args.duration)
+        .max_requests(args.max_requests)
+        .target_rps(args.target_rps)
+        .max_connections(args.max_connections)
+        .abort_on_error_rate(args.abort_on_error_rate)
+        .error_window(Duration::from_secs(args.error_window))
+        .warmup(Duration::from_secs(args.warmup))
+        .timeout(Duration::from_secs(args.timeout))
+        .connect_timeout(args.connection_timeout.map(|secs| Duration::from_secs_f64(secs.max(0.0))))
+        .read_timeout(args.read_timeout.map(Duration::from_millis))
+        .expect_status(args.expect_status.clone())
+        .chunked_upload(args.chunk_size, args.chunk_delay_ms.map(Duration::from_millis))
+        .rampup(Duration::from_secs(args.rampup_duration))
+        .socket_pool_size(args.socket_pool_size.map(|sockets| sockets as usize))
+        .connections_per_second(args.connections_per_second)
+        .burst(args.burst_size.map(|size| size as usize), Duration::from_millis(args.burst_interval_ms))
+        .rate(args.rate)
+        .correct_latency(args.correct_latency)
+        .think_time(args.think_time.map(|ms| ThinkTime {
+            base: Duration::from_secs_f64(ms.max(0.0) / 1000.0),
+            jitter: Duration::from_secs_f64(args.think_time_jitter.max(0.0) / 1000.0),
+        }))
+        .jitter(args.jitter_ms.map(|ms| Jitter {
+            max: Duration::from_millis(ms),
+            distribution: args.jitter_distribution,
+        }))
+        .connection_configs(match &args.connections_file {
+            Some(path) => read_connections_file(path, args.connections)?,
+            None => Vec::new(),
+        })
+        .trace_context(
+            args.otel_trace
+                .then(|| TraceContext::new(args.otel_trace_id.as_deref(), args.otel_tracestate.as_deref()))
+                .transpose()?,
+        )
+        .script(args.script.as_deref().map(Script::load).transpose()?)
+        .retry((args.retry > 0).then(|| RetryPolicy {
+            max_retries: args.retry,
+            backoff: Duration::from_millis(args.retry_backoff_ms),
+            on_status: args.retry_on_status.clone(),
+        }))
+        .max_redirects(args.follow_redirects.then_some(args.max_redirects))
+        .cookies(CookieConfig {
+            jar: args.cookie_jar,
+            cookies: args.cookies.clone(),
+        })
+        .request_log(request_log)
+        .error_log(error_log)
+        .method(Method::from(args.method))
+        .headers(headers)
+        .assertions(BodyAssertions {
+            contains: args.assert_body_contains.clone(),
+            json_paths: args.assert_json_paths.clone(),
+        })
+        .body(body)
+        .http_version(match (args.http2, args.http2_prior_knowledge, args.disable_http2) {
+            (true, _, _) => HttpVersion::Http2,
+            (_, true, _) => HttpVersion::H2c,
+            (_, _, true) => HttpVersion::Http1,
+            _ => args.http_version,
+        })
+        .http2_options(Http2Options {
+            max_concurrent_streams: args.h2_max_concurrent_streams,
+            initial_stream_window_size: args.h2_initial_stream_window_size,
+            initial_connection_window_size: args.h2_initial_connection_window_size,
+        })
+        .keepalive(!args.no_keepalive)
+        .decompress(decompress)
+        .streaming(args.streaming)
+        .size_histograms(args.request_size_histogram)
+        .dump_response_headers(args.dump_response_headers)
+        .ntlm(args.ntlm.as_deref().map(|credentials| NtlmCredentials::parse(&read_secret(credentials)?)).transpose()?)
+        .aws_sigv4(aws_signer(&args)?)
+        .user_agent(user_agent)
+        .request_id_header(args.request_id_header.clone())
+        .keepalive_timeout(Duration::from_secs(args.keepalive_timeout))
+        .pool_max_idle_per_host(args.pool_max_idle_per_host)
+        .proxy(ProxyConfig::new(args.proxy.as_deref())?)
+        .unix_socket(args.unix_socket.clone())
+        .socket_options(SocketOptions {
+            nodelay: args.tcp_nodelay,
+            send_buffer: args.tcp_send_buffer,
+            recv_buffer: args.tcp_recv_buffer,
+            local_address: args.interface,
+            fast_open,
+        })
+        .resolver(resolver(&args))
+        .tls(tls_options(&args));
+    if let Some(template) = template {
+        config = config.body_template(template);
+    }
+    if !form.is_empty() {
+        config = config.multipart(form);
     }
 
+    if !args.parallel.is_empty() {
+        return run_parallel(&args, config).await;
+    }
+
+    // --config 时依次运行各个阶段，否则只有命令行这一组配置
+    let runs = match &args.config {
+        Some(path) => Config::load(path, urls.first().map(String::as_str))?
+            .phases
+            .iter()
+            .enumerate()
+            .map(|(i, phase)| {
+                Ok(Run {
+                    label: Some(phase.label(i)),
+                    target: phase.url.clone().unwrap_or_default(),
+                    threads: phase.threads.unwrap_or(args.threads),
+                    connections: phase.connections.unwrap_or(args.connections),
+                    duration: phase.duration.as_deref().map(parse_duration).transpose()?.unwrap_or(args.duration),
+                    config: phase.apply(config.clone())?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![Run {
+            label: None,
+            target,
+            threads: args.threads,
+            connections: args.connections,
+            duration: args.duration,
+            config,
+        }],
+    };
+
+    if args.dry_run {
+        for run in &runs {
+            if let Some(label) = &run.label {
+                println!("# Phase: {}", label);
+            }
+            print_request(&run.config.dry_run()?);
+        }
+        return Ok(());
+    }
+
+    // 每秒采样：进度输出和 CSV 时间序列，所有阶段共用
+    let stats = Statistics::new()
+        .with_percentiles(args.percentiles.clone())
+        .with_histogram_precision(args.histogram_precision);
+    let csv = match &args.csv {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create CSV file {}", path.display()))?;
+            let mut writer = BufWriter::new(file);
+            writeln!(writer, "timestamp_secs,requests_in_window,errors_in_window,rps,p50_ms,p95_ms,p99_ms,bytes_mb,active_connections")?;
+            writer.flush()?;
+            Some(writer)
+        }
+        None => None,
+    };
+    let influx = (args.influxdb_output.is_some() || args.influxdb_push.is_some()).then(|| {
+        let url = if urls.len() == 1 { urls[0].as_str() } else { "all" };
+        InfluxLines::new(url, &Method::from(args.method), &args.labels)
+    });
+    let progress = !args.machine_output();
+    let sampler = (progress || csv.is_some() || influx.is_some())
+        .then(|| spawn_sampler(stats.clone(), progress, csv, influx.clone()));
+
+    // 内核的 TFO 计数是全局的，取测试前后的差值
+    let fast_open_before = fast_open.then(socket::fast_open_connections).flatten();
+    let mut phases = Vec::with_capacity(runs.len());
+    for (i, run) in runs.iter().enumerate() {
+        if i > 0 {
+            if stop.load(Ordering::Relaxed) {
+                break;
+            }
+            stats.reset();
+        }
+        if !args.machine_output() {
+            print_banner(&args, run);
+        }
+        let result = run.config.run_with(stats.clone()).await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                if let Some(sampler) = &sampler {
+                    sampler.abort();
+                }
+                return Err(e);
+            }
+        };
+        let aborted = result.aborted.is_some();
+        phases.push((run.label.clone().unwrap_or_default(), result));
+        if aborted {
+            break;
+        }
+    }
+    if let Some(sampler) = sampler {
+        sampler.abort();
+    }
+    let fast_open_connections = fast_open_before
+        .zip(socket::fast_open_connections())
+        .map(|(before, after)| after.saturating_sub(before));
+    if let Some(delay) = args.post_delay {
+        if !args.machine_output() {
+            println!("Waiting {:?} for in-flight processing...", delay);
+        }
+        tokio::time::sleep(delay).await;
+    }
+    let request_log = match request_log_writer {
+        Some(writer) => Some(writer.finish().await?),
+        None => None,
+    };
+    if let Some(summary) = &request_log {
+        if summary.dropped > 0 {
+            tracing::warn!(
+                "Request log dropped {} records because the writer couldn't keep up",
+                summary.dropped
+            );
+        }
+    }
+    if let Some(writer) = error_log_writer {
+        let summary = writer.finish().await?;
+        if summary.dropped > 0 {
+            tracing::warn!("Error log dropped {} records because the writer couldn't keep up", summary.dropped);
+        }
+    }
+    let mut result = phases[0].1.clone();
+    for (_, phase) in &phases[1..] {
+        result.merge(phase);
+    }
+
+    if let Some(path) = &args.output_histogram {
+        compare::save_histogram(&result.histogram, path)?;
+    }
+    if let Some(prefix) = &args.output_gnuplot {
+        gnuplot::write_files(prefix, &result.histogram, &result.timeline)?;
+    }
+    if let Some(path) = &args.save_result {
+        result.save(path)?;
+    }
+    if let Some(influx) = &influx {
+        let data = influx.render();
+        if let Some(path) = &args.influxdb_output {
+            influxdb::write_file(path, &data)?;
+        }
+        if let Some(url) = &args.influxdb_push {
+            let target = InfluxDb {
+                url: url.clone(),
+                token: args.influxdb_token.as_deref().map(read_secret).transpose()?,
+                org: args.influxdb_org.clone(),
+                bucket: args.influxdb_bucket.clone(),
+            };
+            target.push(data).await?;
+        }
+    }
+    let reports = url_reports(&runs, &phases);
+    if args.prometheus_output.is_some() || args.prometheus_push.is_some() {
+        let metrics = prometheus::render(&result, &reports, &Method::from(args.method), &args.labels);
+        if let Some(path) = &args.prometheus_output {
+            prometheus::write_file(path, &metrics)?;
+        }
+        if let Some(url) = &args.prometheus_push {
+            prometheus::push(url, metrics).await?;
+        }
+    }
+
+    // 输出汇总结果
+    let mut report = result.report();
+    let resources = ResourceUsage::collect();
+    if resources.near_fd_limit() {
+        tracing::warn!(
+            "{} of {} file descriptors in use; raise the limit (ulimit -n) if requests fail with \"Too many open files\"",
+            resources.open_fds.unwrap_or_default(),
+            resources.fd_limit.unwrap_or_default()
+        );
+    }
+    report.resources = Some(resources);
+    if let Some(path) = &args.sla_file {
+        report.sla_results = check_sla_file(path, &reports)?;
+    }
+    if result.overrange_values > 0 {
+        tracing::warn!(
+            "{} latency values exceeded the histogram range and were recorded at its maximum; the highest percentiles are underestimated",
+            result.overrange_values
+        );
+    }
+    let format = args.output_format();
+    if format == OutputFormat::Json {
+        print_json(&report, args.latency_unit);
+    } else if format == OutputFormat::Csv {
+        print_summary_csv(&report, args.latency_unit);
+    } else {
+        if format == OutputFormat::Table {
+            print_summary_table(&report, args.latency_unit);
+        } else {
+            print_stats(&report, args.latency_unit);
+        }
+        if args.histogram {
+            print_latency_histogram(&result.histogram);
+        }
+        print_url_breakdown(&result);
+        print_sla_results(&report.sla_results);
+        if args.connect_reuse_report {
+            print_connection_reuse(&report);
+        }
+        if args.http_version_report {
+            print_connection_protocols(&report);
+        }
+        if args.verbose {
+            print_worker_breakdown(&result);
+            print_socket_options(&args, &report, fast_open, fast_open_connections);
+            print_http2_options(&args, &report);
+        }
+        if let Some(resources) = &report.resources {
+            print_resources(resources);
+        }
+        if args.runtime_metrics {
+            print_runtime_metrics(report.duration_secs);
+        }
+        if let (Some(path), Some(summary)) = (&args.request_log, &request_log) {
+            println!(
+                "Request Log: {} bytes written to {} ({} records dropped)",
+                summary.bytes_written,
+                path.display(),
+                summary.dropped
+            );
+        }
+        if args.config.is_some() {
+            print_phase_breakdown(&phases);
+        }
+        if args.rate > 0 {
+            println!("Rate: {} req/s configured, {:.2} req/s achieved", args.rate, report.rps);
+        }
+        if let (Some(target), Some(concurrency)) = (args.target_rps, result.final_concurrency) {
+            println!(
+                "Target RPS: {} req/s configured, {:.2} req/s achieved with {} concurrent connections",
+                target, report.rps, concurrency
+            );
+        }
+    }
+
+    let regressions = check_baseline(&args, &report)?;
+
+    if let (Some(error_rate), Some(threshold)) = (result.aborted, args.abort_on_error_rate) {
+        eprintln!("\nTest aborted: error rate {:.2}% exceeded threshold {:.2}%", error_rate, threshold);
+        std::process::exit(1);
+    }
+
+    // 检查 SLA 阈值
+    exit_on_sla_failures(&check_sla(&args, &report));
+
+    exit_on_regressions(&args, &regressions);
     Ok(())
-} 
\ No newline at end of file
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use hyper::header::HeaderValue;
+use rand::Rng;
+use std::path::{Path, PathBuf};
+
+/// A `multipart/form-data` body made of text fields and file uploads.
+///
+/// Forms with only text fields are encoded once per connection; forms with
+/// files are re-encoded for every request so changes on disk are picked up.
+#[derive(Clone, Debug, Default)]
+pub struct MultipartForm {
+    fields: Vec<(String, String)>,
+    files: Vec<(String, PathBuf)>,
+    encoded: Option<(Bytes, HeaderValue)>,
+}
+
+impl MultipartForm {
+    pub fn new(fields: Vec<(String, String)>, files: Vec<(String, PathBuf)>) -> Self {
+        MultipartForm {
+            fields,
+            files,
+            encoded: None,
+        }
+    }
+
+    /// Parse a `name=value` text field given on the command line.
+    pub fn parse_field(field: &str) -> Result<(String, String)> {
+        let (name, value) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid form field {:?}: expected \"name=value\"", field))?;
+        if name.is_empty() {
+            return Err(anyhow!("Invalid form field {:?}: empty name", field));
+        }
+        Ok((name.to_string(), value.to_string()))
+    }
+
+    /// Parse a `name=@path` file field given on the command line.
+    pub fn parse_file(field: &str) -> Result<(String, PathBuf)> {
+        let (name, path) = field
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid form file {:?}: expected \"name=@path\"", field))?;
+        let path = path.strip_prefix('@').unwrap_or(path);
+        if name.is_empty() || path.is_empty() {
+            return Err(anyhow!("Invalid form file {:?}: expected \"name=@path\"", field));
+        }
+        Ok((name.to_string(), PathBuf::from(path)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty() && self.files.is_empty()
+    }
+
+    /// Copy for one connection task, pre-encoded when there are no files
+    pub fn for_connection(&self) -> Result<Self> {
+        let mut form = self.clone();
+        if form.files.is_empty() && form.encoded.is_none() {
+            form.encoded = Some(form.build()?);
+        }
+        Ok(form)
+    }
+
+    /// The body and its `Content-Type` header
+    pub fn encode(&self) -> Result<(Bytes, HeaderValue)> {
+        match &self.encoded {
+            Some(encoded) => Ok(encoded.clone()),
+            None => self.build(),
+        }
+    }
+
+    fn build(&self) -> Result<(Bytes, HeaderValue)> {
+        // 每次编码都用新的随机分隔符
+        let boundary = format!("rustwrk-{:032x}", rand::thread_rng().gen::<u128>());
+        let mut body = BytesMut::new();
+        for (name, value) in &self.fields {
+            part_header(&mut body, &boundary, name, None);
+            body.put_slice(value.as_bytes());
+            body.put_slice(b"\r\n");
+        }
+        for (name, path) in &self.files {
+            let contents = std::fs::read(path)
+                .with_context(|| format!("Failed to read form file {}", path.display()))?;
+            part_header(&mut body, &boundary, name, Some(path));
+            body.put_slice(&contents);
+            body.put_slice(b"\r\n");
+        }
+        body.put_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+        let content_type = HeaderValue::from_str(&format!("multipart/form-data; boundary={}", boundary))
+            .expect("boundary is always a valid header value");
+        Ok((body.freeze(), content_type))
+    }
+}
+
+fn part_header(body: &mut BytesMut, boundary: &str, name: &str, file: Option<&Path>) {
+    body.put_slice(format!("--{}\r\n", boundary).as_bytes());
+    body.put_slice(format!("Content-Disposition: form-data; name=\"{}\"", quote(name)).as_bytes());
+    if let Some(path) = file {
+        let filename = path.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        body.put_slice(format!("; filename=\"{}\"\r\n", quote(&filename)).as_bytes());
+        body.put_slice(b"Content-Type: application/octet-stream\r\n");
+    } else {
+        body.put_slice(b"\r\n");
+    }
+    body.put_slice(b"\r\n");
+}
+
+// 引号和换行按 HTML 表单的方式转义
+fn quote(value: &str) -> String {
+    value.replace('"', "%22").replace('\r', "%0D").replace('\n', "%0A")
+}
@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hyper::header::{HeaderMap, HeaderValue, WWW_AUTHENTICATE};
+use ntlmclient::{Credentials, Flags, Message, NegotiateMessage};
+
+/// Workstation name sent in NTLM messages
+const WORKSTATION: &str = "RUSTWRK";
+
+/// Domain credentials for NTLM authentication (--ntlm). NTLM authenticates a
+/// connection rather than a request, so each connection performs the
+/// handshake once and later requests on it go out without credentials.
+#[derive(Clone, Debug)]
+pub struct NtlmCredentials {
+    credentials: Credentials,
+}
+
+impl NtlmCredentials {
+    /// Parse `DOMAIN\user:password`; the domain may be left out
+    pub fn parse(value: &str) -> Result<Self> {
+        let (account, password) = value
+            .split_once(':')
+            .ok_or_else(|| anyhow!("--ntlm expects \"DOMAIN\\user:password\""))?;
+        let (domain, username) = account.split_once('\\').unwrap_or(("", account));
+        if username.is_empty() {
+            return Err(anyhow!("--ntlm credentials have no user name"));
+        }
+        Ok(NtlmCredentials {
+            credentials: Credentials {
+                username: username.to_string(),
+                password: password.to_string(),
+                domain: domain.to_string(),
+            },
+        })
+    }
+
+    /// Authorization value carrying the type 1 (negotiate) message
+    pub fn negotiate(&self) -> Result<HeaderValue> {
+        let message = Message::Negotiate(NegotiateMessage {
+            flags: Flags::NEGOTIATE_UNICODE
+                | Flags::REQUEST_TARGET
+                | Flags::NEGOTIATE_NTLM
+                | Flags::NEGOTIATE_WORKSTATION_SUPPLIED,
+            supplied_domain: String::new(),
+            supplied_workstation: WORKSTATION.to_string(),
+            os_version: Default::default(),
+        });
+        authorization(&message)
+    }
+
+    /// Authorization value carrying the type 3 (authenticate) message that
+    /// answers the type 2 challenge in a 401 response's headers
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<HeaderValue> {
+        let challenge = challenge(headers).ok_or_else(|| anyhow!("401 response has no NTLM challenge"))?;
+        let challenge = STANDARD.decode(challenge).context("Invalid base64 in NTLM challenge")?;
+        let challenge = match Message::try_from(challenge.as_slice()) {
+            Ok(Message::Challenge(challenge)) => challenge,
+            Ok(other) => return Err(anyhow!("Expected an NTLM challenge, got message type {}", other.message_number())),
+            Err(e) => return Err(e).context("Invalid NTLM challenge"),
+        };
+        let target_info: Vec<u8> = challenge.target_information.iter().flat_map(|entry| entry.to_bytes()).collect();
+        let response = ntlmclient::respond_challenge_ntlm_v2(
+            challenge.challenge,
+            &target_info,
+            ntlmclient::get_ntlm_time(),
+            &self.credentials,
+        );
+        let message = response.to_message(
+            &self.credentials,
+            WORKSTATION,
+            Flags::NEGOTIATE_UNICODE | Flags::NEGOTIATE_NTLM,
+        );
+        authorization(&message)
+    }
+}
+
+/// Whether a 401 response offers NTLM authentication
+pub fn offered(headers: &HeaderMap) -> bool {
+    scheme_values(headers).next().is_some()
+}
+
+// 服务器可能同时提供 Negotiate 和 NTLM，只取 NTLM 的值
+fn scheme_values(headers: &HeaderMap) -> impl Iterator<Item = &str> {
+    headers.get_all(WWW_AUTHENTICATE).iter().filter_map(|value| {
+        let value = value.to_str().ok()?.trim();
+        match value.split_once(' ') {
+            Some((scheme, rest)) if scheme.eq_ignore_ascii_case("NTLM") => Some(rest.trim()),
+            None if value.eq_ignore_ascii_case("NTLM") => Some(""),
+            _ => None,
+        }
+    })
+}
+
+fn challenge(headers: &HeaderMap) -> Option<&str> {
+    scheme_values(headers).find(|value| !value.is_empty())
+}
+
+fn authorization(message: &Message) -> Result<HeaderValue> {
+    let bytes = message.to_bytes().context("Failed to encode NTLM message")?;
+    let mut value = HeaderValue::from_str(&format!("NTLM {}", STANDARD.encode(bytes)))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
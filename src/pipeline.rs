@@ -0,0 +1,454 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use hdrhistogram::Histogram;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::Method;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::time;
+
+use crate::connect::{RawTarget, RunWindow};
+use crate::resolve::Resolver;
+use crate::tls::TlsOptions;
+
+// 状态行和单个响应头的长度上限
+const MAX_LINE: usize = 16 * 1024;
+
+/// HTTP/1.1 pipelining benchmark: every connection writes a burst of `depth`
+/// requests and then reads the `depth` responses in order. hyper does not
+/// pipeline, so requests are written and responses parsed directly on the
+/// socket.
+#[derive(Clone, Debug)]
+pub struct PipelineConfig {
+    pub url: String,
+    pub method: Method,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Bytes,
+    pub connections: usize,
+    /// Requests sent per burst on each connection
+    pub depth: usize,
+    pub duration: Duration,
+    pub warmup: Duration,
+    /// Limit for connecting, writing a burst and reading each response
+    pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub resolver: Resolver,
+    pub stop: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct PipelineStats {
+    sent: AtomicU64,
+    received: AtomicU64,
+    errors: AtomicU64,
+    bytes: AtomicU64,
+    status_codes: Mutex<BTreeMap<u16, u64>>,
+    histogram: Mutex<Histogram<u64>>,
+}
+
+/// Request counts and per-request latencies, excluding the warmup period.
+#[derive(Clone, Debug)]
+pub struct PipelineResult {
+    pub requests_sent: u64,
+    pub responses: u64,
+    /// Failed connects and writes, malformed responses, timeouts and requests
+    /// still in flight when the server closed the connection
+    pub errors: u64,
+    /// Response body bytes
+    pub bytes: u64,
+    pub status_codes: BTreeMap<u16, u64>,
+    pub duration: Duration,
+    pub histogram: Histogram<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct PipelineReport {
+    pub depth: usize,
+    pub requests_sent: u64,
+    pub responses: u64,
+    pub errors: u64,
+    pub bytes: u64,
+    pub status_codes: BTreeMap<u16, u64>,
+    pub duration_secs: f64,
+    pub requests_per_sec: f64,
+    pub latency_mean_ms: f64,
+    pub latency_min_ms: f64,
+    pub latency_max_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+}
+
+impl PipelineResult {
+    pub fn report(&self, depth: usize) -> PipelineReport {
+        let ms = |value: u64| value as f64 / 1000.0;
+        let secs = self.duration.as_secs_f64();
+        PipelineReport {
+            depth,
+            requests_sent: self.requests_sent,
+            responses: self.responses,
+            errors: self.errors,
+            bytes: self.bytes,
+            status_codes: self.status_codes.clone(),
+            duration_secs: secs,
+            requests_per_sec: if secs > 0.0 { self.responses as f64 / secs } else { 0.0 },
+            latency_mean_ms: self.histogram.mean() / 1000.0,
+            latency_min_ms: ms(self.histogram.min()),
+            latency_max_ms: ms(self.histogram.max()),
+            latency_p50_ms: ms(self.histogram.value_at_quantile(0.5)),
+            latency_p95_ms: ms(self.histogram.value_at_quantile(0.95)),
+            latency_p99_ms: ms(self.histogram.value_at_quantile(0.99)),
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub async fn run(self) -> Result<PipelineResult> {
+        if self.depth == 0 {
+            return Err(anyhow!("--pipeline must be at least 1"));
+        }
+        // 只能用 HTTP/1.1，不协商 h2
+        let target = RawTarget::new(&self.url, ("http", "https"), &self.tls, &[b"http/1.1"], self.resolver.clone(), self.timeout)
+            .context("--pipeline needs an http:// or https:// URL")?;
+        let target = Arc::new(target);
+
+        // 一次突发的全部请求预先拼好，写的时候只有一次 write_all
+        let uri = target.uri();
+        let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+        let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+        let request = encode_request(&self.method, path, authority, &self.headers, &self.body);
+        let mut burst = BytesMut::with_capacity(request.len() * self.depth);
+        for _ in 0..self.depth {
+            burst.put_slice(&request);
+        }
+        let burst = burst.freeze();
+        let head = self.method == Method::HEAD;
+
+        let stats = Arc::new(PipelineStats {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            status_codes: Mutex::new(BTreeMap::new()),
+            histogram: Mutex::new(Histogram::<u64>::new(3).expect("Failed to create histogram")),
+        });
+
+        let window = RunWindow::new(self.warmup, self.duration, self.stop.clone());
+        let mut handles = Vec::with_capacity(self.connections);
+        for _ in 0..self.connections {
+            let connection = Connection {
+                target: target.clone(),
+                burst: burst.clone(),
+                depth: self.depth,
+                head,
+                timeout: self.timeout,
+                window: window.clone(),
+                stats: stats.clone(),
+            };
+            handles.push(tokio::spawn(async move {
+                while connection.window.running() {
+                    match connection.target.connect().await {
+                        Ok(opened) => connection.pipeline(opened.stream).await,
+                        Err(e) => connection.error(&e, 1),
+                    }
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.await?;
+        }
+
+        let status_codes = stats.status_codes.lock().unwrap().clone();
+        let histogram = stats.histogram.lock().unwrap().clone();
+        Ok(PipelineResult {
+            requests_sent: stats.sent.load(Ordering::Relaxed),
+            responses: stats.received.load(Ordering::Relaxed),
+            errors: stats.errors.load(Ordering::Relaxed),
+            bytes: stats.bytes.load(Ordering::Relaxed),
+            status_codes,
+            duration: Instant::now().saturating_duration_since(window.warmup_end),
+            histogram,
+        })
+    }
+}
+
+struct Connection {
+    target: Arc<RawTarget>,
+    burst: Bytes,
+    depth: usize,
+    head: bool,
+    timeout: Duration,
+    window: RunWindow,
+    stats: Arc<PipelineStats>,
+}
+
+impl Connection {
+    fn error(&self, e: &dyn std::fmt::Display, count: u64) {
+        tracing::error!("Pipeline error: {}", e);
+        if self.window.recording() {
+            self.stats.errors.fetch_add(count, Ordering::Relaxed);
+        }
+    }
+
+    /// Write bursts and read their responses until the run ends or the
+    /// connection fails
+    async fn pipeline<S: AsyncRead + AsyncWrite>(&self, stream: S) {
+        let (reader, mut writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        // 通道容量等于突发大小：只有上一批的发送时间全部被读端取走，才能写下一批
+        let (in_flight, mut sent) = mpsc::channel::<Instant>(self.depth);
+
+        let write = async move {
+            while self.window.running() {
+                let Ok(permits) = in_flight.reserve_many(self.depth).await else {
+                    // 读端已退出
+                    return;
+                };
+                let start = Instant::now();
+                match time::timeout(self.timeout, writer.write_all(&self.burst)).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => return self.error(&e, self.depth as u64),
+                    Err(_) => return self.error(&"write timeout", self.depth as u64),
+                }
+                if start >= self.window.warmup_end {
+                    self.stats.sent.fetch_add(self.depth as u64, Ordering::Relaxed);
+                }
+                for permit in permits {
+                    permit.send(start);
+                }
+            }
+        };
+
+        let read = async move {
+            while let Some(start) = sent.recv().await {
+                let recording = start >= self.window.warmup_end;
+                match time::timeout(self.timeout, read_response(&mut reader, self.head)).await {
+                    Ok(Ok(response)) => {
+                        if recording {
+                            let latency = start.elapsed().as_micros() as u64;
+                            self.stats.received.fetch_add(1, Ordering::Relaxed);
+                            self.stats.bytes.fetch_add(response.body_bytes, Ordering::Relaxed);
+                            *self.stats.status_codes.lock().unwrap().entry(response.status).or_insert(0) += 1;
+                            let _ = self.stats.histogram.lock().unwrap().record(latency.max(1));
+                        }
+                        if response.close {
+                            // 服务器关闭连接，这一批里还没读到的响应都不会来了
+                            let pending = sent.len() as u64;
+                            if pending > 0 {
+                                self.error(&"server closed the connection with requests in flight", pending);
+                            }
+                            return;
+                        }
+                    }
+                    Ok(Err(e)) => return self.error(&e, 1 + sent.len() as u64),
+                    Err(_) => return self.error(&"response timeout", 1 + sent.len() as u64),
+                }
+            }
+        };
+
+        tokio::join!(write, read);
+    }
+}
+
+fn encode_request(method: &Method, path: &str, authority: &str, headers: &[(HeaderName, HeaderValue)], body: &Bytes) -> Bytes {
+    let mut request = BytesMut::new();
+    request.put_slice(format!("{} {} HTTP/1.1\r\n", method, path).as_bytes());
+    if !headers.iter().any(|(name, _)| name == hyper::header::HOST) {
+        request.put_slice(format!("Host: {}\r\n", authority).as_bytes());
+    }
+    for (name, value) in headers {
+        // 长度由这里计算，分块编码在流水线里没有意义
+        if name == hyper::header::CONTENT_LENGTH || name == hyper::header::TRANSFER_ENCODING {
+            continue;
+        }
+        request.put_slice(name.as_str().as_bytes());
+        request.put_slice(b": ");
+        request.put_slice(value.as_bytes());
+        request.put_slice(b"\r\n");
+    }
+    if !body.is_empty() || matches!(*method, Method::POST | Method::PUT | Method::PATCH) {
+        request.put_slice(format!("Content-Length: {}\r\n", body.len()).as_bytes());
+    }
+    request.put_slice(b"\r\n");
+    request.put_slice(body);
+    request.freeze()
+}
+
+struct Response {
+    status: u16,
+    body_bytes: u64,
+    /// The server closes the connection after this response
+    close: bool,
+}
+
+async fn read_response<R: AsyncBufRead + Unpin>(reader: &mut R, head: bool) -> Result<Response> {
+    let mut line = Vec::new();
+    // 跳过 100 Continue 之类的中间响应
+    let status = loop {
+        let status_line = read_line(reader, &mut line).await?;
+        let status = status_line
+            .strip_prefix("HTTP/1.")
+            .and_then(|rest| rest.get(2..5))
+            .and_then(|code| code.parse::<u16>().ok())
+            .ok_or_else(|| anyhow!("Malformed status line {:?}", status_line))?;
+        if !(100..200).contains(&status) || status == 101 {
+            break status;
+        }
+        while !read_line(reader, &mut line).await?.is_empty() {}
+    };
+    let http10 = line.starts_with(b"HTTP/1.0");
+
+    let mut content_length = None;
+    let mut chunked = false;
+    let mut close = http10;
+    loop {
+        let header = read_line(reader, &mut line).await?;
+        if header.is_empty() {
+            break;
+        }
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(anyhow!("Malformed response header {:?}", header));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse::<u64>().with_context(|| format!("Invalid Content-Length {:?}", value))?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.to_ascii_lowercase().ends_with("chunked");
+        } else if name.eq_ignore_ascii_case("connection") {
+            let value = value.to_ascii_lowercase();
+            close = value.contains("close") || (http10 && !value.contains("keep-alive"));
+        }
+    }
+
+    let body_bytes = if head || status == 204 || status == 304 {
+        0
+    } else if chunked {
+        read_chunked(reader, &mut line).await?
+    } else if let Some(length) = content_length {
+        skip(reader, length).await?
+    } else {
+        // 没有长度信息时响应体到连接关闭为止
+        close = true;
+        tokio::io::copy(reader, &mut tokio::io::sink()).await?
+    };
+    Ok(Response { status, body_bytes, close })
+}
+
+async fn read_chunked<R: AsyncBufRead + Unpin>(reader: &mut R, line: &mut Vec<u8>) -> Result<u64> {
+    let mut total = 0;
+    loop {
+        let size_line = read_line(reader, line).await?;
+        let size = size_line.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16).with_context(|| format!("Invalid chunk size {:?}", size))?;
+        if size == 0 {
+            // 读掉 trailer 直到空行
+            while !read_line(reader, line).await?.is_empty() {}
+            return Ok(total);
+        }
+        total += skip(reader, size).await?;
+        if !read_line(reader, line).await?.is_empty() {
+            return Err(anyhow!("Missing CRLF after chunk"));
+        }
+    }
+}
+
+async fn skip<R: AsyncRead + Unpin>(reader: &mut R, length: u64) -> Result<u64> {
+    let copied = tokio::io::copy(&mut reader.take(length), &mut tokio::io::sink()).await?;
+    if copied < length {
+        return Err(anyhow!("Connection closed after {} of {} body bytes", copied, length));
+    }
+    Ok(copied)
+}
+
+/// Read one line without its CRLF into `line`, returned as text
+async fn read_line<'a, R: AsyncBufRead + Unpin>(reader: &mut R, line: &'a mut Vec<u8>) -> Result<&'a str> {
+    line.clear();
+    let n = reader.take(MAX_LINE as u64).read_until(b'\n', line).await?;
+    if n == 0 {
+        return Err(anyhow!("Connection closed by server"));
+    }
+    if !line.ends_with(b"\n") {
+        return Err(anyhow!("Response line longer than {} bytes", MAX_LINE));
+    }
+    while line.ends_with(b"\n") || line.ends_with(b"\r") {
+        line.pop();
+    }
+    std::str::from_utf8(line).context("Response line is not valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 依次解析 raw 中的全部响应，返回 (状态码, 响应体字节数, 是否关闭连接)
+    async fn responses(raw: &str, head: bool) -> Result<Vec<(u16, u64, bool)>> {
+        let mut reader = raw.as_bytes();
+        let mut parsed = Vec::new();
+        while !reader.is_empty() {
+            let response = read_response(&mut reader, head).await?;
+            parsed.push((response.status, response.body_bytes, response.close));
+        }
+        Ok(parsed)
+    }
+
+    #[tokio::test]
+    async fn skips_informational_responses() {
+        let raw = "HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 103 Early Hints\r\nLink: </a.css>\r\n\r\n\
+                   HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        assert_eq!(responses(raw, false).await.unwrap(), vec![(200, 5, false)]);
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_body_with_trailers() {
+        let raw = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+                   5;name=value\r\nhello\r\n6\r\n world\r\n0\r\nX-Checksum: abc\r\nX-Other: 1\r\n\r\n\
+                   HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n";
+        assert_eq!(responses(raw, false).await.unwrap(), vec![(200, 11, false), (404, 0, false)]);
+
+        let missing_crlf = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nabc\r\n0\r\n\r\n";
+        assert!(responses(missing_crlf, false).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn connection_close() {
+        let raw = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+        assert_eq!(responses(raw, false).await.unwrap(), vec![(200, 2, true)]);
+    }
+
+    #[tokio::test]
+    async fn http10_without_length_reads_to_close() {
+        let raw = "HTTP/1.0 200 OK\r\nServer: old\r\n\r\nbody until eof";
+        assert_eq!(responses(raw, false).await.unwrap(), vec![(200, 14, true)]);
+
+        let keep_alive = "HTTP/1.0 200 OK\r\nConnection: keep-alive\r\nContent-Length: 3\r\n\r\nabc";
+        assert_eq!(responses(keep_alive, false).await.unwrap(), vec![(200, 3, false)]);
+    }
+
+    #[tokio::test]
+    async fn responses_without_body() {
+        let head = "HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n";
+        assert_eq!(responses(head, true).await.unwrap(), vec![(200, 0, false), (200, 0, false)]);
+
+        let raw = "HTTP/1.1 204 No Content\r\n\r\nHTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n\
+                   HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\nx";
+        assert_eq!(responses(raw, false).await.unwrap(), vec![(204, 0, false), (304, 0, false), (200, 1, false)]);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_responses() {
+        for raw in [
+            "HTTP/2 200\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nno colon\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nshort",
+            "HTTP/1.1 200 OK\r\nContent-Length: 1\r\n",
+        ] {
+            assert!(responses(raw, false).await.is_err(), "{:?} should be rejected", raw);
+        }
+    }
+}
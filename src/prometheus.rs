@@ -0,0 +1,103 @@
+use anyhow::{anyhow, Context, Result};
+use hdrhistogram::Histogram;
+use hyper::header::{self, HeaderValue};
+use hyper::Method;
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::bench::BenchmarkResult;
+use crate::push;
+use crate::stats::Report;
+
+// 延迟直方图的桶上界（秒）
+const LATENCY_BUCKETS: [f64; 13] = [0.001, 0.002, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Parse a `key=value` label given on the command line.
+pub fn parse_label(label: &str) -> Result<(String, String)> {
+    let (name, value) = label
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Invalid label {:?}: expected \"key=value\"", label))?;
+    let valid = name.chars().enumerate().all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+    if name.is_empty() || !valid || name.starts_with("__") {
+        return Err(anyhow!("Invalid label name {:?}", name));
+    }
+    if matches!(name, "url" | "method") {
+        return Err(anyhow!("Label {:?} is set by rustwrk itself", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Render the results in Prometheus text exposition format.
+///
+/// Counters are labelled per URL from `reports`; the latency histogram covers
+/// all URLs and is labelled `url="all"` when more than one was benchmarked.
+pub fn render(result: &BenchmarkResult, reports: &[(String, Report)], method: &Method, labels: &[(String, String)]) -> String {
+    let targets: Vec<(&str, &Report)> = reports.iter().map(|(url, report)| (url.as_str(), report)).collect();
+    let label_set = |url: &str| {
+        let mut set = format!("url=\"{}\",method=\"{}\"", escape(url), escape(method.as_str()));
+        for (name, value) in labels {
+            let _ = write!(set, ",{}=\"{}\"", name, escape(value));
+        }
+        set
+    };
+
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, value: &dyn Fn(&Report) -> f64| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (url, report) in &targets {
+            let _ = writeln!(out, "{}{{{}}} {}", name, label_set(url), value(report));
+        }
+    };
+    metric("rustwrk_requests_total", "counter", "Requests completed after warmup", &|r| r.requests as f64);
+    metric("rustwrk_errors_total", "counter", "Requests that failed or returned a non-2xx status", &|r| r.errors as f64);
+    metric("rustwrk_bytes_total", "counter", "Response body bytes received", &|r| r.bytes_transferred as f64);
+    metric("rustwrk_rps", "gauge", "Average requests per second", &|r| r.rps);
+
+    let labels = label_set(if targets.len() > 1 { "all" } else { targets.first().map_or("", |(url, _)| *url) });
+    write_histogram(&mut out, "rustwrk_latency_seconds", &labels, &result.histogram);
+    out
+}
+
+fn write_histogram(out: &mut String, name: &str, labels: &str, histogram: &Histogram<u64>) {
+    let mut counts = [0u64; LATENCY_BUCKETS.len()];
+    for value in histogram.iter_recorded() {
+        let seconds = histogram.highest_equivalent(value.value_iterated_to()) as f64 / 1_000_000.0;
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|upper| seconds <= *upper) {
+            counts[bucket] += value.count_at_value();
+        }
+    }
+
+    let _ = writeln!(out, "# HELP {} Latency of successful requests", name);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    let mut cumulative = 0;
+    for (upper, count) in LATENCY_BUCKETS.iter().zip(counts) {
+        cumulative += count;
+        let _ = writeln!(out, "{}_bucket{{{},le=\"{}\"}} {}", name, labels, upper, cumulative);
+    }
+    let total = histogram.len();
+    let _ = writeln!(out, "{}_bucket{{{},le=\"+Inf\"}} {}", name, labels, total);
+    let _ = writeln!(out, "{}_sum{{{}}} {}", name, labels, histogram.mean() * total as f64 / 1_000_000.0);
+    let _ = writeln!(out, "{}_count{{{}}} {}", name, labels, total);
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+pub fn write_file(path: &Path, metrics: &str) -> Result<()> {
+    std::fs::write(path, metrics)
+        .with_context(|| format!("Failed to write Prometheus metrics to {}", path.display()))
+}
+
+/// POST the metrics to a Pushgateway; `/metrics/job/rustwrk` is appended unless
+/// the URL already names a job.
+pub async fn push(url: &str, metrics: String) -> Result<()> {
+    let url = if url.contains("/metrics/job/") {
+        url.to_string()
+    } else {
+        format!("{}/metrics/job/rustwrk", url.trim_end_matches('/'))
+    };
+    let headers = [(header::CONTENT_TYPE, HeaderValue::from_static("text/plain; version=0.0.4"))];
+    push::post("Pushgateway", &url, &headers, metrics).await
+}
@@ -0,0 +1,383 @@
+use anyhow::{anyhow, Context, Result};
+use hyper::rt::{Read, ReadBufCursor, Write};
+use hyper::Uri;
+use hyper_util::client::legacy::connect::{Connected, Connection, HttpConnector};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::ServerName;
+use std::future::Future;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+use tower_service::Service;
+
+use crate::resolve::Resolver;
+use crate::socket::{self, SocketInfo, SocketOptions};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Clone, Debug, Default)]
+pub struct ProxyConfig {
+    http: Option<Uri>,
+    https: Option<Uri>,
+}
+
+impl ProxyConfig {
+    /// 显式指定的代理优先，其次是 HTTP_PROXY / HTTPS_PROXY 环境变量
+    pub fn new(proxy: Option<&str>) -> Result<Self> {
+        if let Some(proxy) = proxy {
+            let uri = parse_proxy(proxy)?;
+            return Ok(ProxyConfig {
+                http: Some(uri.clone()),
+                https: Some(uri),
+            });
+        }
+
+        let from_env = |names: &[&str]| -> Result<Option<Uri>> {
+            match names.iter().find_map(|name| std::env::var(name).ok()) {
+                Some(value) if !value.is_empty() => parse_proxy(&value).map(Some),
+                _ => Ok(None),
+            }
+        };
+        Ok(ProxyConfig {
+            http: from_env(&["HTTP_PROXY", "http_proxy"])?,
+            https: from_env(&["HTTPS_PROXY", "https_proxy"])?,
+        })
+    }
+
+    fn for_target(&self, dst: &Uri) -> Option<&Uri> {
+        if dst.scheme_str() == Some("https") {
+            self.https.as_ref()
+        } else {
+            self.http.as_ref()
+        }
+    }
+}
+
+fn parse_proxy(proxy: &str) -> Result<Uri> {
+    let uri: Uri = proxy
+        .parse()
+        .with_context(|| format!("Invalid proxy URL {:?}", proxy))?;
+    match uri.scheme_str() {
+        Some("http") | Some("https") => {}
+        _ => return Err(anyhow!("Unsupported proxy scheme in {:?}: expected http:// or https://", proxy)),
+    }
+    if uri.host().is_none() {
+        return Err(anyhow!("Proxy URL {:?} has no host", proxy));
+    }
+    Ok(uri)
+}
+
+#[derive(Clone)]
+pub struct ProxyConnector {
+    http: HttpConnector,
+    tls: TlsConnector,
+    proxy: ProxyConfig,
+    unix_socket: Option<PathBuf>,
+    resolver: Resolver,
+    // 启用 TCP Fast Open 时不经过 HttpConnector，自己创建套接字
+    socket: SocketOptions,
+    connect_timeout: Option<Duration>,
+}
+
+impl ProxyConnector {
+    pub fn new(http: HttpConnector, proxy: ProxyConfig, tls: Arc<rustls::ClientConfig>) -> Self {
+        // 与代理之间的 TLS 不做 ALPN 协商
+        let mut config = (*tls).clone();
+        config.alpn_protocols.clear();
+        ProxyConnector {
+            http,
+            tls: TlsConnector::from(Arc::new(config)),
+            proxy,
+            unix_socket: None,
+            resolver: Resolver::default(),
+            socket: SocketOptions::default(),
+            connect_timeout: None,
+        }
+    }
+
+    /// Connect every request to this Unix domain socket instead of over TCP;
+    /// the URL's host is then only used for the Host header
+    pub fn unix_socket(mut self, path: Option<PathBuf>) -> Self {
+        self.unix_socket = path;
+        self
+    }
+
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Socket options for connections opened with TCP Fast Open; without
+    /// `fast_open` the HttpConnector's own settings are used
+    pub fn socket_options(mut self, socket: SocketOptions, connect_timeout: Option<Duration>) -> Self {
+        self.socket = socket;
+        self.connect_timeout = connect_timeout;
+        self
+    }
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = ProxyStream;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<ProxyStream, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let mut http = self.http.clone();
+        let tls = self.tls.clone();
+        let proxy = self.proxy.for_target(&dst).cloned();
+        let unix_socket = self.unix_socket.clone();
+        let resolver = self.resolver.clone();
+        let fast_open = self.socket.fast_open.then_some((self.socket, self.connect_timeout));
+
+        Box::pin(async move {
+            let started = Instant::now();
+            if let Some(path) = unix_socket {
+                let stream = connect_unix(&path).await?;
+                return Ok(ProxyStream::new(stream, false, started, Duration::ZERO, None));
+            }
+            let Some(proxy) = proxy else {
+                let (tcp, dns) = connect(&mut http, &resolver, &dst, fast_open).await?;
+                let socket = SocketInfo::read(&tcp).ok();
+                return Ok(ProxyStream::new(MaybeTlsStream::Plain(tcp), false, started, dns, socket));
+            };
+
+            let (tcp, dns) = connect(&mut http, &resolver, &proxy, fast_open).await?;
+            let socket = SocketInfo::read(&tcp).ok();
+            let mut stream = if proxy.scheme_str() == Some("https") {
+                let host = ServerName::try_from(proxy.host().unwrap_or_default().to_string())?;
+                MaybeTlsStream::Tls(Box::new(tls.connect(host, tcp).await?))
+            } else {
+                MaybeTlsStream::Plain(tcp)
+            };
+
+            // HTTPS 目标通过 CONNECT 建立隧道，HTTP 目标直接转发给代理
+            if dst.scheme_str() == Some("https") {
+                tunnel(&mut stream, &dst).await?;
+                Ok(ProxyStream::new(stream, false, started, dns, socket))
+            } else {
+                Ok(ProxyStream::new(stream, true, started, dns, socket))
+            }
+        })
+    }
+}
+
+// 自己解析域名以便单独统计 DNS 耗时，再按 IP 逐个尝试连接
+async fn connect(
+    http: &mut HttpConnector,
+    resolver: &Resolver,
+    uri: &Uri,
+    fast_open: Option<(SocketOptions, Option<Duration>)>,
+) -> Result<(TcpStream, Duration), BoxError> {
+    let host = uri.host().ok_or("URL has no host")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri
+        .port_u16()
+        .unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+
+    let started = Instant::now();
+    let addrs = resolver.resolve(host, port).await?;
+    let dns = started.elapsed();
+
+    let mut last_error: BoxError = format!("No addresses found for {}", host).into();
+    for addr in addrs {
+        if let Some((options, timeout)) = &fast_open {
+            match socket::connect_fast_open(addr, options, *timeout).await {
+                Ok(tcp) => return Ok((tcp, dns)),
+                Err(e) => last_error = e.into(),
+            }
+            continue;
+        }
+        let ip: Uri = format!("http://{}", addr).parse()?;
+        match http.call(ip).await {
+            Ok(tcp) => return Ok((tcp.into_inner(), dns)),
+            Err(e) => last_error = e.into(),
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &Path) -> Result<MaybeTlsStream, BoxError> {
+    let stream = tokio::net::UnixStream::connect(path)
+        .await
+        .map_err(|e| format!("Failed to connect to {}: {}", path.display(), e))?;
+    Ok(MaybeTlsStream::Unix(stream))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: &Path) -> Result<MaybeTlsStream, BoxError> {
+    Err("Unix domain sockets are not supported on this platform".into())
+}
+
+async fn tunnel(stream: &mut MaybeTlsStream, dst: &Uri) -> Result<(), BoxError> {
+    let host = dst.host().ok_or("Target URL has no host")?;
+    let port = dst.port_u16().unwrap_or(443);
+    let request = format!(
+        "CONNECT {host}:{port} HTTP/1.1\r\nHost: {host}:{port}\r\n\r\n",
+        host = host,
+        port = port
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut response = Vec::with_capacity(128);
+    let mut buf = [0u8; 512];
+    while !response.ends_with(b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            return Err("Proxy closed the connection during CONNECT".into());
+        }
+        response.extend_from_slice(&buf[..n]);
+        if response.len() > 8192 {
+            return Err("Proxy CONNECT response headers too large".into());
+        }
+    }
+
+    let status_line = String::from_utf8_lossy(&response);
+    let status_line = status_line.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(format!("Proxy CONNECT failed: {}", status_line).into());
+    }
+    Ok(())
+}
+
+/// A plain TCP, TLS or Unix domain socket stream.
+pub enum MaybeTlsStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixStream),
+}
+
+impl AsyncRead for MaybeTlsStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_read(cx, buf),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(unix)]
+            MaybeTlsStream::Unix(s) => Pin::new(s).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for MaybeTlsStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_write(cx, buf),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(unix)]
+            MaybeTlsStream::Unix(s) => Pin::new(s).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_flush(cx),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(unix)]
+            MaybeTlsStream::Unix(s) => Pin::new(s).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            MaybeTlsStream::Plain(s) => Pin::new(s).poll_shutdown(cx),
+            MaybeTlsStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(unix)]
+            MaybeTlsStream::Unix(s) => Pin::new(s).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Added to every response's extensions through the connection's extras, so
+/// responses can tell whether they arrived on a connection that was already used.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionUse(Arc<AtomicBool>);
+
+impl ConnectionUse {
+    /// False for the first response on a connection, true for every later one
+    pub fn reused(&self) -> bool {
+        self.0.swap(true, Ordering::Relaxed)
+    }
+}
+
+pub struct ProxyStream {
+    io: TokioIo<MaybeTlsStream>,
+    forwarded: bool,
+    uses: ConnectionUse,
+    dns: Duration,
+    tcp: Duration,
+    connected_at: Instant,
+    socket: Option<SocketInfo>,
+}
+
+impl ProxyStream {
+    fn new(stream: MaybeTlsStream, forwarded: bool, started: Instant, dns: Duration, socket: Option<SocketInfo>) -> Self {
+        // 经代理时 TCP 阶段也包含与代理的握手和 CONNECT
+        let connected_at = Instant::now();
+        ProxyStream {
+            io: TokioIo::new(stream),
+            forwarded,
+            uses: ConnectionUse::default(),
+            dns,
+            tcp: connected_at.duration_since(started).saturating_sub(dns),
+            connected_at,
+            socket,
+        }
+    }
+
+    pub fn dns_time(&self) -> Duration {
+        self.dns
+    }
+
+    pub fn tcp_time(&self) -> Duration {
+        self.tcp
+    }
+
+    /// Options of the TCP socket, `None` for Unix domain sockets
+    pub fn socket_info(&self) -> Option<SocketInfo> {
+        self.socket
+    }
+
+    /// When the TCP connection (or proxy tunnel) became ready
+    pub fn connected_at(&self) -> Instant {
+        self.connected_at
+    }
+}
+
+impl Connection for ProxyStream {
+    fn connected(&self) -> Connected {
+        Connected::new().proxy(self.forwarded).extra(self.uses.clone())
+    }
+}
+
+impl Read for ProxyStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: ReadBufCursor<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_read(cx, buf)
+    }
+}
+
+impl Write for ProxyStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().io).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().io).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{anyhow, Context, Result};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
+use hyper::header::{HeaderName, HeaderValue};
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client as HyperClient;
+use hyper_util::rt::TokioExecutor;
+
+use crate::tls::TlsOptions;
+
+type Client = HyperClient<hyper_rustls::HttpsConnector<HttpConnector>, Full<Bytes>>;
+
+fn client(tls: &TlsOptions) -> Result<Client> {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    let https = HttpsConnectorBuilder::new()
+        .with_tls_config(tls.client_config()?)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(http);
+    Ok(HyperClient::builder(TokioExecutor::new()).build(https))
+}
+
+/// POST exported results to a metrics backend; `service` names it in errors.
+pub async fn post(service: &str, url: &str, headers: &[(HeaderName, HeaderValue)], body: String) -> Result<()> {
+    let client = client(&TlsOptions::default())?;
+    let mut request = hyper::Request::post(url);
+    for (name, value) in headers {
+        request = request.header(name, value);
+    }
+    let request = request
+        .body(Full::new(Bytes::from(body)))
+        .with_context(|| format!("Invalid {} URL {:?}", service, url))?;
+
+    let response = client
+        .request(request)
+        .await
+        .with_context(|| format!("Failed to push metrics to {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.into_body().collect().await.map(|b| b.to_bytes()).unwrap_or_default();
+        return Err(anyhow!("{} {} returned {}: {}", service, url, status, String::from_utf8_lossy(&body).trim()));
+    }
+    Ok(())
+}
+
+/// GET a request body (--body-from-url), failing on a non-2xx status, an
+/// empty body or one larger than `max_size` bytes.
+pub async fn fetch_body(url: &str, max_size: usize, tls: &TlsOptions) -> Result<Bytes> {
+    let request = hyper::Request::get(url)
+        .body(Full::new(Bytes::new()))
+        .with_context(|| format!("Invalid --body-from-url {:?}", url))?;
+    let response = client(tls)?
+        .request(request)
+        .await
+        .with_context(|| format!("Failed to fetch request body from {}", url))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("Fetching request body from {} returned {}", url, status));
+    }
+    let body = Limited::new(response.into_body(), max_size)
+        .collect()
+        .await
+        .map_err(|e| match e.downcast_ref::<LengthLimitError>() {
+            Some(_) => anyhow!("Request body at {} is larger than --body-max-size ({} bytes)", url, max_size),
+            None => anyhow!("Failed to read request body from {}: {}", url, e),
+        })?
+        .to_bytes();
+    if body.is_empty() {
+        return Err(anyhow!("Request body fetched from {} is empty", url));
+    }
+    Ok(body)
+}
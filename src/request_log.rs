@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use std::fs::File;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+/// Records buffered between the workers and the writer task
+const CHANNEL_CAPACITY: usize = 64 * 1024;
+
+/// A row of a CSV log written by [`RequestLog`].
+pub trait LogRecord: Send + 'static {
+    /// Used in error messages, e.g. "request log"
+    const NAME: &'static str;
+    const HEADER: &'static str;
+
+    fn to_csv(&self) -> String;
+}
+
+/// One line of the request log.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestRecord {
+    /// Wall-clock start of the request, in microseconds since the Unix epoch
+    pub timestamp_us: u64,
+    pub latency_us: u64,
+    pub status: Option<u16>,
+    pub bytes: u64,
+    /// Empty for successful requests
+    pub error: &'static str,
+}
+
+impl RequestRecord {
+    pub fn new(latency: Duration, status: Option<u16>, bytes: u64, error: &'static str) -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        RequestRecord {
+            timestamp_us: now.saturating_sub(latency).as_micros() as u64,
+            latency_us: latency.as_micros() as u64,
+            status,
+            bytes,
+            error,
+        }
+    }
+}
+
+impl LogRecord for RequestRecord {
+    const NAME: &'static str = "request log";
+    const HEADER: &'static str = "timestamp_us,latency_us,status,bytes,error\n";
+
+    fn to_csv(&self) -> String {
+        let status = self.status.map(|s| s.to_string()).unwrap_or_default();
+        format!("{},{},{},{},{}\n", self.timestamp_us, self.latency_us, status, self.bytes, self.error)
+    }
+}
+
+/// One line of the error log (--error-log), written for every failed request.
+#[derive(Clone, Debug)]
+pub struct ErrorRecord {
+    /// Wall-clock start of the request
+    pub timestamp: SystemTime,
+    pub url: String,
+    /// Status code of non-2xx responses, otherwise the error type
+    pub error: String,
+    pub latency: Duration,
+    /// `x-request-id` or `traceparent` sent with the request, if any
+    pub request_id: Option<String>,
+}
+
+impl ErrorRecord {
+    pub fn new(url: String, error: String, latency: Duration, request_id: Option<String>) -> Self {
+        ErrorRecord {
+            timestamp: SystemTime::now() - latency,
+            url,
+            error,
+            latency,
+            request_id,
+        }
+    }
+}
+
+impl LogRecord for ErrorRecord {
+    const NAME: &'static str = "error log";
+    const HEADER: &'static str = "timestamp,url,error,latency_ms,request_id\n";
+
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{:.3},{}\n",
+            iso8601(self.timestamp),
+            csv_field(&self.url),
+            csv_field(&self.error),
+            self.latency.as_secs_f64() * 1000.0,
+            csv_field(self.request_id.as_deref().unwrap_or_default())
+        )
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// UTC timestamp with millisecond precision, e.g. `2024-03-01T12:00:00.000Z`
+pub(crate) fn iso8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, secs_of_day) = (secs / 86400, secs % 86400);
+    // 按公历换算年月日（Howard Hinnant 的 civil_from_days 算法）
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Sending side of the request log, shared by all connection tasks. Records
+/// are dropped rather than blocking when the writer falls behind.
+#[derive(Clone, Debug)]
+pub struct RequestLog<R = RequestRecord> {
+    tx: mpsc::Sender<R>,
+    dropped: Arc<AtomicU64>,
+}
+
+/// Log of failed requests only, see [`ErrorRecord`].
+pub type ErrorLog = RequestLog<ErrorRecord>;
+
+impl<R: LogRecord> RequestLog<R> {
+    /// Create the log file and start the writer task
+    pub fn create(path: &Path, buffer_size: usize) -> Result<(RequestLog<R>, RequestLogWriter)> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create {} {}", R::NAME, path.display()))?;
+        let (tx, mut rx) = mpsc::channel::<R>(CHANNEL_CAPACITY);
+        let done = CancellationToken::new();
+
+        let handle = {
+            let done = done.clone();
+            let path = path.display().to_string();
+            tokio::spawn(async move {
+                let mut writer = BufWriter::with_capacity(buffer_size, tokio::fs::File::from_std(file));
+                let mut written = R::HEADER.len() as u64;
+                let result: std::io::Result<()> = async {
+                    writer.write_all(R::HEADER.as_bytes()).await?;
+                    loop {
+                        // 结束时关闭通道，写完已缓冲的记录
+                        let record = tokio::select! {
+                            record = rx.recv() => record,
+                            _ = done.cancelled() => {
+                                rx.close();
+                                rx.recv().await
+                            }
+                        };
+                        let Some(record) = record else { break };
+                        let line = record.to_csv();
+                        writer.write_all(line.as_bytes()).await?;
+                        written += line.len() as u64;
+                    }
+                    writer.flush().await
+                }
+                .await;
+                result.with_context(|| format!("Failed to write {} {}", R::NAME, path))?;
+                Ok(written)
+            })
+        };
+
+        let dropped = Arc::new(AtomicU64::new(0));
+        Ok((
+            RequestLog { tx, dropped: dropped.clone() },
+            RequestLogWriter { handle, done, dropped },
+        ))
+    }
+
+    pub fn record(&self, record: R) {
+        if self.tx.try_send(record).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Totals reported once the request log has been flushed.
+#[derive(Clone, Copy, Debug)]
+pub struct RequestLogSummary {
+    pub bytes_written: u64,
+    /// Records lost because the channel was full
+    pub dropped: u64,
+}
+
+/// Handle to the writer task of a [`RequestLog`].
+pub struct RequestLogWriter {
+    handle: JoinHandle<Result<u64>>,
+    done: CancellationToken,
+    dropped: Arc<AtomicU64>,
+}
+
+impl RequestLogWriter {
+    /// Write out the remaining records and close the file
+    pub async fn finish(self) -> Result<RequestLogSummary> {
+        self.done.cancel();
+        let bytes_written = self.handle.await??;
+        Ok(RequestLogSummary {
+            bytes_written,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        })
+    }
+}
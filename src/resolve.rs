@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+/// Which address families DNS results are restricted to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpFamily {
+    #[default]
+    Any,
+    V4,
+    V6,
+}
+
+/// A manual `host:port:addr` mapping, like curl's `--resolve`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolveOverride {
+    pub host: String,
+    pub port: u16,
+    pub addr: IpAddr,
+}
+
+impl ResolveOverride {
+    pub fn parse(value: &str) -> Result<Self> {
+        let invalid = || anyhow!("Invalid --resolve {:?}: expected host:port:addr", value);
+        let (host, rest) = value.split_once(':').ok_or_else(invalid)?;
+        let (port, addr) = rest.split_once(':').ok_or_else(invalid)?;
+        let port = port.parse().map_err(|_| invalid())?;
+        // IPv6 地址可以写成 [::1] 或 ::1
+        let addr = addr.trim_start_matches('[').trim_end_matches(']');
+        let addr = addr
+            .parse()
+            .map_err(|_| anyhow!("Invalid address {:?} in --resolve {:?}", addr, value))?;
+        if host.is_empty() {
+            return Err(invalid());
+        }
+        Ok(ResolveOverride {
+            host: host.to_ascii_lowercase(),
+            port,
+            addr,
+        })
+    }
+}
+
+/// Resolves hosts through the manual overrides first, then the system resolver.
+#[derive(Clone, Debug, Default)]
+pub struct Resolver {
+    family: IpFamily,
+    overrides: HashMap<(String, u16), IpAddr>,
+}
+
+impl Resolver {
+    pub fn new(family: IpFamily, overrides: Vec<ResolveOverride>) -> Self {
+        Resolver {
+            family,
+            overrides: overrides
+                .into_iter()
+                .map(|o| ((o.host, o.port), o.addr))
+                .collect(),
+        }
+    }
+
+    pub async fn resolve(&self, host: &str, port: u16) -> io::Result<Vec<SocketAddr>> {
+        if let Some(addr) = self.overrides.get(&(host.to_ascii_lowercase(), port)) {
+            return Ok(vec![SocketAddr::new(*addr, port)]);
+        }
+        let addrs: Vec<_> = tokio::net::lookup_host((host, port))
+            .await?
+            .filter(|addr| match self.family {
+                IpFamily::Any => true,
+                IpFamily::V4 => addr.is_ipv4(),
+                IpFamily::V6 => addr.is_ipv6(),
+            })
+            .collect();
+        if addrs.is_empty() {
+            let family = match self.family {
+                IpFamily::V4 => "IPv4 ",
+                IpFamily::V6 => "IPv6 ",
+                IpFamily::Any => "",
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No {}addresses found for {}", family, host),
+            ));
+        }
+        Ok(addrs)
+    }
+}
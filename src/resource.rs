@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+/// Fraction of the file descriptor limit above which the summary warns
+pub const FD_WARN_RATIO: f64 = 0.9;
+
+/// Memory and file descriptor usage of the rustwrk process; fields the
+/// platform can't report are `None`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub rss_mb: Option<f64>,
+    pub peak_rss_mb: Option<f64>,
+    pub open_fds: Option<u64>,
+    /// Soft RLIMIT_NOFILE
+    pub fd_limit: Option<u64>,
+}
+
+impl ResourceUsage {
+    pub fn collect() -> Self {
+        let (rss_mb, peak_rss_mb) = memory();
+        ResourceUsage {
+            rss_mb,
+            peak_rss_mb,
+            open_fds: open_fds(),
+            fd_limit: fd_limit(),
+        }
+    }
+
+    /// True when the open descriptors are within 90% of the limit
+    pub fn near_fd_limit(&self) -> bool {
+        match (self.open_fds, self.fd_limit) {
+            (Some(open), Some(limit)) if limit > 0 => open as f64 >= limit as f64 * FD_WARN_RATIO,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn memory() -> (Option<f64>, Option<f64>) {
+    let Ok(status) = std::fs::read_to_string("/proc/self/status") else {
+        return (None, None);
+    };
+    // 形如 "VmRSS:     12345 kB"
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|rest| rest.trim().trim_end_matches("kB").trim().parse::<f64>().ok())
+            .map(|kb| kb / 1024.0)
+    };
+    // VmHWM 是常驻内存的峰值，VmPeak 是虚拟内存的峰值
+    (field("VmRSS:"), field("VmHWM:"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory() -> (Option<f64>, Option<f64>) {
+    use sysinfo::{ProcessRefreshKind, System};
+
+    let Ok(pid) = sysinfo::get_current_pid() else {
+        return (None, None);
+    };
+    let mut system = System::new();
+    system.refresh_process_specifics(pid, ProcessRefreshKind::new().with_memory());
+    // sysinfo 只能拿到当前值，没有峰值
+    let rss = system.process(pid).map(|process| process.memory() as f64 / 1024.0 / 1024.0);
+    (rss, None)
+}
+
+#[cfg(unix)]
+fn open_fds() -> Option<u64> {
+    let dir = if cfg!(target_os = "linux") { "/proc/self/fd" } else { "/dev/fd" };
+    // 减去 read_dir 自己打开的描述符
+    let count = std::fs::read_dir(dir).ok()?.count() as u64;
+    Some(count.saturating_sub(1))
+}
+
+#[cfg(not(unix))]
+fn open_fds() -> Option<u64> {
+    None
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t 在不同平台上类型不同
+fn fd_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes to the struct we pass in
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 || limit.rlim_cur == libc::RLIM_INFINITY {
+        return None;
+    }
+    Some(limit.rlim_cur as u64)
+}
+
+#[cfg(not(unix))]
+fn fd_limit() -> Option<u64> {
+    None
+}
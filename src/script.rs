@@ -0,0 +1,190 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, Uri};
+use mlua::{Function, Lua, LuaOptions, StdLib, Table, Value};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// A Lua script with optional wrk-style hooks:
+///
+/// - `setup(thread)` runs once per worker thread; `thread.id` is its index
+/// - `request()` returns a table with any of `method`, `path`, `headers`, `body`
+/// - `response(status, headers, body)` returning `false` counts as an assertion failure
+///
+/// Scripts see a `wrk` table (`url`, `method`, `headers`) and a `counter` table
+/// whose numeric entries are summed across threads and reported at the end.
+#[derive(Clone, Debug)]
+pub struct Script {
+    name: String,
+    source: String,
+}
+
+impl Script {
+    pub fn load(path: &Path) -> Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read script {}", path.display()))?;
+        let script = Script {
+            name: path.display().to_string(),
+            source,
+        };
+        // 先编译一次，语法错误在测试开始前报告
+        Lua::new()
+            .load(script.source.as_str())
+            .set_name(script.name.as_str())
+            .into_function()
+            .with_context(|| format!("Failed to compile script {}", script.name))?;
+        Ok(script)
+    }
+
+    /// Create an independent Lua state for one worker thread and run its `setup` hook
+    pub fn instance(&self, thread: usize, url: &str, method: &Method, headers: &[(HeaderName, HeaderValue)]) -> Result<ScriptState> {
+        let lua = Lua::new_with(StdLib::ALL_SAFE, LuaOptions::default())?;
+        let (has_request, has_response) = {
+            let globals = lua.globals();
+            let wrk = lua.create_table()?;
+            wrk.set("url", url)?;
+            wrk.set("method", method.as_str())?;
+            wrk.set("headers", header_table(&lua, headers.iter().map(|(name, value)| (name, value)))?)?;
+            globals.set("wrk", wrk)?;
+            globals.set("counter", lua.create_table()?)?;
+
+            lua.load(self.source.as_str())
+                .set_name(self.name.as_str())
+                .exec()
+                .with_context(|| format!("Failed to run script {}", self.name))?;
+            if let Some(setup) = globals.get::<_, Option<Function>>("setup")? {
+                let thread_table = lua.create_table()?;
+                thread_table.set("id", thread)?;
+                setup
+                    .call::<_, ()>(thread_table)
+                    .with_context(|| format!("setup() failed in script {}", self.name))?;
+            }
+            (
+                globals.get::<_, Option<Function>>("request")?.is_some(),
+                globals.get::<_, Option<Function>>("response")?.is_some(),
+            )
+        };
+
+        Ok(ScriptState {
+            lua: Mutex::new(lua),
+            has_request,
+            has_response,
+        })
+    }
+}
+
+fn header_table<'lua, 'a>(lua: &'lua Lua, headers: impl Iterator<Item = (&'a HeaderName, &'a HeaderValue)>) -> Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    for (name, value) in headers {
+        table.set(name.as_str(), lua.create_string(value.as_bytes())?)?;
+    }
+    Ok(table)
+}
+
+/// Request fields returned by the script's `request()` hook; unset fields keep
+/// the values from the command line.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptRequest {
+    pub method: Option<Method>,
+    pub path: Option<String>,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Option<Bytes>,
+}
+
+impl ScriptRequest {
+    /// Resolve `path` against the target URL; absolute URLs are used as-is
+    pub fn uri(&self, target: &Uri) -> Result<Uri> {
+        let Some(path) = &self.path else {
+            return Ok(target.clone());
+        };
+        if path.starts_with("http://") || path.starts_with("https://") {
+            return path.parse().with_context(|| format!("Invalid URL {:?} from request()", path));
+        }
+        let scheme = target.scheme_str().unwrap_or("http");
+        let authority = target.authority().map_or("", |a| a.as_str());
+        let separator = if path.starts_with('/') { "" } else { "/" };
+        format!("{}://{}{}{}", scheme, authority, separator, path)
+            .parse()
+            .with_context(|| format!("Invalid path {:?} from request()", path))
+    }
+}
+
+/// The Lua state of one worker thread, shared by its connections.
+pub struct ScriptState {
+    lua: Mutex<Lua>,
+    has_request: bool,
+    has_response: bool,
+}
+
+impl ScriptState {
+    /// Call `request()`, or return `None` when the script doesn't define it
+    pub fn request(&self) -> Result<Option<ScriptRequest>> {
+        if !self.has_request {
+            return Ok(None);
+        }
+        let lua = self.lua.lock().unwrap();
+        let request: Function = lua.globals().get("request")?;
+        let Some(table) = request.call::<_, Option<Table>>(())? else {
+            return Ok(Some(ScriptRequest::default()));
+        };
+
+        let method = match table.get::<_, Option<String>>("method")? {
+            Some(method) => Some(
+                Method::from_bytes(method.to_uppercase().as_bytes())
+                    .with_context(|| format!("Invalid method {:?} from request()", method))?,
+            ),
+            None => None,
+        };
+        let mut headers = Vec::new();
+        if let Some(table) = table.get::<_, Option<Table>>("headers")? {
+            for pair in table.pairs::<String, mlua::String>() {
+                let (name, value) = pair?;
+                let name = HeaderName::from_bytes(name.as_bytes())
+                    .with_context(|| format!("Invalid header name {:?} from request()", name))?;
+                let value = HeaderValue::from_bytes(value.as_bytes())
+                    .with_context(|| format!("Invalid value for header {} from request()", name))?;
+                headers.push((name, value));
+            }
+        }
+        let request = ScriptRequest {
+            method,
+            path: table.get("path")?,
+            headers,
+            body: table
+                .get::<_, Option<mlua::String>>("body")?
+                .map(|body| Bytes::copy_from_slice(body.as_bytes())),
+        };
+        Ok(Some(request))
+    }
+
+    /// Call `response()`; `Ok(false)` means the script rejected the response
+    pub fn response(&self, status: u16, headers: &HeaderMap, body: &[u8]) -> Result<bool> {
+        if !self.has_response {
+            return Ok(true);
+        }
+        let lua = self.lua.lock().unwrap();
+        let response: Function = lua.globals().get("response")?;
+        let headers = header_table(&lua, headers.iter())?;
+        let result = response.call::<_, Value>((status, headers, lua.create_string(body)?))?;
+        Ok(!matches!(result, Value::Boolean(false)))
+    }
+
+    /// Numeric entries of the `counter` table
+    pub fn counters(&self) -> Result<BTreeMap<String, f64>> {
+        let lua = self.lua.lock().unwrap();
+        let mut counters = BTreeMap::new();
+        let Some(counter) = lua.globals().get::<_, Option<Table>>("counter")? else {
+            return Ok(counters);
+        };
+        for pair in counter.pairs::<String, Value>() {
+            match pair? {
+                (name, Value::Integer(value)) => counters.insert(name, value as f64),
+                (name, Value::Number(value)) => counters.insert(name, value),
+                _ => None,
+            };
+        }
+        Ok(counters)
+    }
+}
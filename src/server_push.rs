@@ -0,0 +1,331 @@
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use h2::client::{PushPromise, PushPromises};
+use h2::RecvStream;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Method, Request, Uri, Version};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time;
+
+use crate::connect::{self, RawTarget, RunWindow};
+use crate::resolve::Resolver;
+use crate::stats::Statistics;
+use crate::tls::TlsOptions;
+
+/// What to do with the resources a server pushes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PushMode {
+    /// Read every pushed response to the end
+    Accept,
+    /// Reset every pushed stream with RST_STREAM as soon as it is promised
+    Cancel,
+}
+
+/// HTTP/2 benchmark that enables server push: every connection sends
+/// requests one at a time and handles the PUSH_PROMISE frames that arrive with
+/// each response. hyper always disables push, so requests go through the h2
+/// crate directly, over TLS with ALPN or as h2c with prior knowledge.
+#[derive(Clone, Debug)]
+pub struct ServerPushConfig {
+    pub url: String,
+    pub method: Method,
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Bytes,
+    pub connections: usize,
+    pub duration: Duration,
+    pub warmup: Duration,
+    /// Limit for connecting and for each request, including its pushes
+    pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub resolver: Resolver,
+    pub mode: PushMode,
+    pub stop: Arc<AtomicBool>,
+}
+
+#[derive(Debug, Default)]
+struct PushStats {
+    promised: AtomicU64,
+    completed: AtomicU64,
+    failed: AtomicU64,
+    cancelled: AtomicU64,
+    bytes: AtomicU64,
+}
+
+/// Pushed resources received after warmup.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ServerPushReport {
+    /// PUSH_PROMISE frames received
+    pub resources: u64,
+    /// Pushed responses read to the end
+    pub completed: u64,
+    /// Pushed responses that were reset by the server or could not be read
+    pub failed: u64,
+    /// Pushed streams reset by --server-push-cancel
+    pub cancelled: u64,
+    /// Body bytes of the pushed responses
+    pub bytes: u64,
+}
+
+impl ServerPushConfig {
+    /// Run the benchmark, recording the requests into `stats` (reset when
+    /// the warmup ends), and return the push totals
+    pub async fn run(self, stats: Statistics) -> Result<ServerPushReport> {
+        let target = RawTarget::new(&self.url, ("http", "https"), &self.tls, &[b"h2"], self.resolver.clone(), self.timeout)
+            .context("Server push needs an http:// or https:// URL")?;
+
+        let push = Arc::new(PushStats::default());
+        let window = RunWindow::new(self.warmup, self.duration, self.stop.clone());
+        let reset = {
+            let stats = stats.clone();
+            let warmup_end = window.warmup_end;
+            tokio::spawn(async move {
+                time::sleep_until(warmup_end.into()).await;
+                stats.reset();
+            })
+        };
+
+        let mut handles = Vec::with_capacity(self.connections);
+        for _ in 0..self.connections {
+            let connection = Connection {
+                uri: target.uri().clone(),
+                method: self.method.clone(),
+                headers: self.headers.clone(),
+                body: self.body.clone(),
+                timeout: self.timeout,
+                mode: self.mode,
+                window: window.clone(),
+                stats: stats.clone(),
+                push: push.clone(),
+            };
+            let target = target.clone();
+            handles.push(tokio::spawn(async move {
+                connection.stats.connection_started();
+                while connection.window.running() {
+                    let opened = match target.connect().await {
+                        Ok(opened) => opened,
+                        Err(e) => {
+                            connection.error(&e);
+                            if connect::is_connect_timeout(e.as_ref()) && connection.window.recording() {
+                                connection.stats.record_connect_timeout();
+                            }
+                            continue;
+                        }
+                    };
+                    // 明文连接按 h2c 处理；TLS 连接必须通过 ALPN 协商到 h2
+                    if opened.tls_time.is_some() && opened.alpn_protocol() != Some(b"h2") {
+                        connection.error(&"server did not negotiate HTTP/2 with ALPN");
+                        time::sleep(connect::RECONNECT_DELAY).await;
+                        continue;
+                    }
+                    if connection.window.recording() {
+                        connection.stats.record_connection(opened.dns_time, opened.tcp_time, opened.tls_time, None);
+                        connection.stats.record_connection_protocol(true);
+                    }
+                    connection.requests(opened.stream).await;
+                }
+                connection.stats.connection_finished();
+            }));
+        }
+
+        for handle in handles {
+            handle.await?;
+        }
+        reset.abort();
+
+        Ok(ServerPushReport {
+            resources: push.promised.load(Ordering::Relaxed),
+            completed: push.completed.load(Ordering::Relaxed),
+            failed: push.failed.load(Ordering::Relaxed),
+            cancelled: push.cancelled.load(Ordering::Relaxed),
+            bytes: push.bytes.load(Ordering::Relaxed),
+        })
+    }
+}
+
+struct Connection {
+    uri: Uri,
+    method: Method,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    body: Bytes,
+    timeout: Duration,
+    mode: PushMode,
+    window: RunWindow,
+    stats: Statistics,
+    push: Arc<PushStats>,
+}
+
+impl Connection {
+    fn error(&self, e: &dyn std::fmt::Display) {
+        tracing::error!("Server push error: {}", e);
+        if self.window.recording() {
+            self.stats.record_request(false, 0, Duration::ZERO);
+        }
+    }
+
+    /// Send requests one after another until the run ends or the connection fails
+    async fn requests<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(&self, io: S) {
+        let handshake = h2::client::Builder::new().enable_push(true).handshake::<_, Bytes>(io).await;
+        let (mut client, connection) = match handshake {
+            Ok(handshake) => handshake,
+            Err(e) => return self.error(&e),
+        };
+        let driver = tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::debug!("HTTP/2 connection closed: {}", e);
+            }
+        });
+
+        while self.window.running() {
+            client = match client.ready().await {
+                Ok(client) => client,
+                Err(e) => {
+                    self.error(&e);
+                    break;
+                }
+            };
+            let start = Instant::now();
+            let recording = start >= self.window.warmup_end;
+            let mut request = Request::builder().method(self.method.clone()).uri(self.uri.clone());
+            for (name, value) in &self.headers {
+                request = request.header(name, value);
+            }
+            let request = match request.body(()) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.error(&e);
+                    break;
+                }
+            };
+            let (mut response, mut send) = match client.send_request(request, self.body.is_empty()) {
+                Ok(sent) => sent,
+                Err(e) => {
+                    self.error(&e);
+                    break;
+                }
+            };
+            if !self.body.is_empty() {
+                if let Err(e) = send.send_data(self.body.clone(), true) {
+                    self.error(&e);
+                    break;
+                }
+            }
+            // 推送要在等待响应之前取出，和主响应并行处理
+            let promises = response.push_promises();
+            let main = async {
+                let response = response.await?;
+                let ttfb = start.elapsed();
+                let status = response.status();
+                let (parts, body) = response.into_parts();
+                let bytes = read_body(body).await?;
+                Ok::<_, h2::Error>((status, parts.headers, bytes, ttfb, start.elapsed()))
+            };
+            let result = time::timeout(self.timeout, async { tokio::join!(main, self.pushes(promises, recording)).0 }).await;
+            match result {
+                Ok(Ok((status, headers, bytes, ttfb, latency))) => {
+                    if recording {
+                        if status.is_success() {
+                            self.stats.record_ttfb(ttfb);
+                        }
+                        self.stats.record_version(Version::HTTP_2);
+                        self.stats.record_status(status.as_u16());
+                        self.stats.record_header_bytes(&headers);
+                        self.stats.record_request(status.is_success(), bytes, latency);
+                    }
+                    if !status.is_success() {
+                        tracing::error!("HTTP error: {}", status);
+                    }
+                }
+                Ok(Err(e)) => {
+                    self.error(&e);
+                    break;
+                }
+                Err(_) => {
+                    tracing::error!("Request timeout");
+                    if recording {
+                        self.stats.record_read_timeout();
+                        self.stats.record_request(false, 0, Duration::ZERO);
+                    }
+                    break;
+                }
+            }
+        }
+        driver.abort();
+    }
+
+    /// Handle the pushes promised with one response; returns once the
+    /// response stream is closed and every accepted push has been read
+    async fn pushes(&self, mut promises: PushPromises, recording: bool) {
+        let count = |counter: &AtomicU64, n: u64| {
+            if recording {
+                counter.fetch_add(n, Ordering::Relaxed);
+            }
+        };
+        // 各推送流并发读取，避免其中一个占满连接级流控窗口
+        let mut bodies = FuturesUnordered::new();
+        loop {
+            tokio::select! {
+                promise = promises.push_promise() => match promise {
+                    Some(Ok(promise)) => {
+                        count(&self.push.promised, 1);
+                        match self.mode {
+                            // 丢弃推送的响应句柄时 h2 会发送 RST_STREAM(CANCEL)
+                            PushMode::Cancel => {
+                                drop(promise);
+                                count(&self.push.cancelled, 1);
+                            }
+                            PushMode::Accept => bodies.push(read_pushed(promise)),
+                        }
+                    }
+                    Some(Err(e)) => {
+                        tracing::error!("Push promise error: {}", e);
+                        count(&self.push.failed, 1);
+                        break;
+                    }
+                    None => break,
+                },
+                Some(pushed) = bodies.next(), if !bodies.is_empty() => self.record_pushed(pushed, recording),
+            }
+        }
+        while let Some(pushed) = bodies.next().await {
+            self.record_pushed(pushed, recording);
+        }
+    }
+
+    fn record_pushed(&self, pushed: Result<u64, h2::Error>, recording: bool) {
+        if !recording {
+            return;
+        }
+        match pushed {
+            Ok(bytes) => {
+                self.push.completed.fetch_add(1, Ordering::Relaxed);
+                self.push.bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::error!("Pushed stream failed: {}", e);
+                self.push.failed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+async fn read_pushed(promise: PushPromise) -> Result<u64, h2::Error> {
+    let (_, response) = promise.into_parts();
+    read_body(response.await?.into_body()).await
+}
+
+// 读完整个响应体并及时归还流控窗口
+async fn read_body(mut body: RecvStream) -> Result<u64, h2::Error> {
+    let mut bytes = 0;
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        bytes += chunk.len() as u64;
+        let _ = body.flow_control().release_capacity(chunk.len());
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,139 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::stats::Report;
+
+/// Per-URL thresholds loaded from a YAML file (--sla-file):
+///
+/// ```yaml
+/// - url_pattern: "*/api/v1/*"
+///   p99_ms: 50
+///   error_rate_pct: 0.1
+/// - url_pattern: "http://localhost:8080/health"
+///   p99_ms: 5
+/// ```
+///
+/// `*` matches any run of characters (including `/`) and `?` a single one.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SlaRule {
+    pub url_pattern: String,
+    #[serde(default)]
+    pub p99_ms: Option<f64>,
+    #[serde(default)]
+    pub error_rate_pct: Option<f64>,
+}
+
+/// Outcome of one rule, checked against every benchmarked URL it matches.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SlaResult {
+    pub url_pattern: String,
+    pub urls: Vec<String>,
+    pub passed: bool,
+    /// One entry per URL and threshold that was exceeded
+    pub violations: Vec<String>,
+}
+
+impl SlaResult {
+    /// Whether any benchmarked URL matched the pattern
+    pub fn matched(&self) -> bool {
+        !self.urls.is_empty()
+    }
+}
+
+pub fn load(path: &Path) -> Result<Vec<SlaRule>> {
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read SLA file {}", path.display()))?;
+    serde_yaml::from_str(&contents).with_context(|| format!("Failed to parse SLA file {}", path.display()))
+}
+
+/// Check each rule against the reports of the URLs it matches; a rule that
+/// matches no URL passes, see [`SlaResult::matched`]
+pub fn check(rules: &[SlaRule], reports: &[(String, Report)]) -> Vec<SlaResult> {
+    rules
+        .iter()
+        .map(|rule| {
+            let mut urls = Vec::new();
+            let mut violations = Vec::new();
+            for (url, report) in reports.iter().filter(|(url, _)| glob_match(&rule.url_pattern, url)) {
+                // 多个阶段可能测同一个 URL
+                if !urls.contains(url) {
+                    urls.push(url.clone());
+                }
+                if let Some(limit) = rule.p99_ms {
+                    if report.latency_p99_ms > limit {
+                        violations.push(format!("{}: p99 latency {:.2}ms exceeds {:.2}ms", url, report.latency_p99_ms, limit));
+                    }
+                }
+                if let Some(limit) = rule.error_rate_pct {
+                    let error_rate = if report.requests > 0 {
+                        report.errors as f64 / report.requests as f64 * 100.0
+                    } else {
+                        0.0
+                    };
+                    if error_rate > limit {
+                        violations.push(format!("{}: error rate {:.2}% exceeds {:.2}%", url, error_rate, limit));
+                    }
+                }
+            }
+            SlaResult {
+                url_pattern: rule.url_pattern.clone(),
+                urls,
+                passed: violations.is_empty(),
+                violations,
+            }
+        })
+        .collect()
+}
+
+// 回溯匹配：记录最近一个 * 的位置，失配时让它多吞一个字符
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_match_wildcards() {
+        assert!(glob_match("*.*/api/v1/*", "http://example.com/api/v1/users"));
+        assert!(glob_match("*.*/api/v1/*", "https://a.b.example.com/api/v1/"));
+        assert!(!glob_match("*.*/api/v1/*", "http://localhost/api/v1/users"));
+        assert!(!glob_match("*.*/api/v1/*", "http://example.com/api/v2/users"));
+
+        assert!(glob_match("http://host:808?/", "http://host:8080/"));
+        assert!(!glob_match("http://host:808?/", "http://host:808/"));
+        assert!(!glob_match("http://host:808?/", "http://host:80800/"));
+
+        assert!(glob_match("http://host/*", "http://host/"));
+        assert!(glob_match("http://host/*", "http://host/a/b?c=d"));
+        assert!(glob_match("http://host/**", "http://host/"));
+        assert!(!glob_match("http://host/*", "http://host"));
+        assert!(!glob_match("http://host/", "http://host/a"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("", "x"));
+    }
+}
@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener};
+use std::time::Duration;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// TCP socket options applied to every benchmark connection.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SocketOptions {
+    /// Set TCP_NODELAY, disabling Nagle's algorithm
+    pub nodelay: bool,
+    /// Requested SO_SNDBUF, in bytes
+    pub send_buffer: Option<usize>,
+    /// Requested SO_RCVBUF, in bytes
+    pub recv_buffer: Option<usize>,
+    /// Source address to bind before connecting
+    pub local_address: Option<LocalAddress>,
+    /// Set TCP_FASTOPEN_CONNECT so the first request can ride in the SYN
+    pub fast_open: bool,
+}
+
+/// Source addresses for outgoing connections, from `--interface`. Connections
+/// to a target of the other address family are not bound.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LocalAddress {
+    pub ipv4: Option<Ipv4Addr>,
+    pub ipv6: Option<Ipv6Addr>,
+}
+
+impl LocalAddress {
+    /// Parse an IP address or look up the addresses of a network interface,
+    /// then check that they can actually be bound
+    pub fn parse(interface: &str) -> Result<Self> {
+        let addrs = match interface.parse::<IpAddr>() {
+            Ok(ip) => vec![ip],
+            Err(_) => interface_addresses(interface)?,
+        };
+        let local = LocalAddress {
+            ipv4: addrs.iter().find_map(|ip| match ip {
+                IpAddr::V4(ip) => Some(*ip),
+                IpAddr::V6(_) => None,
+            }),
+            // 链路本地地址需要 scope id 才能绑定，跳过
+            ipv6: addrs.iter().find_map(|ip| match ip {
+                IpAddr::V6(ip) if !ip.is_unicast_link_local() => Some(*ip),
+                _ => None,
+            }),
+        };
+        if local.ipv4.is_none() && local.ipv6.is_none() {
+            return Err(anyhow!("Network interface {:?} has no usable IPv4 or IPv6 address", interface));
+        }
+        for ip in local.ipv4.map(IpAddr::V4).into_iter().chain(local.ipv6.map(IpAddr::V6)) {
+            TcpListener::bind(SocketAddr::new(ip, 0)).map_err(|e| anyhow!("Failed to bind to {}: {}", ip, e))?;
+        }
+        Ok(local)
+    }
+}
+
+#[cfg(unix)]
+fn interface_addresses(name: &str) -> Result<Vec<IpAddr>> {
+    use std::ffi::CStr;
+
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    // SAFETY: getifaddrs fills in a list that is freed with freeifaddrs below
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return Err(io::Error::last_os_error()).context("Failed to list network interfaces");
+    }
+    let mut found = false;
+    let mut addrs = Vec::new();
+    let mut cursor = ifaddrs;
+    while !cursor.is_null() {
+        // SAFETY: cursor points into the list, which stays alive until freeifaddrs
+        let entry = unsafe { &*cursor };
+        cursor = entry.ifa_next;
+        if unsafe { CStr::from_ptr(entry.ifa_name) }.to_bytes() != name.as_bytes() {
+            continue;
+        }
+        found = true;
+        if entry.ifa_addr.is_null() {
+            continue;
+        }
+        // SAFETY: ifa_addr is non-null and sa_family tells which sockaddr it is
+        match i32::from(unsafe { (*entry.ifa_addr).sa_family }) {
+            libc::AF_INET => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in) };
+                addrs.push(IpAddr::V4(Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr))));
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(entry.ifa_addr as *const libc::sockaddr_in6) };
+                addrs.push(IpAddr::V6(Ipv6Addr::from(addr.sin6_addr.s6_addr)));
+            }
+            _ => {}
+        }
+    }
+    // SAFETY: ifaddrs came from getifaddrs and is not used after this
+    unsafe { libc::freeifaddrs(ifaddrs) };
+
+    if !found {
+        return Err(anyhow!("Network interface {:?} not found", name));
+    }
+    Ok(addrs)
+}
+
+#[cfg(not(unix))]
+fn interface_addresses(name: &str) -> Result<Vec<IpAddr>> {
+    Err(anyhow!("Interface names are not supported on this platform; pass an IP address instead of {:?}", name))
+}
+
+/// Why outgoing connections can't use TCP Fast Open on this host, if they can't.
+#[cfg(target_os = "linux")]
+pub fn fast_open_unavailable() -> Option<String> {
+    // 第 0 位允许客户端使用 TFO；读不到时交给 setsockopt 判断
+    let sysctl = std::fs::read_to_string("/proc/sys/net/ipv4/tcp_fastopen").ok()?;
+    let value: u32 = sysctl.trim().parse().ok()?;
+    (value & 1 == 0).then(|| format!("net.ipv4.tcp_fastopen is {}, which disables it for clients", value))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fast_open_unavailable() -> Option<String> {
+    Some("TCP Fast Open is only supported on Linux".to_string())
+}
+
+/// Connections that sent data in the SYN and had it accepted, counted by the
+/// kernel for the whole host (TcpExt TCPFastOpenActive in /proc/net/netstat).
+#[cfg(target_os = "linux")]
+pub fn fast_open_connections() -> Option<u64> {
+    let netstat = std::fs::read_to_string("/proc/net/netstat").ok()?;
+    // 每组计数器是两行：一行名称，一行数值
+    let mut lines = netstat.lines().filter(|line| line.starts_with("TcpExt:"));
+    let (names, values) = (lines.next()?, lines.next()?);
+    let index = names.split_whitespace().position(|name| name == "TCPFastOpenActive")?;
+    values.split_whitespace().nth(index)?.parse().ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn fast_open_connections() -> Option<u64> {
+    None
+}
+
+/// Open a TCP connection with TCP_FASTOPEN_CONNECT set, applying the other
+/// options the way HttpConnector would.
+pub async fn connect_fast_open(addr: SocketAddr, options: &SocketOptions, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    socket.set_nodelay(options.nodelay)?;
+    if let Some(size) = options.send_buffer {
+        socket.set_send_buffer_size(size as u32)?;
+    }
+    if let Some(size) = options.recv_buffer {
+        socket.set_recv_buffer_size(size as u32)?;
+    }
+    let local = options.local_address.and_then(|local| match addr {
+        SocketAddr::V4(_) => local.ipv4.map(IpAddr::V4),
+        SocketAddr::V6(_) => local.ipv6.map(IpAddr::V6),
+    });
+    if let Some(ip) = local {
+        socket.bind(SocketAddr::new(ip, 0))?;
+    }
+    set_fast_open_connect(&socket)?;
+    // 有 TFO cookie 时 connect 立即返回，SYN 随第一次写入一起发出
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, socket.connect(addr))
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "connect timed out"))?,
+        None => socket.connect(addr).await,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_fast_open_connect(socket: &TcpSocket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let enable: libc::c_int = 1;
+    // SAFETY: the fd is owned by `socket` and the option value outlives the call
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            libc::TCP_FASTOPEN_CONNECT,
+            &enable as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_fast_open_connect(_socket: &TcpSocket) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "TCP Fast Open is only supported on Linux"))
+}
+
+/// Socket options as the OS actually applied them; buffer sizes are often
+/// rounded up (Linux doubles the requested value).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SocketInfo {
+    pub nodelay: bool,
+    pub send_buffer_bytes: usize,
+    pub recv_buffer_bytes: usize,
+}
+
+impl SocketInfo {
+    pub fn read(tcp: &TcpStream) -> io::Result<Self> {
+        let socket = SockRef::from(tcp);
+        Ok(SocketInfo {
+            nodelay: tcp.nodelay()?,
+            send_buffer_bytes: socket.send_buffer_size()?,
+            recv_buffer_bytes: socket.recv_buffer_size()?,
+        })
+    }
+}
@@ -1,71 +1,1204 @@
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use dashmap::DashMap;
 use hdrhistogram::Histogram;
+use hyper::{HeaderMap, Version};
+use serde::{Deserialize, Serialize};
+use crate::resource::ResourceUsage;
+use crate::server_push::ServerPushReport;
+use crate::sla::SlaResult;
+use crate::socket::SocketInfo;
+use crate::tls::TlsVersion;
 use std::time::{Duration, Instant};
 
-#[derive(Debug, Default)]
+const MAX_STATUS_CODE: usize = 600;
+
+#[derive(Debug)]
 pub struct AtomicStats {
     pub requests: AtomicU64,
     pub success: AtomicU64,
     pub errors: AtomicU64,
+    pub assertion_failures: AtomicU64,
+    pub retries: AtomicU64,
+    pub redirects: AtomicU64,
+    /// Requests that failed because the connection couldn't be opened in time
+    pub connect_timeouts: AtomicU64,
+    /// Requests that hit the request timeout (-T)
+    pub read_timeouts: AtomicU64,
+    /// Responses whose body stalled for longer than --read-timeout
+    pub body_timeouts: AtomicU64,
+    /// Body chunks received with --streaming
+    pub chunks: AtomicU64,
     pub bytes: AtomicU64,
+    pub bytes_decompressed: AtomicU64,
+    /// Request body bytes sent
+    pub bytes_sent: AtomicU64,
+    /// Response header names and values plus ": " and CRLF, as decoded
+    pub header_bytes: AtomicU64,
+    pub http1_responses: AtomicU64,
+    pub http2_responses: AtomicU64,
+    pub new_connections: AtomicU64,
+    /// Responses received on a connection that had already served one
+    pub reused_connections: AtomicU64,
+    /// New connections by negotiated protocol
+    pub http1_connections: AtomicU64,
+    pub http2_connections: AtomicU64,
+    pub tls12_connections: AtomicU64,
+    pub tls13_connections: AtomicU64,
+    pub active_connections: AtomicU64,
+    pub status_codes: Vec<AtomicU64>,
+}
+
+impl Default for AtomicStats {
+    fn default() -> Self {
+        AtomicStats {
+            requests: AtomicU64::default(),
+            success: AtomicU64::default(),
+            errors: AtomicU64::default(),
+            assertion_failures: AtomicU64::default(),
+            retries: AtomicU64::default(),
+            redirects: AtomicU64::default(),
+            connect_timeouts: AtomicU64::default(),
+            read_timeouts: AtomicU64::default(),
+            body_timeouts: AtomicU64::default(),
+            chunks: AtomicU64::default(),
+            bytes: AtomicU64::default(),
+            bytes_decompressed: AtomicU64::default(),
+            bytes_sent: AtomicU64::default(),
+            header_bytes: AtomicU64::default(),
+            http1_responses: AtomicU64::default(),
+            http2_responses: AtomicU64::default(),
+            new_connections: AtomicU64::default(),
+            reused_connections: AtomicU64::default(),
+            http1_connections: AtomicU64::default(),
+            http2_connections: AtomicU64::default(),
+            tls12_connections: AtomicU64::default(),
+            tls13_connections: AtomicU64::default(),
+            active_connections: AtomicU64::default(),
+            status_codes: (0..MAX_STATUS_CODE).map(|_| AtomicU64::default()).collect(),
+        }
+    }
+}
+
+/// Significant figures of latency histograms when --histogram-precision is not given.
+pub const DEFAULT_HISTOGRAM_PRECISION: u8 = 3;
+
+// 超过 2^40 微秒（约 12.7 天）的值都落在最后一个桶里并单独计数，最大值仍然精确记录
+const MAX_VALUE_BITS: u32 = 40;
+const MAX_VALUE: u64 = (1 << MAX_VALUE_BITS) - 1;
+
+/// Lock-free histogram of `u64` values (microseconds here) that can be
+/// recorded from many threads through a shared reference.
+///
+/// Bucket boundaries are powers of two, each range split into enough linear
+/// sub-buckets for the configured significant figures (1024 for 3, so
+/// quantiles are within 0.1% of the recorded values). The exact minimum and
+/// maximum are tracked separately.
+#[derive(Debug)]
+pub struct AtomicHistogram {
+    buckets: Box<[AtomicU64]>,
+    sigfigs: u8,
+    sub_bucket_bits: u32,
+    min: AtomicU64,
+    max: AtomicU64,
+    // 超出范围、按最大可区分值记录的数量
+    overrange: AtomicU64,
+}
+
+impl Default for AtomicHistogram {
+    fn default() -> Self {
+        AtomicHistogram::new()
+    }
+}
+
+impl AtomicHistogram {
+    pub fn new() -> Self {
+        AtomicHistogram::with_precision(DEFAULT_HISTOGRAM_PRECISION)
+    }
+
+    /// Buckets fine enough for `sigfigs` (1-5) significant figures; memory
+    /// grows tenfold per extra figure, from ~250KB at 3 to ~25MB at 5
+    pub fn with_precision(sigfigs: u8) -> Self {
+        // 每个区间至少 10^sigfigs 个桶
+        let sub_bucket_bits = (f64::from(sigfigs) * 10f64.log2()).ceil() as u32;
+        let buckets = (1usize << sub_bucket_bits) * (MAX_VALUE_BITS - sub_bucket_bits + 1) as usize;
+        AtomicHistogram {
+            buckets: (0..buckets).map(|_| AtomicU64::new(0)).collect(),
+            sigfigs,
+            sub_bucket_bits,
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+            overrange: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sigfigs(&self) -> u8 {
+        self.sigfigs
+    }
+
+    /// Values above the trackable range, counted in the highest bucket
+    pub fn overrange(&self) -> u64 {
+        self.overrange.load(Ordering::Relaxed)
+    }
+
+    pub fn record(&self, value: u64) {
+        if value > MAX_VALUE {
+            self.overrange.fetch_add(1, Ordering::Relaxed);
+        }
+        self.buckets[bucket_index(value, self.sub_bucket_bits)].fetch_add(1, Ordering::Relaxed);
+        // 先读再比较，大多数记录不需要更新最值
+        if value < self.min.load(Ordering::Relaxed) {
+            self.min.fetch_min(value, Ordering::Relaxed);
+        }
+        if value > self.max.load(Ordering::Relaxed) {
+            self.max.fetch_max(value, Ordering::Relaxed);
+        }
+    }
+
+    /// Record `value` and back-fill `value - interval`, `value - 2 * interval`, ...
+    /// down to `interval`, like `Histogram::record_correct`
+    pub fn record_correct(&self, value: u64, interval: u64) {
+        self.record(value);
+        if interval == 0 {
+            return;
+        }
+        let mut missing = value.saturating_sub(interval);
+        while missing >= interval {
+            self.record(missing);
+            missing -= interval;
+        }
+    }
+
+    /// Number of recorded values; sums all buckets, so not for the hot path
+    pub fn len(&self) -> u64 {
+        self.buckets.iter().map(|bucket| bucket.load(Ordering::Relaxed)).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.max.load(Ordering::Relaxed) == 0 && self.len() == 0
+    }
+
+    /// Highest value in the bucket holding the `q` quantile, capped at the maximum
+    pub fn value_at_quantile(&self, q: f64) -> u64 {
+        let total = self.len();
+        if total == 0 {
+            return 0;
+        }
+        let target = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket.load(Ordering::Relaxed);
+            if seen >= target {
+                let (low, width) = bucket_range(index, self.sub_bucket_bits);
+                return (low + (width - 1)).min(self.max.load(Ordering::Relaxed));
+            }
+        }
+        self.max.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+        self.overrange.store(0, Ordering::Relaxed);
+    }
+
+    /// Copy into an HdrHistogram for reporting, merging and serialization.
+    /// Each bucket is recorded at its midpoint, except that the exact minimum
+    /// and maximum are kept.
+    pub fn to_histogram(&self) -> Histogram<u64> {
+        let mut histogram = new_histogram(self.sigfigs);
+        if self.is_empty() {
+            return histogram;
+        }
+        let (min, max) = (self.min.load(Ordering::Relaxed), self.max.load(Ordering::Relaxed));
+        let (min_index, max_index) = (bucket_index(min, self.sub_bucket_bits), bucket_index(max, self.sub_bucket_bits));
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            let mut count = bucket.load(Ordering::Relaxed);
+            if count == 0 {
+                continue;
+            }
+            if index == min_index {
+                histogram.record(min).unwrap_or_default();
+                count -= 1;
+            }
+            if index == max_index && count > 0 {
+                histogram.record(max).unwrap_or_default();
+                count -= 1;
+            }
+            let (low, width) = bucket_range(index, self.sub_bucket_bits);
+            histogram.record_n(low + (width - 1) / 2, count).unwrap_or_default();
+        }
+        histogram
+    }
+}
+
+/// An auto-resizing HdrHistogram with `sigfigs` significant figures
+pub fn new_histogram(sigfigs: u8) -> Histogram<u64> {
+    Histogram::<u64>::new(sigfigs).expect("Failed to create histogram")
+}
+
+fn bucket_index(value: u64, sub_bucket_bits: u32) -> usize {
+    let sub_buckets = 1usize << sub_bucket_bits;
+    let value = value.min(MAX_VALUE);
+    if value < sub_buckets as u64 {
+        return value as usize;
+    }
+    // 最高位决定区间，其后 sub_bucket_bits 位决定区间内的桶
+    let exponent = 63 - value.leading_zeros();
+    let shift = exponent - sub_bucket_bits;
+    let sub = (value >> shift) as usize - sub_buckets;
+    sub_buckets + shift as usize * sub_buckets + sub
+}
+
+// 返回桶的下界和宽度
+fn bucket_range(index: usize, sub_bucket_bits: u32) -> (u64, u64) {
+    let sub_buckets = 1usize << sub_bucket_bits;
+    if index < sub_buckets {
+        return (index as u64, 1);
+    }
+    let shift = (index - sub_buckets) / sub_buckets;
+    let sub = (index - sub_buckets) % sub_buckets;
+    (((sub_buckets + sub) as u64) << shift, 1 << shift)
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CounterSnapshot {
+    pub requests: u64,
+    pub success: u64,
+    pub errors: u64,
+    pub bytes: u64,
+    pub bytes_decompressed: u64,
+}
+
+impl AtomicStats {
+    pub fn snapshot(&self) -> CounterSnapshot {
+        CounterSnapshot {
+            requests: self.requests.load(Ordering::Relaxed),
+            success: self.success.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            bytes_decompressed: self.bytes_decompressed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl CounterSnapshot {
+    pub fn delta(&self, previous: &CounterSnapshot) -> CounterSnapshot {
+        CounterSnapshot {
+            requests: self.requests.saturating_sub(previous.requests),
+            success: self.success.saturating_sub(previous.success),
+            errors: self.errors.saturating_sub(previous.errors),
+            bytes: self.bytes.saturating_sub(previous.bytes),
+            bytes_decompressed: self.bytes_decompressed.saturating_sub(previous.bytes_decompressed),
+        }
+    }
+}
+
+pub struct Window {
+    pub counters: CounterSnapshot,
+    pub histogram: Histogram<u64>,
+}
+
+/// Requests, errors and latency of one second of the test.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StatsSnapshot {
+    #[serde(skip, default = "Instant::now")]
+    pub timestamp: Instant,
+    /// Seconds since the end of the warmup
+    pub elapsed_secs: u64,
+    pub requests: u64,
+    pub errors: u64,
+    pub bytes: u64,
+    pub p50_us: u64,
+    pub p99_us: u64,
+}
+
+impl StatsSnapshot {
+    pub fn new(elapsed_secs: u64, window: &Window) -> Self {
+        StatsSnapshot {
+            timestamp: Instant::now(),
+            elapsed_secs,
+            requests: window.counters.requests,
+            errors: window.counters.errors,
+            bytes: window.counters.bytes,
+            p50_us: window.histogram.value_at_quantile(0.50),
+            p99_us: window.histogram.value_at_quantile(0.99),
+        }
+    }
+}
+
+pub struct Sampler {
+    stats: Statistics,
+    last: CounterSnapshot,
+    last_histogram: Histogram<u64>,
+}
+
+impl Sampler {
+    pub fn new(stats: Statistics) -> Self {
+        let last = stats.snapshot();
+        let last_histogram = stats.histogram();
+        Sampler { stats, last, last_histogram }
+    }
+
+    pub fn sample(&mut self) -> Window {
+        let current = self.stats.snapshot();
+        let counters = current.delta(&self.last);
+
+        // 只保留这一窗口内的延迟分布；预热重置后直接使用当前直方图
+        let histogram = self.stats.histogram();
+        let mut window = histogram.clone();
+        if window.subtract(&self.last_histogram).is_err() {
+            window = histogram.clone();
+        }
+
+        self.last = current;
+        self.last_histogram = histogram;
+        Window { counters, histogram: window }
+    }
+}
+
+/// Latency percentiles reported when none are configured.
+pub const DEFAULT_PERCENTILES: [f64; 5] = [50.0, 75.0, 90.0, 95.0, 99.0];
+
+/// Latency at one configured percentile (--percentiles).
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Percentile {
+    pub percentile: f64,
+    pub latency_ms: f64,
+}
+
+/// Latency summary for one connection setup phase.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PhaseReport {
+    pub count: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+}
+
+impl PhaseReport {
+    fn new(histogram: &Histogram<u64>) -> Self {
+        let ms = |q: f64| histogram.value_at_quantile(q) as f64 / 1000.0;
+        PhaseReport {
+            count: histogram.len(),
+            mean_ms: histogram.mean() / 1000.0,
+            p50_ms: ms(0.50),
+            p99_ms: ms(0.99),
+        }
+    }
+}
+
+/// Chunk timings of streamed responses (--streaming).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct StreamingReport {
+    pub chunks: u64,
+    pub chunks_per_response: f64,
+    /// Time to first chunk, measured from the start of the request
+    pub ttfc_p50_ms: f64,
+    pub ttfc_p99_ms: f64,
+    /// Delay between consecutive chunks of the same response
+    pub chunk_delay_p50_ms: f64,
+    pub chunk_delay_p99_ms: f64,
+}
+
+impl StreamingReport {
+    pub fn new(chunks: u64, ttfc: &Histogram<u64>, chunk_delay: &Histogram<u64>) -> Self {
+        let ms = |histogram: &Histogram<u64>, q: f64| histogram.value_at_quantile(q) as f64 / 1000.0;
+        StreamingReport {
+            chunks,
+            chunks_per_response: if !ttfc.is_empty() { chunks as f64 / ttfc.len() as f64 } else { 0.0 },
+            ttfc_p50_ms: ms(ttfc, 0.50),
+            ttfc_p99_ms: ms(ttfc, 0.99),
+            chunk_delay_p50_ms: ms(chunk_delay, 0.50),
+            chunk_delay_p99_ms: ms(chunk_delay, 0.99),
+        }
+    }
 }
 
+/// Distribution of request or response body sizes, in bytes.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SizeReport {
+    pub min: u64,
+    pub mean: f64,
+    pub max: u64,
+    pub p99: u64,
+}
+
+impl SizeReport {
+    pub fn new(histogram: &Histogram<u64>) -> Self {
+        SizeReport {
+            min: histogram.min(),
+            mean: histogram.mean(),
+            max: histogram.max(),
+            p99: histogram.value_at_quantile(0.99),
+        }
+    }
+}
+
+/// New vs. reused connections, with setup phase timings for the new ones.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConnectionReport {
+    pub new: u64,
+    pub reused: u64,
+    /// New connections that negotiated HTTP/1.1 and HTTP/2
+    pub http1: u64,
+    pub http2: u64,
+    /// New connections that negotiated TLS 1.2 and TLS 1.3
+    #[serde(default)]
+    pub tls12: u64,
+    #[serde(default)]
+    pub tls13: u64,
+    pub dns: PhaseReport,
+    pub tcp: PhaseReport,
+    pub tls: PhaseReport,
+    /// Options of the most recently opened TCP socket
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub socket: Option<SocketInfo>,
+    /// When connection tasks started, only with --connections-per-second
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub setup: Option<ConnectionSetup>,
+}
+
+/// Offsets from the start of the run at which the first and the last
+/// connection task started.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ConnectionSetup {
+    pub first_ms: f64,
+    pub last_ms: f64,
+}
+
+/// One --burst-size burst: when it started and how long it took until the
+/// last of its responses arrived.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct BurstSample {
+    /// Seconds since the end of the warmup
+    pub elapsed_secs: f64,
+    pub requests: u64,
+    pub completion_ms: f64,
+}
+
+/// Number of active connection tasks at a point in the test.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ConcurrencySample {
+    pub elapsed_secs: u64,
+    pub connections: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub requests: u64,
+    pub successes: u64,
+    pub errors: u64,
+    /// 2xx responses whose body failed --assert-body-contains / --assert-json-path
+    pub assertion_failures: u64,
+    /// Extra attempts made by --retry; not counted in `requests`
+    pub retries: u64,
+    /// Redirects followed with --follow-redirects; not counted in `requests`
+    pub redirects: u64,
+    /// Errors caused by --connection-timeout (or the OS connect timeout)
+    pub connect_timeouts: u64,
+    /// Errors caused by the request timeout (-T)
+    pub read_timeouts: u64,
+    /// Errors caused by a body frame taking longer than --read-timeout
+    pub body_timeouts: u64,
+    /// Response body bytes as received on the wire
+    pub bytes_transferred: u64,
+    /// Response body bytes after decompression (0 when decompression is off)
+    pub bytes_decompressed: u64,
+    pub compression_ratio: f64,
+    /// Response header bytes; for HTTP/2 this is the size before HPACK
+    pub header_bytes: u64,
+    pub header_bytes_per_sec: f64,
+    /// Request body bytes uploaded
+    pub bytes_sent: u64,
+    pub upload_mb_per_sec: f64,
+    pub protocol: String,
+    pub duration_secs: f64,
+    pub rps: f64,
+    pub transfer_mb_per_sec: f64,
+    pub latency_mean_ms: f64,
+    pub latency_min_ms: f64,
+    pub latency_max_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p75_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+    pub latency_p999_ms: f64,
+    /// Latency at each configured percentile, in the order given
+    pub latency_percentiles: Vec<Percentile>,
+    pub ttfb_p50_ms: f64,
+    pub ttfb_p95_ms: f64,
+    pub ttfb_p99_ms: f64,
+    pub connections: ConnectionReport,
+    pub status_codes: BTreeMap<u16, u64>,
+    /// Custom counters collected by a --script, summed across threads
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub script_counters: BTreeMap<String, f64>,
+    /// Per-second concurrency while ramping up connections
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub concurrency: Vec<ConcurrencySample>,
+    /// Requests, errors and latency in each second of the test
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<StatsSnapshot>,
+    /// Start and completion time of every burst, only with --burst-size
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bursts: Vec<BurstSample>,
+    /// Chunk timings, only with --streaming
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub streaming: Option<StreamingReport>,
+    /// Number of responses that carried each header name, only with
+    /// --dump-response-headers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub response_headers: BTreeMap<String, u64>,
+    /// Methods listed in the Allow headers of OPTIONS responses
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub allowed_methods: BTreeSet<String>,
+    /// Request and response body sizes, only with --request-size-histogram
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_sizes: Option<SizeReport>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_sizes: Option<SizeReport>,
+    /// Per-URL thresholds from --sla-file and whether they held
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sla_results: Vec<SlaResult>,
+    /// Pushed resources, only with --server-push-accept or --server-push-cancel
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_push: Option<ServerPushReport>,
+    /// Memory and file descriptor usage at the end of the test
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceUsage>,
+}
+
+impl Report {
+    /// Build a report from raw counters; protocol and status codes are left empty
+    pub fn new(counters: &CounterSnapshot, duration: Duration, histogram: &Histogram<u64>) -> Self {
+        let duration = duration.as_secs_f64();
+        let ms = |micros: u64| micros as f64 / 1000.0;
+
+        Report {
+            requests: counters.requests,
+            successes: counters.success,
+            errors: counters.errors,
+            assertion_failures: 0,
+            retries: 0,
+            redirects: 0,
+            connect_timeouts: 0,
+            read_timeouts: 0,
+            body_timeouts: 0,
+            bytes_transferred: counters.bytes,
+            bytes_decompressed: counters.bytes_decompressed,
+            compression_ratio: if counters.bytes > 0 && counters.bytes_decompressed > 0 {
+                counters.bytes_decompressed as f64 / counters.bytes as f64
+            } else {
+                0.0
+            },
+            header_bytes: 0,
+            header_bytes_per_sec: 0.0,
+            bytes_sent: 0,
+            upload_mb_per_sec: 0.0,
+            protocol: String::new(),
+            duration_secs: duration,
+            rps: counters.requests as f64 / duration,
+            transfer_mb_per_sec: counters.bytes as f64 / duration / 1024.0 / 1024.0,
+            latency_mean_ms: histogram.mean() / 1000.0,
+            latency_min_ms: ms(histogram.min()),
+            latency_max_ms: ms(histogram.max()),
+            latency_p50_ms: ms(histogram.value_at_quantile(0.50)),
+            latency_p75_ms: ms(histogram.value_at_quantile(0.75)),
+            latency_p95_ms: ms(histogram.value_at_quantile(0.95)),
+            latency_p99_ms: ms(histogram.value_at_quantile(0.99)),
+            latency_p999_ms: ms(histogram.value_at_quantile(0.999)),
+            latency_percentiles: Vec::new(),
+            ttfb_p50_ms: 0.0,
+            ttfb_p95_ms: 0.0,
+            ttfb_p99_ms: 0.0,
+            connections: ConnectionReport::default(),
+            status_codes: BTreeMap::new(),
+            script_counters: BTreeMap::new(),
+            concurrency: Vec::new(),
+            bursts: Vec::new(),
+            timeline: Vec::new(),
+            streaming: None,
+            response_headers: BTreeMap::new(),
+            allowed_methods: BTreeSet::new(),
+            request_sizes: None,
+            response_sizes: None,
+            sla_results: Vec::new(),
+            server_push: None,
+            resources: None,
+        }
+        .with_percentiles(histogram, &DEFAULT_PERCENTILES)
+    }
+
+    pub fn with_percentiles(self, histogram: &Histogram<u64>, percentiles: &[f64]) -> Self {
+        Report {
+            latency_percentiles: percentiles
+                .iter()
+                .map(|&percentile| Percentile {
+                    percentile,
+                    latency_ms: histogram.value_at_quantile(percentile / 100.0) as f64 / 1000.0,
+                })
+                .collect(),
+            ..self
+        }
+    }
+
+    pub fn with_header_bytes(self, header_bytes: u64) -> Self {
+        Report {
+            header_bytes,
+            header_bytes_per_sec: if self.duration_secs > 0.0 { header_bytes as f64 / self.duration_secs } else { 0.0 },
+            ..self
+        }
+    }
+
+    pub fn with_bytes_sent(self, bytes_sent: u64) -> Self {
+        Report {
+            bytes_sent,
+            upload_mb_per_sec: if self.duration_secs > 0.0 { bytes_sent as f64 / self.duration_secs / 1024.0 / 1024.0 } else { 0.0 },
+            ..self
+        }
+    }
+
+    pub fn with_ttfb(self, ttfb: &Histogram<u64>) -> Self {
+        let ms = |q: f64| ttfb.value_at_quantile(q) as f64 / 1000.0;
+        Report {
+            ttfb_p50_ms: ms(0.50),
+            ttfb_p95_ms: ms(0.95),
+            ttfb_p99_ms: ms(0.99),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PhaseHistograms {
+    dns: Histogram<u64>,
+    tcp: Histogram<u64>,
+    tls: Histogram<u64>,
+    socket: Option<SocketInfo>,
+}
+
+#[derive(Debug)]
+struct ChunkHistograms {
+    first: Histogram<u64>,
+    delay: Histogram<u64>,
+}
+
+#[derive(Debug)]
+struct SizeHistograms {
+    request: Histogram<u64>,
+    response: Histogram<u64>,
+}
+
+impl PhaseHistograms {
+    fn new(sigfigs: u8) -> Self {
+        PhaseHistograms {
+            dns: new_histogram(sigfigs),
+            tcp: new_histogram(sigfigs),
+            tls: new_histogram(sigfigs),
+            socket: None,
+        }
+    }
+}
+
+impl ChunkHistograms {
+    fn new(sigfigs: u8) -> Self {
+        ChunkHistograms {
+            first: new_histogram(sigfigs),
+            delay: new_histogram(sigfigs),
+        }
+    }
+}
+
+impl SizeHistograms {
+    fn new(sigfigs: u8) -> Self {
+        SizeHistograms {
+            request: new_histogram(sigfigs),
+            response: new_histogram(sigfigs),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Statistics {
     stats: Arc<AtomicStats>,
-    histogram: Histogram<u64>,
-    start_time: Instant,
+    histogram: Arc<AtomicHistogram>,
+    // 从发出请求到收到响应头的时间
+    ttfb: Arc<AtomicHistogram>,
+    // 新建连接各阶段的耗时
+    phases: Arc<Mutex<PhaseHistograms>>,
+    // 流式响应的首块时间和块间隔
+    chunks: Arc<Mutex<ChunkHistograms>>,
+    // 请求体和响应体的大小
+    sizes: Arc<Mutex<SizeHistograms>>,
+    // 每个响应头名称出现在多少个响应中
+    // 每个响应都要更新，用分片的并发 map 避免全局锁
+    response_headers: Arc<DashMap<String, AtomicU64>>,
+    // OPTIONS 响应的 Allow 头中出现过的方法
+    allowed_methods: Arc<Mutex<BTreeSet<String>>>,
+    // 汇总时计算的延迟百分位
+    percentiles: Arc<Vec<f64>>,
+    start_time: Arc<Mutex<Instant>>,
+}
+
+impl Default for Statistics {
+    fn default() -> Self {
+        Statistics::new()
+    }
 }
 
 impl Statistics {
     pub fn new() -> Self {
         Statistics {
             stats: Arc::new(AtomicStats::default()),
-            histogram: Histogram::<u64>::new(3).expect("Failed to create histogram"),
-            start_time: Instant::now(),
+            histogram: Arc::new(AtomicHistogram::new()),
+            ttfb: Arc::new(AtomicHistogram::new()),
+            phases: Arc::new(Mutex::new(PhaseHistograms::new(DEFAULT_HISTOGRAM_PRECISION))),
+            chunks: Arc::new(Mutex::new(ChunkHistograms::new(DEFAULT_HISTOGRAM_PRECISION))),
+            sizes: Arc::new(Mutex::new(SizeHistograms::new(DEFAULT_HISTOGRAM_PRECISION))),
+            response_headers: Arc::new(DashMap::new()),
+            allowed_methods: Arc::new(Mutex::new(BTreeSet::new())),
+            percentiles: Arc::new(DEFAULT_PERCENTILES.to_vec()),
+            start_time: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Record timings with this many significant figures (1-5) instead of 3
+    pub fn with_histogram_precision(mut self, sigfigs: u8) -> Self {
+        self.histogram = Arc::new(AtomicHistogram::with_precision(sigfigs));
+        self.ttfb = Arc::new(AtomicHistogram::with_precision(sigfigs));
+        self.phases = Arc::new(Mutex::new(PhaseHistograms::new(sigfigs)));
+        self.chunks = Arc::new(Mutex::new(ChunkHistograms::new(sigfigs)));
+        self.sizes = Arc::new(Mutex::new(SizeHistograms::new(sigfigs)));
+        self
+    }
+
+    pub fn histogram_precision(&self) -> u8 {
+        self.histogram.sigfigs()
+    }
+
+    /// Report latency at these percentiles instead of the defaults
+    pub fn with_percentiles(mut self, percentiles: Vec<f64>) -> Self {
+        self.percentiles = Arc::new(percentiles);
+        self
+    }
+
+    pub fn percentiles(&self) -> Vec<f64> {
+        self.percentiles.to_vec()
+    }
+
+    pub fn record_version(&self, version: Version) {
+        if version == Version::HTTP_2 {
+            self.stats.http2_responses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.stats.http1_responses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_status(&self, status: u16) {
+        if let Some(counter) = self.stats.status_codes.get(status as usize) {
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn status_codes(&self) -> BTreeMap<u16, u64> {
+        self.stats
+            .status_codes
+            .iter()
+            .enumerate()
+            .map(|(code, count)| (code as u16, count.load(Ordering::Relaxed)))
+            .filter(|&(_, count)| count > 0)
+            .collect()
+    }
+
+    pub fn protocol(&self) -> String {
+        let http1 = self.stats.http1_responses.load(Ordering::Relaxed);
+        let http2 = self.stats.http2_responses.load(Ordering::Relaxed);
+        match (http1, http2) {
+            (0, 0) => "unknown".to_string(),
+            (_, 0) => "HTTP/1.1".to_string(),
+            (0, _) => "HTTP/2".to_string(),
+            _ => format!("HTTP/2 ({}), HTTP/1.1 ({})", http2, http1),
         }
     }
 
-    pub fn record_request(&mut self, success: bool, bytes: u64, latency: Duration) {
+    pub fn snapshot(&self) -> CounterSnapshot {
+        self.stats.snapshot()
+    }
+
+    pub fn histogram(&self) -> Histogram<u64> {
+        self.histogram.to_histogram()
+    }
+
+    pub fn record_connection(&self, dns: Duration, tcp: Duration, tls: Option<Duration>, socket: Option<SocketInfo>) {
+        self.stats.new_connections.fetch_add(1, Ordering::Relaxed);
+        let micros = |d: Duration| d.as_micros() as u64;
+        let mut phases = self.phases.lock().unwrap();
+        phases.dns.record(micros(dns)).unwrap_or_default();
+        phases.tcp.record(micros(tcp)).unwrap_or_default();
+        if let Some(tls) = tls {
+            phases.tls.record(micros(tls)).unwrap_or_default();
+        }
+        if socket.is_some() {
+            phases.socket = socket;
+        }
+    }
+
+    pub fn record_connection_protocol(&self, http2: bool) {
+        let counter = if http2 { &self.stats.http2_connections } else { &self.stats.http1_connections };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_tls_version(&self, version: TlsVersion) {
+        let counter = match version {
+            TlsVersion::Tls12 => &self.stats.tls12_connections,
+            TlsVersion::Tls13 => &self.stats.tls13_connections,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_reused_connection(&self) {
+        self.stats.reused_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_started(&self) {
+        self.stats.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_finished(&self) {
+        self.stats.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn active_connections(&self) -> u64 {
+        self.stats.active_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn connections(&self) -> ConnectionReport {
+        let phases = self.phases.lock().unwrap();
+        ConnectionReport {
+            new: self.stats.new_connections.load(Ordering::Relaxed),
+            reused: self.stats.reused_connections.load(Ordering::Relaxed),
+            http1: self.stats.http1_connections.load(Ordering::Relaxed),
+            http2: self.stats.http2_connections.load(Ordering::Relaxed),
+            tls12: self.stats.tls12_connections.load(Ordering::Relaxed),
+            tls13: self.stats.tls13_connections.load(Ordering::Relaxed),
+            dns: PhaseReport::new(&phases.dns),
+            tcp: PhaseReport::new(&phases.tcp),
+            tls: PhaseReport::new(&phases.tls),
+            socket: phases.socket,
+            setup: None,
+        }
+    }
+
+    pub fn ttfb_histogram(&self) -> Histogram<u64> {
+        self.ttfb.to_histogram()
+    }
+
+    /// Latency and TTFB values too large for the histograms, recorded at
+    /// their highest bucket
+    pub fn overrange(&self) -> u64 {
+        self.histogram.overrange() + self.ttfb.overrange()
+    }
+
+    pub fn record_ttfb(&self, ttfb: Duration) {
+        self.ttfb.record(ttfb.as_micros() as u64);
+    }
+
+    /// Record one body chunk; `delay` is the time since the previous chunk,
+    /// or since the start of the request for the first one
+    pub fn record_chunk(&self, first: bool, delay: Duration) {
+        self.stats.chunks.fetch_add(1, Ordering::Relaxed);
+        let micros = delay.as_micros() as u64;
+        let mut chunks = self.chunks.lock().unwrap();
+        if first {
+            chunks.first.record(micros).unwrap_or_default();
+        } else {
+            chunks.delay.record(micros).unwrap_or_default();
+        }
+    }
+
+    pub fn chunks(&self) -> u64 {
+        self.stats.chunks.load(Ordering::Relaxed)
+    }
+
+    /// Time-to-first-chunk and inter-chunk delay histograms
+    pub fn chunk_histograms(&self) -> (Histogram<u64>, Histogram<u64>) {
+        let chunks = self.chunks.lock().unwrap();
+        (chunks.first.clone(), chunks.delay.clone())
+    }
+
+    /// Record the body sizes of one request and its response
+    pub fn record_sizes(&self, request: u64, response: u64) {
+        let mut sizes = self.sizes.lock().unwrap();
+        sizes.request.record(request).unwrap_or_default();
+        sizes.response.record(response).unwrap_or_default();
+    }
+
+    /// Count each header name of one response once
+    pub fn record_response_headers(&self, headers: &HeaderMap) {
+        for name in headers.keys() {
+            match self.response_headers.get(name.as_str()) {
+                Some(count) => count.fetch_add(1, Ordering::Relaxed),
+                None => self.response_headers.entry(name.as_str().to_string()).or_default().fetch_add(1, Ordering::Relaxed),
+            };
+        }
+    }
+
+    pub fn response_headers(&self) -> BTreeMap<String, u64> {
+        self.response_headers
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Collect the methods of an OPTIONS response's Allow headers
+    pub fn record_allowed_methods(&self, headers: &HeaderMap) {
+        let mut methods = self.allowed_methods.lock().unwrap();
+        for value in headers.get_all(hyper::header::ALLOW) {
+            let Ok(value) = value.to_str() else { continue };
+            for method in value.split(',').map(str::trim).filter(|method| !method.is_empty()) {
+                methods.insert(method.to_ascii_uppercase());
+            }
+        }
+    }
+
+    pub fn allowed_methods(&self) -> BTreeSet<String> {
+        self.allowed_methods.lock().unwrap().clone()
+    }
+
+    /// Request and response body size histograms
+    pub fn size_histograms(&self) -> (Histogram<u64>, Histogram<u64>) {
+        let sizes = self.sizes.lock().unwrap();
+        (sizes.request.clone(), sizes.response.clone())
+    }
+
+    pub fn reset(&self) {
+        self.stats.requests.store(0, Ordering::Relaxed);
+        self.stats.success.store(0, Ordering::Relaxed);
+        self.stats.errors.store(0, Ordering::Relaxed);
+        self.stats.assertion_failures.store(0, Ordering::Relaxed);
+        self.stats.retries.store(0, Ordering::Relaxed);
+        self.stats.redirects.store(0, Ordering::Relaxed);
+        self.stats.connect_timeouts.store(0, Ordering::Relaxed);
+        self.stats.read_timeouts.store(0, Ordering::Relaxed);
+        self.stats.body_timeouts.store(0, Ordering::Relaxed);
+        self.stats.chunks.store(0, Ordering::Relaxed);
+        self.stats.bytes.store(0, Ordering::Relaxed);
+        self.stats.bytes_decompressed.store(0, Ordering::Relaxed);
+        self.stats.bytes_sent.store(0, Ordering::Relaxed);
+        self.stats.header_bytes.store(0, Ordering::Relaxed);
+        self.stats.http1_responses.store(0, Ordering::Relaxed);
+        self.stats.http2_responses.store(0, Ordering::Relaxed);
+        self.stats.new_connections.store(0, Ordering::Relaxed);
+        self.stats.reused_connections.store(0, Ordering::Relaxed);
+        self.stats.http1_connections.store(0, Ordering::Relaxed);
+        self.stats.http2_connections.store(0, Ordering::Relaxed);
+        self.stats.tls12_connections.store(0, Ordering::Relaxed);
+        self.stats.tls13_connections.store(0, Ordering::Relaxed);
+        for counter in &self.stats.status_codes {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.histogram.reset();
+        self.ttfb.reset();
+        {
+            let mut phases = self.phases.lock().unwrap();
+            phases.dns.reset();
+            phases.tcp.reset();
+            phases.tls.reset();
+            phases.socket = None;
+        }
+        {
+            let mut chunks = self.chunks.lock().unwrap();
+            chunks.first.reset();
+            chunks.delay.reset();
+        }
+        {
+            let mut sizes = self.sizes.lock().unwrap();
+            sizes.request.reset();
+            sizes.response.reset();
+        }
+        self.response_headers.clear();
+        self.allowed_methods.lock().unwrap().clear();
+        *self.start_time.lock().unwrap() = Instant::now();
+    }
+
+    pub fn record_assertion_failure(&self) {
+        self.stats.assertion_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn assertion_failures(&self) -> u64 {
+        self.stats.assertion_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn record_retry(&self) {
+        self.stats.retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn retries(&self) -> u64 {
+        self.stats.retries.load(Ordering::Relaxed)
+    }
+
+    pub fn record_redirect(&self) {
+        self.stats.redirects.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn redirects(&self) -> u64 {
+        self.stats.redirects.load(Ordering::Relaxed)
+    }
+
+    pub fn record_connect_timeout(&self) {
+        self.stats.connect_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connect_timeouts(&self) -> u64 {
+        self.stats.connect_timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn record_read_timeout(&self) {
+        self.stats.read_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn read_timeouts(&self) -> u64 {
+        self.stats.read_timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn record_body_timeout(&self) {
+        self.stats.body_timeouts.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn body_timeouts(&self) -> u64 {
+        self.stats.body_timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn record_header_bytes(&self, headers: &HeaderMap) {
+        let bytes: usize = headers.iter().map(|(name, value)| name.as_str().len() + value.len() + 4).sum();
+        self.stats.header_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn header_bytes(&self) -> u64 {
+        self.stats.header_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn record_bytes_sent(&self, bytes: u64) {
+        self.stats.bytes_sent.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn bytes_sent(&self) -> u64 {
+        self.stats.bytes_sent.load(Ordering::Relaxed)
+    }
+
+    pub fn record_decompressed(&self, bytes: u64) {
+        self.stats.bytes_decompressed.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_request(&self, success: bool, bytes: u64, latency: Duration) {
+        self.record(success, bytes, latency, None);
+    }
+
+    /// Like `record_request`, but back-fills samples for every `expected_interval`
+    /// slot the request overran (coordinated omission correction).
+    pub fn record_request_corrected(&self, success: bool, bytes: u64, latency: Duration, expected_interval: Duration) {
+        self.record(success, bytes, latency, Some(expected_interval));
+    }
+
+    fn record(&self, success: bool, bytes: u64, latency: Duration, expected_interval: Option<Duration>) {
         self.stats.requests.fetch_add(1, Ordering::Relaxed);
         if success {
             self.stats.success.fetch_add(1, Ordering::Relaxed);
             self.stats.bytes.fetch_add(bytes, Ordering::Relaxed);
             let micros = latency.as_micros() as u64;
-            self.histogram.record(micros).unwrap_or_default();
+            match expected_interval {
+                Some(interval) => self.histogram.record_correct(micros, interval.as_micros() as u64),
+                None => self.histogram.record(micros),
+            }
         } else {
             self.stats.errors.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    pub fn print_stats(&self) {
-        let duration = self.start_time.elapsed().as_secs_f64();
-        let requests = self.stats.requests.load(Ordering::Relaxed);
-        let success = self.stats.success.load(Ordering::Relaxed);
-        let errors = self.stats.errors.load(Ordering::Relaxed);
-        let bytes = self.stats.bytes.load(Ordering::Relaxed);
-
-        println!("\nStatistics:");
-        println!("  Requests/sec: {:.2}", requests as f64 / duration);
-        println!("  Transfer/sec: {:.2}MB", bytes as f64 / duration / 1024.0 / 1024.0);
-        println!("\nLatency:");
-        
-        let mean = self.histogram.mean();
-        let min = self.histogram.min();
-        let max = self.histogram.max();
-        let p99 = self.histogram.value_at_quantile(0.99);
-        
-        println!("  Avg: {:.2}ms", mean / 1000.0);
-        println!("  Min: {:.2}ms", min as f64 / 1000.0);
-        println!("  Max: {:.2}ms", max as f64 / 1000.0);
-        println!("  P99: {:.2}ms", p99 as f64 / 1000.0);
-        
-        let success_rate = if requests > 0 {
-            (success as f64 / requests as f64) * 100.0
-        } else {
-            0.0
-        };
-        println!("\nSuccess: {:.2}% ({}/{})", success_rate, success, requests);
-        println!("Errors: {:.2}% ({} errors)", (errors as f64 / requests as f64) * 100.0, errors);
+    pub fn report(&self) -> Report {
+        let duration = self.start_time.lock().unwrap().elapsed();
+        let histogram = self.histogram();
+        let (request_sizes, response_sizes) = self.size_histograms();
+        Report {
+            protocol: self.protocol(),
+            status_codes: self.status_codes(),
+            connections: self.connections(),
+            assertion_failures: self.assertion_failures(),
+            retries: self.retries(),
+            redirects: self.redirects(),
+            connect_timeouts: self.connect_timeouts(),
+            read_timeouts: self.read_timeouts(),
+            body_timeouts: self.body_timeouts(),
+            streaming: (self.chunks() > 0).then(|| {
+                let (first, delay) = self.chunk_histograms();
+                StreamingReport::new(self.chunks(), &first, &delay)
+            }),
+            response_headers: self.response_headers(),
+            allowed_methods: self.allowed_methods(),
+            request_sizes: (!request_sizes.is_empty()).then(|| SizeReport::new(&request_sizes)),
+            response_sizes: (!response_sizes.is_empty()).then(|| SizeReport::new(&response_sizes)),
+            ..Report::new(&self.snapshot(), duration, &histogram)
+        }
+        .with_percentiles(&histogram, &self.percentiles)
+        .with_ttfb(&self.ttfb_histogram())
+        .with_header_bytes(self.header_bytes())
+        .with_bytes_sent(self.bytes_sent())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bucket_boundaries_round_trip() {
+        for sub_bucket_bits in [4, 10] {
+            let last = (1usize << sub_bucket_bits) * (MAX_VALUE_BITS - sub_bucket_bits + 1) as usize - 1;
+            for index in 0..=last {
+                let (low, width) = bucket_range(index, sub_bucket_bits);
+                assert_eq!(bucket_index(low, sub_bucket_bits), index);
+                assert_eq!(bucket_index(low + width - 1, sub_bucket_bits), index);
+                if index < last {
+                    assert_eq!(bucket_index(low + width, sub_bucket_bits), index + 1);
+                }
+            }
+            assert_eq!(bucket_index(MAX_VALUE, sub_bucket_bits), last);
+        }
+    }
+
+    #[test]
+    fn overrange_values_land_in_last_bucket() {
+        let histogram = AtomicHistogram::new();
+        histogram.record(MAX_VALUE);
+        histogram.record(MAX_VALUE + 1);
+        histogram.record(u64::MAX);
+        assert_eq!(histogram.overrange(), 2);
+        assert_eq!(histogram.buckets.last().unwrap().load(Ordering::Relaxed), 3);
+        assert_eq!(histogram.value_at_quantile(1.0), MAX_VALUE);
+        assert_eq!(histogram.to_histogram().len(), 3);
+    }
+
+    #[test]
+    fn to_histogram_keeps_exact_min_and_max() {
+        // 1024 以上每个桶宽 2，HdrHistogram 在 2048 以下是精确的
+        let histogram = AtomicHistogram::new();
+        for value in [1025, 1500, 1500, 2047] {
+            histogram.record(value);
+        }
+        let copy = histogram.to_histogram();
+        assert_eq!(copy.len(), 4);
+        assert_eq!(copy.min(), 1025);
+        assert_eq!(copy.max(), 2047);
+        assert_eq!(copy.value_at_quantile(0.5), 1500);
+
+        let single = AtomicHistogram::new();
+        single.record(1025);
+        let copy = single.to_histogram();
+        assert_eq!((copy.len(), copy.min(), copy.max()), (1, 1025, 1025));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use rand::Rng;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Index,
+    RandomInt,
+    RandomUuid,
+    TimestampMs,
+}
+
+/// A request body with `{{index}}`, `{{random_int}}`, `{{random_uuid}}` and
+/// `{{timestamp_ms}}` placeholders, rendered afresh for every request.
+#[derive(Clone, Debug)]
+pub struct Template {
+    segments: Arc<Vec<Segment>>,
+    next_index: Arc<AtomicU64>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find("{{") {
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+            let after = &rest[open + 2..];
+            let close = after
+                .find("}}")
+                .ok_or_else(|| anyhow!("Unclosed placeholder in body template: {:?}", &rest[open..]))?;
+            segments.push(match after[..close].trim() {
+                "index" => Segment::Index,
+                "random_int" => Segment::RandomInt,
+                "random_uuid" => Segment::RandomUuid,
+                "timestamp_ms" => Segment::TimestampMs,
+                other => return Err(anyhow!("Unknown placeholder {{{{{}}}}} in body template", other)),
+            });
+            rest = &after[close + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Ok(Template {
+            segments: Arc::new(segments),
+            next_index: Arc::new(AtomicU64::new(0)),
+        })
+    }
+
+    /// Render the next body; `{{index}}` counts up from 0 across all connections
+    pub fn render(&self) -> Bytes {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let mut rng = rand::thread_rng();
+        let mut body = String::new();
+        for segment in self.segments.iter() {
+            match segment {
+                Segment::Literal(text) => body.push_str(text),
+                Segment::Index => body.push_str(&index.to_string()),
+                Segment::RandomInt => body.push_str(&rng.gen::<u32>().to_string()),
+                Segment::RandomUuid => body.push_str(&uuid_v4(rng.gen())),
+                Segment::TimestampMs => {
+                    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                    body.push_str(&now.as_millis().to_string());
+                }
+            }
+        }
+        Bytes::from(body)
+    }
+}
+
+// 按 RFC 4122 设置版本号和变体位
+fn uuid_v4(random: u128) -> String {
+    let value = (random & !(0xf << 76) | (0x4 << 76)) & !(0x3 << 62) | (0x2 << 62);
+    let hex = format!("{:032x}", value);
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_adjacent_placeholders_and_literals() {
+        let template = Template::parse("a{{index}}{{ index }}b").unwrap();
+        assert_eq!(
+            *template.segments,
+            vec![Segment::Literal("a".into()), Segment::Index, Segment::Index, Segment::Literal("b".into())]
+        );
+        assert_eq!(template.render(), Bytes::from("a00b"));
+        assert_eq!(template.render(), Bytes::from("a11b"));
+        assert_eq!(Template::parse("plain").unwrap().render(), Bytes::from("plain"));
+    }
+
+    #[test]
+    fn parse_rejects_bad_placeholders() {
+        let err = Template::parse("{\"id\": {{nope}}}").unwrap_err();
+        assert!(err.to_string().contains("Unknown placeholder {{nope}}"), "{}", err);
+        let err = Template::parse("x {{index").unwrap_err();
+        assert!(err.to_string().contains("Unclosed placeholder"), "{}", err);
+    }
+
+    #[test]
+    fn uuid_v4_sets_version_and_variant() {
+        assert_eq!(uuid_v4(0), "00000000-0000-4000-8000-000000000000");
+        assert_eq!(uuid_v4(u128::MAX), "ffffffff-ffff-4fff-bfff-ffffffffffff");
+    }
+}
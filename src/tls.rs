@@ -0,0 +1,195 @@
+use anyhow::{anyhow, Context, Result};
+use clap::ValueEnum;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, ProtocolVersion, RootCertStore, SignatureScheme, SupportedProtocolVersion};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// TLS protocol versions selectable with --tls-min-version / --tls-max-version.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+    #[value(name = "tls1.2")]
+    Tls12,
+    #[value(name = "tls1.3")]
+    Tls13,
+}
+
+impl TlsVersion {
+    fn supported(self) -> &'static SupportedProtocolVersion {
+        match self {
+            TlsVersion::Tls12 => &rustls::version::TLS12,
+            TlsVersion::Tls13 => &rustls::version::TLS13,
+        }
+    }
+
+    /// The version negotiated on a connection, if it is one we know
+    pub fn negotiated(version: ProtocolVersion) -> Option<Self> {
+        match version {
+            ProtocolVersion::TLSv1_2 => Some(TlsVersion::Tls12),
+            ProtocolVersion::TLSv1_3 => Some(TlsVersion::Tls13),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TlsOptions {
+    pub insecure: bool,
+    pub cacert: Option<PathBuf>,
+    pub cert: Option<PathBuf>,
+    pub key: Option<PathBuf>,
+    pub min_version: Option<TlsVersion>,
+    pub max_version: Option<TlsVersion>,
+    /// rustls cipher suite names, e.g. TLS13_AES_128_GCM_SHA256; empty means all
+    pub ciphers: Vec<String>,
+}
+
+impl TlsOptions {
+    pub fn client_config(&self) -> Result<ClientConfig> {
+        let builder = ClientConfig::builder_with_provider(Arc::new(self.provider()?))
+            .with_protocol_versions(&self.versions()?)
+            .context("No cipher suite in --tls-ciphers works with the selected TLS versions")?;
+        let builder = if self.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerification::new()))
+        } else {
+            builder.with_root_certificates(self.root_store()?)
+        };
+
+        let config = match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .context("Invalid client certificate or key")?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => return Err(anyhow!("--cert and --key must be given together")),
+        };
+        Ok(config)
+    }
+
+    fn versions(&self) -> Result<Vec<&'static SupportedProtocolVersion>> {
+        let min = self.min_version.unwrap_or(TlsVersion::Tls12);
+        let max = self.max_version.unwrap_or(TlsVersion::Tls13);
+        if min > max {
+            return Err(anyhow!("--tls-min-version is higher than --tls-max-version"));
+        }
+        Ok([TlsVersion::Tls12, TlsVersion::Tls13]
+            .into_iter()
+            .filter(|version| (min..=max).contains(version))
+            .map(TlsVersion::supported)
+            .collect())
+    }
+
+    // 默认的加密套件，或者只保留 --tls-ciphers 中列出的
+    fn provider(&self) -> Result<CryptoProvider> {
+        let mut provider = CryptoProvider::get_default()
+            .map(|provider| provider.as_ref().clone())
+            .unwrap_or_else(rustls::crypto::aws_lc_rs::default_provider);
+        if self.ciphers.is_empty() {
+            return Ok(provider);
+        }
+        let name = |suite: &rustls::SupportedCipherSuite| suite.suite().as_str().unwrap_or_default();
+        let mut selected = Vec::with_capacity(self.ciphers.len());
+        for cipher in &self.ciphers {
+            match provider.cipher_suites.iter().find(|suite| name(suite).eq_ignore_ascii_case(cipher)) {
+                Some(suite) => selected.push(*suite),
+                None => {
+                    let supported: Vec<&str> = provider.cipher_suites.iter().map(name).collect();
+                    return Err(anyhow!("Unsupported cipher suite {:?}; supported: {}", cipher, supported.join(", ")));
+                }
+            }
+        }
+        provider.cipher_suites = selected;
+        Ok(provider)
+    }
+
+    fn root_store(&self) -> Result<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        match &self.cacert {
+            Some(path) => {
+                for cert in load_certs(path)? {
+                    roots
+                        .add(cert)
+                        .with_context(|| format!("Invalid CA certificate in {}", path.display()))?;
+                }
+            }
+            None => {
+                let native = rustls_native_certs::load_native_certs();
+                roots.add_parsable_certificates(native.certs);
+            }
+        }
+        Ok(roots)
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open certificate file {}", path.display()))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to parse certificates in {}", path.display()))?;
+    if certs.is_empty() {
+        return Err(anyhow!("No PEM certificates found in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open key file {}", path.display()))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to parse private key in {}", path.display()))?
+        .ok_or_else(|| anyhow!("No PEM private key found in {}", path.display()))
+}
+
+// --insecure：接受任何服务器证书，但仍然校验握手签名
+#[derive(Debug)]
+struct NoVerification(Arc<CryptoProvider>);
+
+impl NoVerification {
+    fn new() -> Self {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::aws_lc_rs::default_provider()));
+        NoVerification(provider)
+    }
+}
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{anyhow, Context, Result};
+use hyper::header::HeaderValue;
+use rand::RngCore;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Generates W3C `traceparent` headers so benchmark requests show up in
+/// distributed tracing backends.
+#[derive(Clone, Debug)]
+pub struct TraceContext {
+    /// Shared by every request when set, otherwise each request gets a random one
+    trace_id: Option<[u8; 16]>,
+    tracestate: Option<HeaderValue>,
+    next_span: Arc<AtomicU64>,
+}
+
+impl TraceContext {
+    pub fn new(trace_id: Option<&str>, tracestate: Option<&str>) -> Result<Self> {
+        let trace_id = trace_id.map(parse_trace_id).transpose()?;
+        let tracestate = tracestate
+            .map(|value| HeaderValue::from_str(value).with_context(|| format!("Invalid tracestate {:?}", value)))
+            .transpose()?;
+        Ok(TraceContext {
+            trace_id,
+            tracestate,
+            next_span: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    /// `traceparent` for the next request: version 00, sampled
+    pub fn traceparent(&self) -> HeaderValue {
+        let trace_id = self.trace_id.unwrap_or_else(|| {
+            let mut id = [0u8; 16];
+            // 全零的 trace-id 是无效的
+            while id == [0; 16] {
+                rand::thread_rng().fill_bytes(&mut id);
+            }
+            id
+        });
+        let span_id = self.next_span.fetch_add(1, Ordering::Relaxed);
+        let value = format!("00-{}-{:016x}-01", hex(&trace_id), span_id);
+        HeaderValue::from_str(&value).expect("traceparent is always a valid header value")
+    }
+
+    pub fn tracestate(&self) -> Option<&HeaderValue> {
+        self.tracestate.as_ref()
+    }
+}
+
+fn parse_trace_id(value: &str) -> Result<[u8; 16]> {
+    let invalid = || anyhow!("Invalid trace ID {:?}: expected 32 hex characters, not all zero", value);
+    if value.len() != 32 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let mut id = [0u8; 16];
+    for (i, byte) in id.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&value[i * 2..i * 2 + 2], 16).map_err(|_| invalid())?;
+    }
+    if id == [0; 16] {
+        return Err(invalid());
+    }
+    Ok(id)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
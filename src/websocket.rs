@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Context, Result};
+use futures::{SinkExt, StreamExt};
+use hdrhistogram::Histogram;
+use hyper::header::{HeaderName, HeaderValue};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::time;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::connect::{self, RawTarget, RunWindow};
+use crate::resolve::Resolver;
+use crate::tls::TlsOptions;
+
+/// Echo-style WebSocket benchmark: every connection sends a message, waits for
+/// the next data frame in reply and records the round trip.
+#[derive(Clone, Debug)]
+pub struct WebSocketConfig {
+    pub url: String,
+    /// Sent as a text frame when it is valid UTF-8, otherwise as binary
+    pub message: Vec<u8>,
+    /// Extra headers for the opening handshake
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub connections: usize,
+    pub duration: Duration,
+    pub warmup: Duration,
+    /// Limit for connecting and for each reply
+    pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub resolver: Resolver,
+    pub stop: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+struct WebSocketStats {
+    sent: AtomicU64,
+    received: AtomicU64,
+    errors: AtomicU64,
+    histogram: Mutex<Histogram<u64>>,
+}
+
+/// Message counts and round-trip latencies, excluding the warmup period.
+#[derive(Clone, Debug)]
+pub struct WebSocketResult {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// Failed connects, failed sends, closed connections and reply timeouts
+    pub errors: u64,
+    pub duration: Duration,
+    pub histogram: Histogram<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct WebSocketReport {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub errors: u64,
+    pub duration_secs: f64,
+    pub messages_per_sec: f64,
+    pub latency_mean_ms: f64,
+    pub latency_min_ms: f64,
+    pub latency_max_ms: f64,
+    pub latency_p50_ms: f64,
+    pub latency_p95_ms: f64,
+    pub latency_p99_ms: f64,
+}
+
+impl WebSocketResult {
+    pub fn report(&self) -> WebSocketReport {
+        let ms = |value: u64| value as f64 / 1000.0;
+        let secs = self.duration.as_secs_f64();
+        WebSocketReport {
+            messages_sent: self.messages_sent,
+            messages_received: self.messages_received,
+            errors: self.errors,
+            duration_secs: secs,
+            messages_per_sec: if secs > 0.0 { self.messages_received as f64 / secs } else { 0.0 },
+            latency_mean_ms: self.histogram.mean() / 1000.0,
+            latency_min_ms: ms(self.histogram.min()),
+            latency_max_ms: ms(self.histogram.max()),
+            latency_p50_ms: ms(self.histogram.value_at_quantile(0.5)),
+            latency_p95_ms: ms(self.histogram.value_at_quantile(0.95)),
+            latency_p99_ms: ms(self.histogram.value_at_quantile(0.99)),
+        }
+    }
+}
+
+impl WebSocketConfig {
+    pub async fn run(self) -> Result<WebSocketResult> {
+        let mut request = self
+            .url
+            .as_str()
+            .into_client_request()
+            .with_context(|| format!("Invalid WebSocket URL {:?}", self.url))?;
+        request.headers_mut().extend(self.headers.iter().cloned());
+        let target = RawTarget::new(&self.url, ("ws", "wss"), &self.tls, &[], self.resolver.clone(), self.timeout)?;
+        let message = match String::from_utf8(self.message.clone()) {
+            Ok(text) => Message::Text(text),
+            Err(e) => Message::Binary(e.into_bytes()),
+        };
+        let stats = Arc::new(WebSocketStats {
+            sent: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            histogram: Mutex::new(Histogram::<u64>::new(3).expect("Failed to create histogram")),
+        });
+
+        let window = RunWindow::new(self.warmup, self.duration, self.stop.clone());
+        let timeout = self.timeout;
+        let mut handles = Vec::with_capacity(self.connections);
+        for _ in 0..self.connections {
+            let request = request.clone();
+            let target = target.clone();
+            let message = message.clone();
+            let stats = stats.clone();
+            let window = window.clone();
+            let error = {
+                let stats = stats.clone();
+                let window = window.clone();
+                move |e: &dyn std::fmt::Display| {
+                    tracing::error!("WebSocket error: {}", e);
+                    if window.recording() {
+                        stats.errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            };
+
+            handles.push(tokio::spawn(async move {
+                while window.running() {
+                    let opened = match target.connect().await {
+                        Ok(opened) => opened,
+                        Err(e) => {
+                            error(&e);
+                            continue;
+                        }
+                    };
+                    let mut ws = match time::timeout(timeout, tokio_tungstenite::client_async(request.clone(), opened.stream)).await {
+                        Ok(Ok((ws, _))) => ws,
+                        Ok(Err(e)) => {
+                            error(&e);
+                            time::sleep(connect::RECONNECT_DELAY).await;
+                            continue;
+                        }
+                        Err(_) => {
+                            error(&"handshake timeout");
+                            continue;
+                        }
+                    };
+
+                    while window.running() {
+                        let start = Instant::now();
+                        let recording = start >= window.warmup_end;
+                        if let Err(e) = ws.send(message.clone()).await {
+                            error(&e);
+                            break;
+                        }
+                        if recording {
+                            stats.sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                        // 等待下一个数据帧，Ping/Pong 由 tungstenite 自动处理
+                        let reply = time::timeout(timeout, async {
+                            loop {
+                                match ws.next().await {
+                                    Some(Ok(Message::Text(_) | Message::Binary(_))) => return Ok(()),
+                                    Some(Ok(Message::Close(_))) | None => return Err(anyhow!("connection closed by server")),
+                                    Some(Ok(_)) => continue,
+                                    Some(Err(e)) => return Err(e.into()),
+                                }
+                            }
+                        })
+                        .await;
+                        match reply {
+                            Ok(Ok(())) => {
+                                if recording {
+                                    let latency = start.elapsed().as_micros() as u64;
+                                    stats.received.fetch_add(1, Ordering::Relaxed);
+                                    let _ = stats.histogram.lock().unwrap().record(latency.max(1));
+                                }
+                            }
+                            Ok(Err(e)) => {
+                                error(&e);
+                                break;
+                            }
+                            Err(_) => {
+                                error(&"reply timeout");
+                                break;
+                            }
+                        }
+                    }
+                    let _ = ws.close(None).await;
+                }
+            }));
+        }
+
+        for handle in handles {
+            handle.await?;
+        }
+
+        let histogram = stats.histogram.lock().unwrap().clone();
+        Ok(WebSocketResult {
+            messages_sent: stats.sent.load(Ordering::Relaxed),
+            messages_received: stats.received.load(Ordering::Relaxed),
+            errors: stats.errors.load(Ordering::Relaxed),
+            duration: Instant::now().saturating_duration_since(window.warmup_end),
+            histogram,
+        })
+    }
+}
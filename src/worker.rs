@@ -1,137 +1,1520 @@
-use anyhow::{Result, Error};
-use hyper::Uri;
-use hyper_util::client::legacy::Client as HyperClient;
+use anyhow::Result;
+use clap::ValueEnum;
+use hdrhistogram::Histogram;
+use hyper::header::{self, HeaderMap, HeaderName, HeaderValue};
+use hyper::{Method, StatusCode, Uri, Version};
+use hyper_util::client::legacy::{Builder as ClientBuilder, Client as HyperClient};
 use hyper_util::rt::TokioExecutor;
-use hyper_tls::HttpsConnector;
+use hyper_rustls::HttpsConnectorBuilder;
 use hyper_util::client::legacy::connect::HttpConnector;
-use std::time::{Duration, Instant};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{watch, Notify, OwnedSemaphorePermit, Semaphore};
 use tokio::time;
-use http_body_util::{Empty, BodyExt};
-use hyper::body::Bytes;
-use crate::stats::Statistics;
+use tokio_util::sync::CancellationToken;
+use tower_service::Service;
+use url::Url;
+use uuid::Uuid;
+use futures::StreamExt;
+use http_body_util::combinators::UnsyncBoxBody;
+use http_body_util::{Full, BodyExt, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use std::convert::Infallible;
+use bytes::BytesMut;
+use crate::assertion::BodyAssertions;
+use crate::connect::{self, TimedConnector};
+use crate::cookie::{CookieConfig, CookieSession};
+use crate::decode;
+use crate::multipart::MultipartForm;
+use crate::ntlm::{self, NtlmCredentials};
+use crate::proxy::{ConnectionUse, ProxyConfig, ProxyConnector};
+use crate::request_log::{ErrorLog, ErrorRecord, RequestLog, RequestRecord};
+use crate::resolve::Resolver;
+use crate::script::{ScriptRequest, ScriptState};
+use crate::sigv4::AwsSigner;
+use crate::socket::{LocalAddress, SocketOptions};
+use crate::stats::{BurstSample, ConnectionSetup, CounterSnapshot, Report, Statistics};
+use crate::template::Template;
+use crate::trace::TraceContext;
 
-type Client = HyperClient<HttpsConnector<HttpConnector>, Empty<Bytes>>;
-type StatsResult = Result<(u64, u64, u64, u64, Duration)>;
+/// Request body, sent in one piece or streamed in chunks (--chunk-size)
+type UploadBody = UnsyncBoxBody<Bytes, Infallible>;
+type Client = HyperClient<TimedConnector, UploadBody>;
+
+const REFILL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HttpVersion {
+    Auto,
+    Http1,
+    Http2,
+    /// Cleartext HTTP/2 with prior knowledge; only valid for http:// URLs
+    H2c,
+}
+
+/// HTTP/2 flow control and stream limits; unset values keep hyper's defaults.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Http2Options {
+    /// Streams opened concurrently on a connection before the server's
+    /// SETTINGS_MAX_CONCURRENT_STREAMS arrives
+    pub max_concurrent_streams: Option<usize>,
+    /// Initial flow control window of each stream, in bytes
+    pub initial_stream_window_size: Option<u32>,
+    /// Initial flow control window of the whole connection, in bytes
+    pub initial_connection_window_size: Option<u32>,
+}
+
+/// How the expected start of the next request is derived when correcting
+/// for coordinated omission.
+#[derive(Clone, Copy, Debug)]
+pub enum LatencyCorrection {
+    /// Fixed per-connection interval implied by the configured rate
+    Fixed(Duration),
+    /// The previous request's latency on the same connection
+    Previous,
+}
+
+/// Pause between a successful response and the next request on the same connection.
+#[derive(Clone, Copy, Debug)]
+pub struct ThinkTime {
+    pub base: Duration,
+    /// Upper bound of a uniformly distributed random delay added to `base`
+    pub jitter: Duration,
+}
+
+impl ThinkTime {
+    fn delay(&self) -> Duration {
+        if self.jitter.is_zero() {
+            self.base
+        } else {
+            self.base + self.jitter.mul_f64(rand::thread_rng().gen::<f64>())
+        }
+    }
+}
+
+/// Shape of the random delay added before each request (--jitter-distribution).
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JitterDistribution {
+    Uniform,
+    /// Memoryless gaps, as between Poisson arrivals
+    Exponential,
+    /// Heavy-tailed gaps (shape 1.5) that produce bursts and long pauses
+    Pareto,
+}
+
+/// Random delay before each request on a connection, not counted in latency.
+#[derive(Clone, Copy, Debug)]
+pub struct Jitter {
+    /// Upper bound for uniform jitter; exponential and pareto jitter have the
+    /// same mean (half of this) but no upper bound
+    pub max: Duration,
+    pub distribution: JitterDistribution,
+}
+
+impl Jitter {
+    const PARETO_SHAPE: f64 = 1.5;
+
+    fn delay(&self) -> Duration {
+        let max_ms = self.max.as_millis() as u64;
+        let mut rng = rand::thread_rng();
+        // 1 - u 落在 (0, 1]，取对数和求幂都不会溢出
+        let u = 1.0 - rng.gen::<f64>();
+        let mean = self.max.as_secs_f64() / 2.0;
+        match self.distribution {
+            JitterDistribution::Uniform => Duration::from_millis(rng.gen_range(0..=max_ms)),
+            JitterDistribution::Exponential => Duration::from_secs_f64(-mean * u.ln()),
+            JitterDistribution::Pareto => {
+                // 均值为 shape * scale / (shape - 1)，由此反推 scale
+                let scale = mean * (Self::PARETO_SHAPE - 1.0) / Self::PARETO_SHAPE;
+                Duration::from_secs_f64(scale / u.powf(1.0 / Self::PARETO_SHAPE))
+            }
+        }
+    }
+}
+
+// 相对地址按当前请求的 URL 解析
+fn redirect_location(base: &Uri, status: StatusCode, headers: &HeaderMap) -> Option<Uri> {
+    if !status.is_redirection() {
+        return None;
+    }
+    let location = headers.get(header::LOCATION)?.to_str().ok()?;
+    let url = Url::parse(&base.to_string()).ok()?.join(location).ok()?;
+    url.as_str().parse().ok()
+}
+
+/// Split `body` into `chunk_size` pieces sent as separate frames, waiting
+/// `chunk_delay` between them; without a chunk size the body is sent whole.
+fn upload_body(body: Bytes, chunk_size: Option<usize>, chunk_delay: Option<Duration>) -> UploadBody {
+    let Some(chunk_size) = chunk_size.filter(|&size| size > 0 && size < body.len()) else {
+        return Full::new(body).boxed_unsync();
+    };
+    let chunks: Vec<Bytes> = (0..body.len())
+        .step_by(chunk_size)
+        .map(|start| body.slice(start..(start + chunk_size).min(body.len())))
+        .collect();
+    let frames = futures::stream::iter(chunks.into_iter().enumerate()).then(move |(i, chunk)| async move {
+        if let (true, Some(delay)) = (i > 0, chunk_delay) {
+            time::sleep(delay).await;
+        }
+        Ok(Frame::data(chunk))
+    });
+    StreamBody::new(frames).boxed_unsync()
+}
+
+// 逐帧读取响应体，记录每个数据块到达的时间
+/// Read a body frame by frame, recording chunk timings into `stats` if given.
+/// Fails once a single frame takes longer than `read_timeout`.
+async fn read_chunks(
+    mut body: Incoming,
+    start: Instant,
+    read_timeout: Option<Duration>,
+    stats: Option<&Statistics>,
+) -> Result<Bytes, time::error::Elapsed> {
+    let mut content = BytesMut::new();
+    let mut last = None;
+    loop {
+        let frame = match read_timeout {
+            Some(read_timeout) => time::timeout(read_timeout, body.frame()).await?,
+            None => body.frame().await,
+        };
+        let Some(Ok(frame)) = frame else { break };
+        let Ok(data) = frame.into_data() else { continue };
+        if data.is_empty() {
+            continue;
+        }
+        let now = Instant::now();
+        if let Some(stats) = stats {
+            stats.record_chunk(last.is_none(), now - last.unwrap_or(start));
+        }
+        last = Some(now);
+        content.extend_from_slice(&data);
+    }
+    Ok(content.freeze())
+}
+
+// 每批预先生成的请求 ID 数量
+const REQUEST_ID_BATCH: usize = 64;
+
+/// UUIDs for --request-id-header, generated in batches by each connection task.
+#[derive(Default)]
+struct RequestIds(Vec<HeaderValue>);
+
+impl RequestIds {
+    fn next(&mut self) -> HeaderValue {
+        if self.0.is_empty() {
+            self.0.extend((0..REQUEST_ID_BATCH).map(|_| {
+                let id = Uuid::new_v4();
+                HeaderValue::from_str(id.hyphenated().encode_lower(&mut Uuid::encode_buffer()))
+                    .expect("UUID is always a valid header value")
+            }));
+        }
+        self.0.pop().expect("batch was just refilled")
+    }
+}
+
+/// One socket of --socket-pool-size, shared by the connection tasks that use
+/// its client. Over HTTP/1.1 they take turns, so hyper never needs a second
+/// connection; once a response arrives over HTTP/2 they send concurrently.
+#[derive(Clone, Debug)]
+struct SocketSlot {
+    turns: Arc<Semaphore>,
+    http2: Arc<AtomicBool>,
+}
+
+impl SocketSlot {
+    fn new(http2: bool) -> Self {
+        SocketSlot {
+            turns: Arc::new(Semaphore::new(1)),
+            http2: Arc::new(AtomicBool::new(http2)),
+        }
+    }
+
+    /// Wait for the socket to be free; hold the permit until the response body has been read
+    async fn turn(&self) -> Option<OwnedSemaphorePermit> {
+        if self.http2.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(self.turns.clone().acquire_owned().await.expect("socket semaphore is never closed"))
+    }
+
+    fn record_version(&self, version: Version) {
+        if version == Version::HTTP_2 {
+            self.http2.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Overrides for a single connection task, e.g. one simulated user's credentials.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionConfig {
+    /// Always request this URL instead of cycling through the targets
+    pub url: Option<Uri>,
+    /// Replace command-line headers with the same name
+    pub headers: Vec<(HeaderName, HeaderValue)>,
+    pub body: Option<Bytes>,
+}
+
+impl ConnectionConfig {
+    fn merge_headers(&self, headers: &[(HeaderName, HeaderValue)]) -> Vec<(HeaderName, HeaderValue)> {
+        let overridden = |name: &HeaderName| self.headers.iter().any(|(n, _)| n == name);
+        headers
+            .iter()
+            .filter(|(name, _)| !overridden(name))
+            .chain(&self.headers)
+            .cloned()
+            .collect()
+    }
+}
+
+/// One attempt's request: the connection's headers with script overrides,
+/// cookies, trace context, a fresh request ID and the rendered body.
+#[allow(clippy::too_many_arguments)]
+fn build_request(
+    method: &Method,
+    uri: &Uri,
+    headers: &[(HeaderName, HeaderValue)],
+    scripted: &ScriptRequest,
+    cookies: &CookieSession,
+    trace: Option<&TraceContext>,
+    request_id: Option<(&HeaderName, HeaderValue)>,
+    body: &RequestBody,
+    drop_body: bool,
+) -> Result<hyper::Request<Bytes>> {
+    let mut builder = hyper::Request::builder().method(method.clone()).uri(uri.clone());
+    let overridden = |name: &HeaderName| scripted.headers.iter().any(|(n, _)| n == name);
+    for (name, value) in headers.iter().filter(|(name, _)| !overridden(name)).chain(&scripted.headers) {
+        builder = builder.header(name, value);
+    }
+    if let Some(cookie) = cookies.header(uri) {
+        builder = builder.header(header::COOKIE, cookie);
+    }
+    // 每次尝试都是一个新的 span
+    if let Some(trace) = trace {
+        builder = builder.header("traceparent", trace.traceparent());
+        if let Some(tracestate) = trace.tracestate() {
+            builder = builder.header("tracestate", tracestate);
+        }
+    }
+    if let (Some((name, id)), Some(headers)) = (request_id, builder.headers_mut()) {
+        headers.insert(name, id);
+    }
+    let (body, content_type) = match (&scripted.body, drop_body) {
+        (_, true) => (Bytes::new(), None),
+        (Some(body), false) => (body.clone(), None),
+        (None, false) => body.render()?,
+    };
+    // multipart 的 Content-Type 带有分隔符，必须覆盖其他设置
+    if let (Some(content_type), Some(headers)) = (content_type, builder.headers_mut()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    Ok(builder.body(body)?)
+}
+
+/// Re-send requests that failed to connect or got a retryable status code.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after each further attempt
+    pub backoff: Duration,
+    pub on_status: Vec<u16>,
+}
+
+/// Whether a TRACE response body echoes the request line that was sent
+/// (RFC 7231, section 4.3.8); the protocol version is not compared because
+/// intermediaries may forward the request over another one
+fn echoes_trace_request(body: &[u8], uri: &Uri) -> bool {
+    let path = uri.path_and_query().map_or("/", |path| path.as_str());
+    let request_line = body.split(|&b| b == b'\n').next().unwrap_or_default();
+    let mut parts = request_line.split(|&b| b == b' ');
+    parts.next() == Some(b"TRACE".as_slice()) && parts.next() == Some(path.as_bytes())
+}
+
+/// Whether `status` counts as a success: any 2xx, or exactly the codes
+/// given with --expect-status
+fn is_expected(status: StatusCode, expect_status: &[u16]) -> bool {
+    if expect_status.is_empty() {
+        status.is_success()
+    } else {
+        expect_status.contains(&status.as_u16())
+    }
+}
+
+impl RetryPolicy {
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    tokens: Arc<Semaphore>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        let tokens = Arc::new(Semaphore::new(0));
+        let bucket = Arc::downgrade(&tokens);
+
+        // 后台定时补充令牌，桶容量为一秒的请求量
+        tokio::spawn(async move {
+            let capacity = rate.max(1) as usize;
+            let per_tick = rate as f64 * REFILL_INTERVAL.as_secs_f64();
+            let mut pending = 0.0;
+            let mut interval = time::interval(REFILL_INTERVAL);
+            loop {
+                interval.tick().await;
+                let Some(tokens) = bucket.upgrade() else { break };
+                pending += per_tick;
+                let whole = pending as usize;
+                pending -= whole as f64;
+                let room = capacity.saturating_sub(tokens.available_permits());
+                tokens.add_permits(whole.min(room));
+            }
+        });
+
+        RateLimiter { tokens }
+    }
+
+    pub async fn acquire(&self) {
+        if let Ok(permit) = self.tokens.acquire().await {
+            permit.forget();
+        }
+    }
+}
+
+/// Releases requests in bursts (--burst-size): the next burst only starts once
+/// every request of the previous one has completed and the interval has
+/// passed. [`BurstLimiter::drive`] runs the bursts and records their timings.
+#[derive(Clone, Debug)]
+pub struct BurstLimiter {
+    size: usize,
+    tokens: Arc<Semaphore>,
+    outstanding: Arc<AtomicUsize>,
+    completed: Arc<Notify>,
+    samples: Arc<std::sync::Mutex<Vec<BurstSample>>>,
+}
+
+/// Held by a connection task while its request of the current burst is in
+/// flight; dropping the last one completes the burst.
+pub struct BurstPermit<'a>(&'a BurstLimiter);
+
+impl Drop for BurstPermit<'_> {
+    fn drop(&mut self) {
+        if self.0.outstanding.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0.completed.notify_one();
+        }
+    }
+}
+
+impl BurstLimiter {
+    pub fn new(size: usize) -> Self {
+        BurstLimiter {
+            size: size.max(1),
+            tokens: Arc::new(Semaphore::new(0)),
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            completed: Arc::new(Notify::new()),
+            samples: Arc::new(std::sync::Mutex::new(Vec::new())),
+        }
+    }
+
+    async fn acquire(&self) -> BurstPermit<'_> {
+        if let Ok(permit) = self.tokens.acquire().await {
+            permit.forget();
+        }
+        BurstPermit(self)
+    }
+
+    /// Release bursts until aborted, pausing `interval` after each one has
+    /// completed; bursts that start before `warmup_end` are not recorded
+    pub async fn drive(self, interval: Duration, warmup_end: Instant) {
+        loop {
+            let start = Instant::now();
+            self.outstanding.store(self.size, Ordering::Release);
+            self.tokens.add_permits(self.size);
+            self.completed.notified().await;
+            if start >= warmup_end {
+                self.samples.lock().unwrap().push(BurstSample {
+                    elapsed_secs: start.duration_since(warmup_end).as_secs_f64(),
+                    requests: self.size as u64,
+                    completion_ms: start.elapsed().as_secs_f64() * 1000.0,
+                });
+            }
+            time::sleep(interval).await;
+        }
+    }
+
+    /// Completed bursts, in order
+    pub fn samples(&self) -> Vec<BurstSample> {
+        self.samples.lock().unwrap().clone()
+    }
+}
+
+/// Request body sent by every connection: fixed bytes, a template rendered per
+/// request or a multipart form.
+#[derive(Clone, Debug)]
+pub enum RequestBody {
+    Static(Bytes),
+    Template(Template),
+    Multipart(MultipartForm),
+}
+
+impl RequestBody {
+    /// The body for the next request, plus the Content-Type it requires
+    fn render(&self) -> Result<(Bytes, Option<HeaderValue>)> {
+        match self {
+            RequestBody::Static(body) => Ok((body.clone(), None)),
+            RequestBody::Template(template) => Ok((template.render(), None)),
+            RequestBody::Multipart(form) => form.encode().map(|(body, content_type)| (body, Some(content_type))),
+        }
+    }
+
+    fn for_connection(&self) -> Result<Self> {
+        match self {
+            RequestBody::Multipart(form) => form.for_connection().map(RequestBody::Multipart),
+            body => Ok(body.clone()),
+        }
+    }
+}
+
+/// How many connection tasks may send requests, adjusted during the test by
+/// --target-rps. Task `n` only runs while `n` is below the limit.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit {
+    limit: Arc<watch::Sender<usize>>,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(limit: usize) -> Self {
+        ConcurrencyLimit {
+            limit: Arc::new(watch::Sender::new(limit)),
+        }
+    }
+
+    pub fn get(&self) -> usize {
+        *self.limit.borrow()
+    }
+
+    pub fn set(&self, limit: usize) {
+        self.limit.send_replace(limit);
+    }
+
+    async fn wait_for_slot(&self, index: usize) {
+        let mut limit = self.limit.subscribe();
+        let _ = limit.wait_for(|&limit| index < limit).await;
+    }
+}
+
+/// Spaces out the start of connection tasks across all workers
+/// (--connections-per-second), so the initial handshakes don't arrive at once.
+#[derive(Clone, Debug)]
+pub struct ConnectionPacer {
+    interval: Arc<tokio::sync::Mutex<time::Interval>>,
+    started: Instant,
+    // 第一个和最后一个连接任务启动的时间
+    setup: Arc<std::sync::Mutex<Option<(Duration, Duration)>>>,
+}
+
+impl ConnectionPacer {
+    pub fn new(per_second: u64) -> Self {
+        let mut interval = time::interval(Duration::from_secs_f64(1.0 / per_second.max(1) as f64));
+        // 落后时不补发，保持瞬时速率
+        interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+        ConnectionPacer {
+            interval: Arc::new(tokio::sync::Mutex::new(interval)),
+            started: Instant::now(),
+            setup: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    async fn wait(&self) {
+        self.interval.lock().await.tick().await;
+        let elapsed = self.started.elapsed();
+        let mut setup = self.setup.lock().unwrap();
+        let first = setup.map_or(elapsed, |(first, _)| first);
+        *setup = Some((first, elapsed));
+    }
+
+    /// When the first and the last connection task started
+    pub fn setup(&self) -> Option<ConnectionSetup> {
+        self.setup.lock().unwrap().map(|(first, last)| ConnectionSetup {
+            first_ms: first.as_secs_f64() * 1000.0,
+            last_ms: last.as_secs_f64() * 1000.0,
+        })
+    }
+}
+
+/// Total number of requests left across all workers.
+#[derive(Clone, Debug)]
+pub struct RequestBudget {
+    remaining: Arc<AtomicU64>,
+}
+
+impl RequestBudget {
+    pub fn new(requests: u64) -> Self {
+        RequestBudget {
+            remaining: Arc::new(AtomicU64::new(requests)),
+        }
+    }
+
+    fn take(&self) -> bool {
+        self.remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.remaining.load(Ordering::Relaxed) == 0
+    }
+}
+
+/// Responses whose headers are still to be printed (--dump-response-headers),
+/// shared by all workers of a run.
+#[derive(Clone, Debug)]
+pub struct HeaderDump {
+    remaining: Arc<AtomicUsize>,
+}
+
+impl HeaderDump {
+    pub fn new(responses: usize) -> Self {
+        HeaderDump {
+            remaining: Arc::new(AtomicUsize::new(responses)),
+        }
+    }
+
+    fn print(&self, uri: &Uri, status: StatusCode, headers: &HeaderMap) {
+        if self.remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1)).is_err() {
+            return;
+        }
+        // 一次写出，避免多个连接的输出交错
+        let mut dump = format!("Response headers from {} ({}):\n", uri, status);
+        for (name, value) in headers {
+            dump.push_str(&format!("  {}: {}\n", name, String::from_utf8_lossy(value.as_bytes())));
+        }
+        eprint!("{}", dump);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ClientOptions {
+    pub http_version: HttpVersion,
+    pub http2: Http2Options,
+    pub keepalive: bool,
+    pub decompress: bool,
+    /// Read bodies frame by frame and record chunk timings
+    pub streaming: bool,
+    /// Record request and response body sizes
+    pub size_histograms: bool,
+    /// Print the first responses' headers and count header names
+    pub header_dump: Option<HeaderDump>,
+    /// Authenticate each connection with NTLM
+    pub ntlm: Option<Arc<NtlmCredentials>>,
+    /// Sign every request with AWS Signature Version 4
+    pub aws_signer: Option<Arc<AwsSigner>>,
+    /// User-Agent added to requests that don't already set one
+    pub user_agent: Option<HeaderValue>,
+    /// Header that carries a random UUID on every request
+    pub request_id_header: Option<HeaderName>,
+    pub keepalive_timeout: Duration,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub proxy: ProxyConfig,
+    pub unix_socket: Option<PathBuf>,
+    pub socket: SocketOptions,
+    /// Limit for the TCP and TLS handshakes of a new connection
+    pub connect_timeout: Option<Duration>,
+    /// Limit for each read of a response body
+    pub read_timeout: Option<Duration>,
+    /// Status codes counted as successes instead of any 2xx
+    pub expect_status: Vec<u16>,
+    /// Stream request bodies in pieces of this many bytes
+    pub chunk_size: Option<usize>,
+    /// Pause between request body chunks
+    pub chunk_delay: Option<Duration>,
+    pub resolver: Resolver,
+    pub tls: rustls::ClientConfig,
+}
+
+pub struct Targets {
+    urls: Vec<(String, Uri)>,
+    stats: HashMap<String, Statistics>,
+    random: bool,
+    next: AtomicUsize,
+}
+
+impl Targets {
+    pub fn new(urls: Vec<String>, random: bool, histogram_precision: u8) -> Result<Self> {
+        let mut targets = Vec::with_capacity(urls.len());
+        for url in urls {
+            if !targets.iter().any(|(existing, _)| *existing == url) {
+                let uri = url.parse::<Uri>()?;
+                targets.push((url, uri));
+            }
+        }
+
+        // 只有多个 URL 时才需要分别统计
+        let stats = if targets.len() > 1 {
+            targets
+                .iter()
+                .map(|(url, _)| (url.clone(), Statistics::new().with_histogram_precision(histogram_precision)))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Targets {
+            urls: targets,
+            stats,
+            random,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    fn pick(&self) -> (&Uri, Option<&Statistics>) {
+        let index = if self.random {
+            rand::thread_rng().gen_range(0..self.urls.len())
+        } else {
+            self.next.fetch_add(1, Ordering::Relaxed) % self.urls.len()
+        };
+        let (url, uri) = &self.urls[index];
+        (uri, self.stats.get(url))
+    }
+
+    pub fn per_url(&self) -> Vec<(&str, &Statistics)> {
+        self.urls
+            .iter()
+            .filter_map(|(url, _)| self.stats.get(url).map(|stats| (url.as_str(), stats)))
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        for stats in self.stats.values() {
+            stats.reset();
+        }
+    }
+}
+
+/// Totals measured by one worker, excluding the warmup period.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WorkerResult {
+    /// Index of the worker thread that produced this result
+    pub worker_id: usize,
+    pub total_requests: u64,
+    pub total_successes: u64,
+    pub total_errors: u64,
+    pub total_assertion_failures: u64,
+    pub total_retries: u64,
+    pub total_redirects: u64,
+    /// Numeric entries of the script's `counter` table
+    pub script_counters: BTreeMap<String, f64>,
+    pub total_bytes: u64,
+    pub total_bytes_decompressed: u64,
+    pub duration: Duration,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub histogram: Histogram<u64>,
+    #[serde(with = "crate::compare::base64_histogram")]
+    pub ttfb_histogram: Histogram<u64>,
+}
+
+impl WorkerResult {
+    /// Add the same worker's totals from a later, sequential run
+    pub fn merge(&mut self, other: &WorkerResult) {
+        self.total_requests += other.total_requests;
+        self.total_successes += other.total_successes;
+        self.total_errors += other.total_errors;
+        self.total_assertion_failures += other.total_assertion_failures;
+        self.total_retries += other.total_retries;
+        self.total_redirects += other.total_redirects;
+        for (name, value) in &other.script_counters {
+            *self.script_counters.entry(name.clone()).or_default() += value;
+        }
+        self.total_bytes += other.total_bytes;
+        self.total_bytes_decompressed += other.total_bytes_decompressed;
+        self.duration += other.duration;
+        self.histogram.add(&other.histogram).unwrap_or_default();
+        self.ttfb_histogram.add(&other.ttfb_histogram).unwrap_or_default();
+    }
+
+    pub fn report(&self) -> Report {
+        let counters = CounterSnapshot {
+            requests: self.total_requests,
+            success: self.total_successes,
+            errors: self.total_errors,
+            bytes: self.total_bytes,
+            bytes_decompressed: self.total_bytes_decompressed,
+        };
+        Report::new(&counters, self.duration, &self.histogram).with_ttfb(&self.ttfb_histogram)
+    }
+}
 
 pub struct Worker {
-    client: Client,
+    id: usize,
+    // 连接任务按编号轮流使用这些客户端，--socket-pool-size 时每个客户端只有一个套接字
+    clients: Vec<(Client, Option<SocketSlot>)>,
+    // 超出 -c 的套接字数量，只建立连接、保持空闲
+    spare_sockets: Option<(usize, TimedConnector)>,
+    // NTLM 认证的是连接，每个连接任务用自己的连接池，握手的几个请求才会落在同一个连接上
+    ntlm: Option<(Arc<NtlmCredentials>, ClientBuilder, TimedConnector)>,
     stats: Statistics,
     connections: usize,
+    method: Method,
+    body: RequestBody,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    rate_limiter: Option<RateLimiter>,
+    burst: Option<BurstLimiter>,
+    budget: Option<RequestBudget>,
+    concurrency: Option<ConcurrencyLimit>,
+    pacer: Option<ConnectionPacer>,
+    cancel: CancellationToken,
+    stop: Arc<AtomicBool>,
+    assertions: Option<Arc<BodyAssertions>>,
+    decompress: bool,
+    streaming: bool,
+    size_histograms: bool,
+    header_dump: Option<HeaderDump>,
+    aws_signer: Option<Arc<AwsSigner>>,
+    read_timeout: Option<Duration>,
+    expect_status: Arc<[u16]>,
+    chunk_size: Option<usize>,
+    chunk_delay: Option<Duration>,
+    correction: Option<LatencyCorrection>,
+    think_time: Option<ThinkTime>,
+    jitter: Option<Jitter>,
+    retry: Option<RetryPolicy>,
+    max_redirects: Option<u32>,
+    script: Option<Arc<ScriptState>>,
+    connection_configs: Vec<ConnectionConfig>,
+    trace: Option<TraceContext>,
+    request_id_header: Option<HeaderName>,
+    cookies: CookieConfig,
+    request_log: Option<RequestLog>,
+    error_log: Option<ErrorLog>,
 }
 
 impl Worker {
-    pub fn new(connections: usize) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        id: usize,
+        connections: usize,
+        sockets: Option<usize>,
+        method: Method,
+        body: RequestBody,
+        mut headers: Vec<(HeaderName, HeaderValue)>,
+        stats: Statistics,
+        rate_limiter: Option<RateLimiter>,
+        burst: Option<BurstLimiter>,
+        budget: Option<RequestBudget>,
+        concurrency: Option<ConcurrencyLimit>,
+        pacer: Option<ConnectionPacer>,
+        cancel: CancellationToken,
+        stop: Arc<AtomicBool>,
+        assertions: Option<Arc<BodyAssertions>>,
+        correction: Option<LatencyCorrection>,
+        think_time: Option<ThinkTime>,
+        jitter: Option<Jitter>,
+        retry: Option<RetryPolicy>,
+        max_redirects: Option<u32>,
+        script: Option<Arc<ScriptState>>,
+        connection_configs: Vec<ConnectionConfig>,
+        trace: Option<TraceContext>,
+        cookies: CookieConfig,
+        request_log: Option<RequestLog>,
+        error_log: Option<ErrorLog>,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        if let Some(user_agent) = &options.user_agent {
+            if !headers.iter().any(|(name, _)| *name == header::USER_AGENT) {
+                headers.push((header::USER_AGENT, user_agent.clone()));
+            }
+        }
+
         let mut http = HttpConnector::new();
         http.enforce_http(false);
-        let https = HttpsConnector::new_with_connector(http);
-        let client = HyperClient::builder(TokioExecutor::new())
-            .pool_idle_timeout(Duration::from_secs(30))
-            .build(https);
-
-        Worker {
-            client,
-            stats: Statistics::new(),
-            connections,
+        // 缓冲区大小在 connect 之前设置，接收窗口的缩放因子在握手时就已确定
+        http.set_nodelay(options.socket.nodelay);
+        http.set_send_buffer_size(options.socket.send_buffer);
+        http.set_recv_buffer_size(options.socket.recv_buffer);
+        http.set_connect_timeout(options.connect_timeout);
+        match options.socket.local_address {
+            Some(LocalAddress { ipv4: Some(ipv4), ipv6: Some(ipv6) }) => http.set_local_addresses(ipv4, ipv6),
+            Some(LocalAddress { ipv4, ipv6 }) => http.set_local_address(ipv4.map(IpAddr::V4).or(ipv6.map(IpAddr::V6))),
+            None => {}
+        }
+        let proxy = ProxyConnector::new(http, options.proxy.clone(), Arc::new(options.tls.clone()))
+            .unix_socket(options.unix_socket.clone())
+            .resolver(options.resolver.clone())
+            .socket_options(options.socket, options.connect_timeout);
+        let tls = HttpsConnectorBuilder::new()
+            .with_tls_config(options.tls.clone())
+            .https_or_http();
+        // 通过 ALPN 协商 HTTP 版本
+        let https = match options.http_version {
+            HttpVersion::Auto => tls.enable_http1().enable_http2().wrap_connector(proxy),
+            HttpVersion::Http1 => tls.enable_http1().wrap_connector(proxy),
+            HttpVersion::Http2 | HttpVersion::H2c => tls.enable_http2().wrap_connector(proxy),
+        };
+
+        let mut builder = HyperClient::builder(TokioExecutor::new());
+        // TLS 连接按 ALPN 结果选择协议；明文连接只有 h2c 才直接使用 HTTP/2
+        builder.http2_only(options.http_version == HttpVersion::H2c);
+        builder
+            .http2_initial_max_send_streams(options.http2.max_concurrent_streams)
+            .http2_initial_stream_window_size(options.http2.initial_stream_window_size)
+            .http2_initial_connection_window_size(options.http2.initial_connection_window_size);
+        // 关闭 keep-alive 时每个请求都使用新连接
+        if options.keepalive && !options.keepalive_timeout.is_zero() {
+            builder.pool_idle_timeout(options.keepalive_timeout);
+            // --socket-pool-size 的每个客户端只保留一个空闲连接，偶尔多建的连接归还时即被关闭
+            if sockets.is_some() {
+                builder.pool_max_idle_per_host(1);
+            } else if let Some(max_idle) = options.pool_max_idle_per_host {
+                builder.pool_max_idle_per_host(max_idle);
+            }
+        } else {
+            builder.pool_max_idle_per_host(0);
+            headers.push((header::CONNECTION, HeaderValue::from_static("close")));
         }
+        let connector = TimedConnector::new(https.clone(), stats.clone())
+            .timeout(options.connect_timeout)
+            .h2c(options.http_version == HttpVersion::H2c);
+        let clients = match sockets {
+            Some(sockets) => (0..sockets.min(connections).max(1))
+                .map(|_| (builder.build(connector.clone()), Some(SocketSlot::new(options.http_version == HttpVersion::H2c))))
+                .collect(),
+            None => vec![(builder.build(connector.clone()), None)],
+        };
+        // 空闲套接字不发请求，不计入连接统计
+        let spare_sockets = sockets.filter(|&sockets| sockets > connections).map(|sockets| {
+            let connector = TimedConnector::new(https.clone(), Statistics::new())
+                .timeout(options.connect_timeout)
+                .h2c(options.http_version == HttpVersion::H2c);
+            (sockets - connections, connector)
+        });
+        let ntlm = options.ntlm.clone().map(|credentials| (credentials, builder, connector));
+
+        Ok(Worker {
+            id,
+            clients,
+            spare_sockets,
+            ntlm,
+            stats,
+            connections,
+            method,
+            body,
+            headers,
+            rate_limiter,
+            burst,
+            budget,
+            concurrency,
+            pacer,
+            cancel,
+            stop,
+            assertions,
+            decompress: options.decompress,
+            streaming: options.streaming,
+            size_histograms: options.size_histograms,
+            header_dump: options.header_dump.clone(),
+            aws_signer: options.aws_signer.clone(),
+            read_timeout: options.read_timeout,
+            expect_status: options.expect_status.as_slice().into(),
+            chunk_size: options.chunk_size,
+            chunk_delay: options.chunk_delay,
+            correction,
+            think_time,
+            jitter,
+            retry,
+            max_redirects,
+            script,
+            connection_configs,
+            trace,
+            request_id_header: options.request_id_header.clone(),
+            cookies,
+            request_log,
+            error_log,
+        })
+    }
+
+    /// Build the request the first connection would send first, without
+    /// connecting; used by --dry-run
+    pub fn first_request(&self, targets: &Targets) -> Result<hyper::Request<Bytes>> {
+        let connection = self.connection_configs.first().cloned().unwrap_or_default();
+        let body = match &connection.body {
+            Some(body) => RequestBody::Static(body.clone()),
+            None => self.body.for_connection()?,
+        };
+        let headers = connection.merge_headers(&self.headers);
+        let uri = match &connection.url {
+            Some(uri) => uri.clone(),
+            None => targets.pick().0.clone(),
+        };
+        let scripted = self.script.as_ref().map_or(Ok(None), |script| script.request())?;
+        let uri = scripted.as_ref().map_or(Ok(uri.clone()), |scripted| scripted.uri(&uri))?;
+        let scripted = scripted.unwrap_or_default();
+        let method = scripted.method.clone().unwrap_or_else(|| self.method.clone());
+        build_request(
+            &method,
+            &uri,
+            &headers,
+            &scripted,
+            &self.cookies.session(),
+            self.trace.as_ref(),
+            self.request_id_header.as_ref().map(|name| (name, RequestIds::default().next())),
+            &body,
+            false,
+        )
+        .and_then(|mut req| {
+            if let Some(signer) = &self.aws_signer {
+                signer.sign(&mut req, SystemTime::now())?;
+            }
+            Ok(req)
+        })
     }
 
-    pub async fn run(&mut self, url: String, duration: Duration, timeout: Duration) -> Result<()> {
-        let uri = url.parse::<Uri>()?;
-        let end_time = Instant::now() + duration;
+    pub async fn run(
+        self,
+        targets: Arc<Targets>,
+        warmup: Duration,
+        duration: Duration,
+        timeout: Duration,
+        rampup: Duration,
+    ) -> Result<WorkerResult> {
+        let warmup_end = Instant::now() + warmup;
+        // 本 worker 自己的统计，运行结束后汇总返回
+        let local = Statistics::new().with_histogram_precision(self.stats.histogram_precision());
+        let end_time = warmup_end + duration;
 
         let mut handles = Vec::with_capacity(self.connections);
 
-        for _ in 0..self.connections {
-            let client = self.client.clone();
-            let uri = uri.clone();
-
-            let handle: tokio::task::JoinHandle<StatsResult> = tokio::spawn(async move {
-                let mut requests = 0u64;
-                let mut successes = 0u64;
-                let mut total_bytes = 0u64;
-                let mut total_latency = Duration::default();
-                let mut errors = 0u64;
-                
-                while Instant::now() < end_time {
+        // 逐秒增加连接数，直到达到 --connections
+        let per_tick = if rampup.is_zero() {
+            self.connections
+        } else {
+            ((self.connections as f64 / rampup.as_secs_f64()).ceil() as usize).max(1)
+        };
+        let mut rampup_interval = time::interval(Duration::from_secs(1));
+        rampup_interval.tick().await;
+
+        // 空闲套接字在测试期间一直保持打开，任务结束时随之关闭
+        let spare = self.spare_sockets.clone().map(|(count, connector)| {
+            let targets = targets.clone();
+            tokio::spawn(async move {
+                // 按顺序轮流连接各个目标，不影响请求使用的 pick() 游标
+                let sockets = futures::future::join_all((0..count).map(|i| {
+                    let mut connector = connector.clone();
+                    let uri = targets.urls[i % targets.urls.len()].1.clone();
+                    async move {
+                        std::future::poll_fn(|cx| connector.poll_ready(cx)).await?;
+                        connector.call(uri).await
+                    }
+                }))
+                .await;
+                let failed = sockets.iter().filter(|socket| socket.is_err()).count();
+                if failed > 0 {
+                    tracing::warn!("Failed to open {} of {} idle sockets", failed, count);
+                }
+                std::future::pending::<()>().await;
+                drop(sockets);
+            })
+        });
+
+        for i in 0..self.connections {
+            if i > 0 && i % per_tick == 0 {
+                rampup_interval.tick().await;
+                let stopped = self.cancel.is_cancelled() || self.stop.load(Ordering::Relaxed);
+                if Instant::now() >= end_time || stopped || self.budget.as_ref().is_some_and(RequestBudget::is_exhausted) {
+                    break;
+                }
+            }
+            // 只在建立连接阶段限速，任务启动后不再受影响
+            if let Some(pacer) = &self.pacer {
+                tokio::select! {
+                    _ = pacer.wait() => {}
+                    _ = self.cancel.cancelled() => break,
+                }
+                if Instant::now() >= end_time || self.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+            }
+            let (client, socket, ntlm) = match &self.ntlm {
+                Some((credentials, builder, connector)) => (builder.build(connector.clone()), None, Some(credentials.clone())),
+                None => {
+                    let (client, socket) = self.clients[i % self.clients.len()].clone();
+                    (client, socket, None)
+                }
+            };
+            let targets = targets.clone();
+            let method = self.method.clone();
+            // 按连接覆盖 URL、请求头和请求体
+            let connection = self.connection_configs.get(i).cloned().unwrap_or_default();
+            let body = match &connection.body {
+                Some(body) => RequestBody::Static(body.clone()),
+                None => self.body.for_connection()?,
+            };
+            let headers = connection.merge_headers(&self.headers);
+            let stats = self.stats.clone();
+            let local = local.clone();
+            let rate_limiter = self.rate_limiter.clone();
+            let burst = self.burst.clone();
+            let budget = self.budget.clone();
+            let concurrency = self.concurrency.clone();
+            // 全局编号，用于和 --target-rps 调整的并发上限比较
+            let index = self.id * self.connections + i;
+            let cancel = self.cancel.clone();
+            let stop = self.stop.clone();
+            let assertions = self.assertions.clone();
+            let correction = self.correction;
+            let think_time = self.think_time;
+            let jitter = self.jitter;
+            let retry = self.retry.clone();
+            let max_redirects = self.max_redirects;
+            let script = self.script.clone();
+            let trace = self.trace.clone();
+            let request_id_header = self.request_id_header.clone();
+            let decompress = self.decompress;
+            let streaming = self.streaming;
+            let size_histograms = self.size_histograms;
+            let header_dump = self.header_dump.clone();
+            let aws_signer = self.aws_signer.clone();
+            let read_timeout = self.read_timeout;
+            let expect_status = self.expect_status.clone();
+            let (chunk_size, chunk_delay) = (self.chunk_size, self.chunk_delay);
+            let request_log = self.request_log.clone();
+            let error_log = self.error_log.clone();
+            // 每个连接任务有自己的 cookie jar
+            let mut cookies = self.cookies.session();
+            let mut request_ids = RequestIds::default();
+
+            let handle = tokio::spawn(async move {
+                stats.connection_started();
+                let mut expected_interval = match correction {
+                    Some(LatencyCorrection::Fixed(interval)) => Some(interval),
+                    _ => None,
+                };
+                while Instant::now() < end_time && !cancel.is_cancelled() && !stop.load(Ordering::Relaxed) {
+                    if let Some(concurrency) = &concurrency {
+                        tokio::select! {
+                            _ = concurrency.wait_for_slot(index) => {}
+                            // 定期回到循环条件，检查结束时间和停止标志
+                            _ = time::sleep(Duration::from_millis(100)) => continue,
+                            _ = cancel.cancelled() => break,
+                        }
+                    }
+                    if let Some(jitter) = jitter {
+                        let wake = (Instant::now() + jitter.delay()).min(end_time);
+                        tokio::select! {
+                            _ = time::sleep_until(wake.into()) => {}
+                            _ = cancel.cancelled() => break,
+                        }
+                        if Instant::now() >= end_time {
+                            break;
+                        }
+                    }
+                    if let Some(limiter) = &rate_limiter {
+                        limiter.acquire().await;
+                        if Instant::now() >= end_time {
+                            break;
+                        }
+                    }
+                    // 突发模式：上一批请求全部完成并等待间隔后才放行下一批
+                    let burst_permit = match &burst {
+                        Some(burst) => tokio::select! {
+                            permit = burst.acquire() => Some(permit),
+                            _ = time::sleep_until(end_time.into()) => break,
+                            _ = cancel.cancelled() => break,
+                        },
+                        None => None,
+                    };
                     let start = Instant::now();
-                    let req = hyper::Request::builder()
-                        .method(hyper::Method::GET)
-                        .uri(uri.clone())
-                        .body(Empty::<Bytes>::new())
-                        .unwrap();
-
-                    requests += 1;
-                    match time::timeout(timeout, client.request(req)).await {
+                    // 预热阶段发出的请求不计入统计
+                    let recording = start >= warmup_end;
+                    if let (true, Some(budget)) = (recording, &budget) {
+                        if !budget.take() {
+                            break;
+                        }
+                    }
+                    // 等待共享套接字的时间计入延迟，和在连接池里排队一样
+                    let socket_turn = match &socket {
+                        Some(socket) => tokio::select! {
+                            turn = socket.turn() => turn,
+                            _ = time::sleep_until(end_time.into()) => break,
+                            _ = cancel.cancelled() => break,
+                        },
+                        None => None,
+                    };
+                    let (uri, url_stats) = match &connection.url {
+                        Some(uri) => (uri, None),
+                        None => targets.pick(),
+                    };
+                    // 脚本的 request() 可以覆盖方法、路径、请求头和请求体
+                    let scripted = script.as_ref().map_or(Ok(None), |script| script.request()).and_then(|scripted| {
+                        let uri = scripted.as_ref().map_or(Ok(uri.clone()), |scripted| scripted.uri(uri))?;
+                        Ok((scripted.unwrap_or_default(), uri))
+                    });
+                    let (scripted, mut uri) = match scripted {
+                        Ok(scripted) => scripted,
+                        Err(e) => {
+                            tracing::error!("Script request() failed, closing connection: {:#}", e);
+                            break;
+                        }
+                    };
+                    let mut method = scripted.method.clone().unwrap_or_else(|| method.clone());
+                    // 重试和重定向只计一次请求，延迟包含所有尝试和退避时间
+                    let mut attempt = 0;
+                    let mut redirects = 0;
+                    let mut too_many_redirects = false;
+                    let mut drop_body = false;
+                    let mut request_id = None;
+                    let mut reused = false;
+                    let mut sent = 0;
+                    let mut ntlm_authorization = None;
+                    let mut ntlm_legs = 0;
+                    let response = loop {
+                        let req = build_request(
+                            &method,
+                            &uri,
+                            &headers,
+                            &scripted,
+                            &cookies,
+                            trace.as_ref(),
+                            request_id_header.as_ref().map(|name| (name, request_ids.next())),
+                            &body,
+                            drop_body,
+                        );
+                        let req = match req {
+                            Ok(mut req) => {
+                                sent = req.body().len() as u64;
+                                if let Some(authorization) = &ntlm_authorization {
+                                    req.headers_mut().insert(header::AUTHORIZATION, HeaderValue::clone(authorization));
+                                }
+                                // 签名带时间戳，每次尝试都重新计算
+                                if let Some(signer) = &aws_signer {
+                                    if let Err(e) = signer.sign(&mut req, SystemTime::now()) {
+                                        tracing::error!("Failed to sign request, closing connection: {:#}", e);
+                                        break None;
+                                    }
+                                }
+                                req.map(|body| upload_body(body, chunk_size, chunk_delay))
+                            }
+                            Err(e) => {
+                                tracing::error!("Failed to build request body, closing connection: {:#}", e);
+                                break None;
+                            }
+                        };
+                        if error_log.is_some() {
+                            let generated = request_id_header.as_ref().and_then(|name| req.headers().get(name));
+                            request_id = generated
+                                .or_else(|| ["x-request-id", "traceparent"].into_iter().find_map(|name| req.headers().get(name)))
+                                .and_then(|value| value.to_str().ok())
+                                .map(str::to_string);
+                        }
+
+                        let response = tokio::select! {
+                            response = time::timeout(timeout, client.request(req)) => response,
+                            _ = cancel.cancelled() => break None,
+                        };
+                        if let Ok(Ok(resp)) = &response {
+                            cookies.store(&uri, resp.headers());
+                            // 预热和重定向的响应也要标记连接已被使用
+                            reused = resp.extensions().get::<ConnectionUse>().is_some_and(ConnectionUse::reused);
+                        }
+                        // NTLM 握手：第一个 401 之后发送 type 1 消息，带 challenge 的 401 再用 type 3 消息应答
+                        if let (Some(ntlm), Ok(Ok(resp))) = (&ntlm, &response) {
+                            if resp.status() == StatusCode::UNAUTHORIZED && ntlm::offered(resp.headers()) && ntlm_legs < 2 {
+                                let next = if ntlm_legs == 0 { ntlm.negotiate() } else { ntlm.authenticate(resp.headers()) };
+                                match next {
+                                    Ok(authorization) => {
+                                        ntlm_authorization = Some(authorization);
+                                        ntlm_legs += 1;
+                                        // 读完响应体，连接才会回到连接池供下一步握手使用
+                                        if let Ok(Ok(resp)) = response {
+                                            let _ = resp.into_body().collect().await;
+                                        }
+                                        continue;
+                                    }
+                                    Err(e) => tracing::error!("NTLM authentication failed: {:#}", e),
+                                }
+                            }
+                        }
+                        if let (Some(max_redirects), Ok(Ok(resp))) = (max_redirects, &response) {
+                            let status = resp.status();
+                            if let Some(location) = redirect_location(&uri, status, resp.headers()) {
+                                if redirects >= max_redirects {
+                                    tracing::error!("Too many redirects (more than {})", max_redirects);
+                                    too_many_redirects = true;
+                                    break Some(response);
+                                }
+                                redirects += 1;
+                                if recording {
+                                    stats.record_redirect();
+                                    local.record_redirect();
+                                }
+                                // 303 以及 POST 的 301/302 改用不带请求体的 GET
+                                let to_get = status == StatusCode::SEE_OTHER
+                                    || (method == Method::POST && matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND));
+                                if to_get && method != Method::HEAD {
+                                    method = Method::GET;
+                                    drop_body = true;
+                                }
+                                uri = location;
+                                if let Ok(Ok(resp)) = response {
+                                    let _ = resp.into_body().collect().await;
+                                }
+                                continue;
+                            }
+                        }
+                        let Some(retry) = retry.as_ref().filter(|retry| attempt < retry.max_retries) else {
+                            break Some(response);
+                        };
+                        let retryable = match &response {
+                            Ok(Ok(resp)) => retry.on_status.contains(&resp.status().as_u16()),
+                            Ok(Err(e)) => e.is_connect(),
+                            Err(_) => false,
+                        };
+                        if !retryable {
+                            break Some(response);
+                        }
+                        // 退避超过测试结束时间时结果未定，不计入统计
+                        let wake = Instant::now() + retry.backoff(attempt);
+                        if wake >= end_time {
+                            break None;
+                        }
+                        match &response {
+                            Ok(Ok(resp)) => tracing::debug!("Retrying after HTTP {}", resp.status()),
+                            _ => tracing::debug!("Retrying after connection error"),
+                        }
+                        attempt += 1;
+                        if recording {
+                            stats.record_retry();
+                            local.record_retry();
+                        }
+                        // 丢弃响应体后再退避，连接可以回到连接池
+                        if let Ok(Ok(resp)) = response {
+                            let _ = resp.into_body().collect().await;
+                        }
+                        tokio::select! {
+                            _ = time::sleep_until(wake.into()) => {}
+                            _ = cancel.cancelled() => break None,
+                        }
+                    };
+                    let Some(response) = response else {
+                        break;
+                    };
+                    let (success, bytes, decompressed, status, failure) = match response {
                         Ok(Ok(resp)) => {
+                            let ttfb = start.elapsed();
                             let status = resp.status();
-                            let body = resp.into_body();
-                            let bytes = match body.collect().await {
-                                Ok(collected) => collected.to_bytes().len(),
-                                Err(_) => 0,
+                            let version = resp.version();
+                            if let Some(socket) = &socket {
+                                socket.record_version(version);
+                            }
+                            let (parts, body) = resp.into_parts();
+                            // HEAD 响应没有响应体；设置了 --read-timeout 时逐帧读取并分别计时
+                            let body = if method == Method::HEAD {
+                                Ok(Bytes::new())
+                            } else if streaming || read_timeout.is_some() {
+                                read_chunks(body, start, read_timeout, (streaming && recording).then_some(&stats)).await
+                            } else {
+                                match body.collect().await {
+                                    Ok(collected) => Ok(collected.to_bytes()),
+                                    Err(_) => Ok(Bytes::new()),
+                                }
                             };
-                            let latency = start.elapsed();
-                            
-                            if status.is_success() {
-                                successes += 1;
-                                total_bytes += bytes as u64;
-                                total_latency += latency;
+                            let body_timeout = body.is_err();
+                            if body_timeout {
+                                tracing::error!("Response body read timeout");
+                                if recording {
+                                    stats.record_body_timeout();
+                                }
+                            }
+                            let body = body.unwrap_or_default();
+                            let expected = is_expected(status, &expect_status);
+                            let mut success = expected && !too_many_redirects && !body_timeout;
+                            let wire_bytes = body.len() as u64;
+                            let (content, decompressed) = if decompress {
+                                match decode::decode_body(&parts.headers, body) {
+                                    Ok(decoded) => {
+                                        let len = decoded.len() as u64;
+                                        (decoded, len)
+                                    }
+                                    Err(e) => {
+                                        tracing::error!("Failed to decompress response: {}", e);
+                                        success = false;
+                                        (Bytes::new(), 0)
+                                    }
+                                }
                             } else {
-                                errors += 1;
+                                (body, 0)
+                            };
+                            // 状态码符合预期的响应还需要通过响应体断言
+                            if let (true, Some(assertions)) = (success, &assertions) {
+                                if !assertions.check(&content) {
+                                    tracing::error!("Response body assertion failed");
+                                    success = false;
+                                    if recording {
+                                        stats.record_assertion_failure();
+                                        local.record_assertion_failure();
+                                    }
+                                }
+                            }
+                            if success && method == Method::TRACE && !echoes_trace_request(&content, &uri) {
+                                tracing::error!("TRACE response does not echo the request");
+                                success = false;
+                                if recording {
+                                    stats.record_assertion_failure();
+                                    local.record_assertion_failure();
+                                }
+                            }
+                            if let Some(script) = &script {
+                                match script.response(status.as_u16(), &parts.headers, &content) {
+                                    Ok(true) => {}
+                                    Ok(false) => {
+                                        tracing::error!("Script response() rejected the response");
+                                        if success && recording {
+                                            stats.record_assertion_failure();
+                                            local.record_assertion_failure();
+                                        }
+                                        success = false;
+                                    }
+                                    Err(e) => {
+                                        tracing::error!("Script response() failed: {:#}", e);
+                                        success = false;
+                                    }
+                                }
+                            }
+
+                            if !expected {
                                 tracing::error!("HTTP error: {}", status);
                             }
+                            if let Some(dump) = &header_dump {
+                                dump.print(&uri, status, &parts.headers);
+                                if recording {
+                                    stats.record_response_headers(&parts.headers);
+                                }
+                            }
+                            if recording {
+                                if reused {
+                                    stats.record_reused_connection();
+                                }
+                                if method == Method::OPTIONS {
+                                    stats.record_allowed_methods(&parts.headers);
+                                }
+                                stats.record_header_bytes(&parts.headers);
+                                stats.record_version(version);
+                                stats.record_status(status.as_u16());
+                                if expected {
+                                    stats.record_ttfb(ttfb);
+                                    local.record_ttfb(ttfb);
+                                }
+                            }
+                            let failure = if success {
+                                ""
+                            } else if body_timeout {
+                                "body_timeout"
+                            } else if too_many_redirects {
+                                "too_many_redirects"
+                            } else if !expected {
+                                "http_error"
+                            } else {
+                                "assertion_failed"
+                            };
+                            (success, wire_bytes, decompressed, Some(status.as_u16()), failure)
+                        }
+                        Ok(Err(e)) if e.is_connect() && connect::is_connect_timeout(&e) => {
+                            tracing::error!("Connect timeout: {}", e);
+                            if recording {
+                                stats.record_connect_timeout();
+                            }
+                            (false, 0, 0, None, "connect_timeout")
                         }
                         Ok(Err(e)) => {
-                            let latency = start.elapsed();
-                            errors += 1;
                             tracing::error!("Request error: {}", e);
-                            total_latency += latency;
+                            (false, 0, 0, None, "request_error")
                         }
                         Err(_) => {
-                            let latency = start.elapsed();
-                            errors += 1;
                             tracing::error!("Request timeout");
-                            total_latency += latency;
+                            if recording {
+                                stats.record_read_timeout();
+                            }
+                            (false, 0, 0, None, "timeout")
+                        }
+                    };
+                    let latency = start.elapsed();
+                    if let (true, Some(log)) = (recording, &request_log) {
+                        log.record(RequestRecord::new(latency, status, bytes, failure));
+                    }
+                    if let (true, false, Some(log)) = (recording, success, &error_log) {
+                        let error = match status {
+                            Some(status) if failure == "http_error" => status.to_string(),
+                            _ => failure.to_string(),
+                        };
+                        log.record(ErrorRecord::new(uri.to_string(), error, latency, request_id));
+                    }
+
+                    if recording {
+                        for stats in [&stats, &local].into_iter().chain(url_stats) {
+                            match expected_interval {
+                                Some(interval) => stats.record_request_corrected(success, bytes, latency, interval),
+                                None => stats.record_request(success, bytes, latency),
+                            }
+                            if success {
+                                stats.record_decompressed(decompressed);
+                            }
+                            stats.record_bytes_sent(sent);
+                        }
+                        // 只统计收到响应的请求
+                        if let (true, Some(_)) = (size_histograms, status) {
+                            stats.record_sizes(sent, bytes);
+                        }
+                    }
+                    if let Some(LatencyCorrection::Previous) = correction {
+                        expected_interval = Some(latency);
+                    }
+                    drop(socket_turn);
+                    drop(burst_permit);
+                    // 模拟用户思考时间，不计入延迟
+                    if let (true, Some(think_time)) = (success, think_time) {
+                        let wake = (Instant::now() + think_time.delay()).min(end_time);
+                        tokio::select! {
+                            _ = time::sleep_until(wake.into()) => {}
+                            _ = cancel.cancelled() => {}
                         }
                     }
                 }
-                Ok((requests, successes, errors, total_bytes, total_latency))
+                stats.connection_finished();
             });
             handles.push(handle);
         }
 
-        let mut total_requests = 0;
-        let mut total_successes = 0;
-        let mut total_errors = 0;
-        let mut total_bytes = 0;
-        let mut total_latency = Duration::default();
-
         for handle in handles {
-            if let Ok(Ok((requests, successes, errors, bytes, latency))) = handle.await {
-                total_requests += requests;
-                total_successes += successes;
-                total_errors += errors;
-                total_bytes += bytes;
-                total_latency += latency;
-                
-                // 记录每个请求的延迟
-                if requests > 0 {
-                    let avg_latency = Duration::from_nanos((latency.as_nanos() / requests as u128) as u64);
-                    self.stats.record_request(successes > 0, bytes / requests, avg_latency);
-                }
-            }
+            handle.await?;
         }
-
-        println!("\nSummary:");
-        println!("Total Requests: {}", total_requests);
-        println!("Successful Requests: {}", total_successes);
-        println!("Failed Requests: {}", total_errors);
-        if total_requests > 0 {
-            println!("Success Rate: {:.2}%", (total_successes as f64 / total_requests as f64) * 100.0);
-            println!("Average Latency: {:.2}ms", total_latency.as_secs_f64() * 1000.0 / total_requests as f64);
-            println!("Total Bytes: {:.2}MB", total_bytes as f64 / 1024.0 / 1024.0);
+        if let Some(spare) = spare {
+            spare.abort();
         }
-        
-        self.stats.print_stats();
-        Ok(())
+
+        let counters = local.snapshot();
+        let script_counters = match &self.script {
+            Some(script) => script.counters()?,
+            None => BTreeMap::new(),
+        };
+        Ok(WorkerResult {
+            worker_id: self.id,
+            total_requests: counters.requests,
+            total_successes: counters.success,
+            total_errors: counters.errors,
+            total_assertion_failures: local.assertion_failures(),
+            total_retries: local.retries(),
+            total_redirects: local.redirects(),
+            script_counters,
+            total_bytes: counters.bytes,
+            total_bytes_decompressed: counters.bytes_decompressed,
+            duration: Instant::now().saturating_duration_since(warmup_end),
+            histogram: local.histogram(),
+            ttfb_histogram: local.ttfb_histogram(),
+        })
     }
-} 
\ No newline at end of file
+}